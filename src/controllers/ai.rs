@@ -1,15 +1,31 @@
 use actix_web::{post, web, HttpResponse};
 use actix_files::NamedFile;
 use sea_orm::{ActiveModelTrait, Set, DatabaseConnection};
-use std::path::PathBuf;
 use crate::models::conversion;
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::libraries::Libraries;
+use crate::utils::media_index::media_id;
 
 #[post("{video_path:.*}/categorize")]
 pub async fn categorize_video(
-	video_path: web::Path<PathBuf>,
+	video_path: web::Path<String>,
+	libraries: web::Data<Libraries>,
 	db: web::Data<DatabaseConnection>,
+	user: CurrentUser,
 ) -> HttpResponse {
-	let source_filename = video_path.display().to_string();
+	let video = match libraries.resolve(&video_path) {
+		Ok(video) => video,
+		Err(e) => return e.response(),
+	};
+	if let Err(resp) = user.require_at(Permission::Edit, &video.relative) {
+		return resp;
+	}
+	if !video.absolute.is_file() {
+		return HttpResponse::NotFound().body("Video not found");
+	}
+	let media_id = media_id(db.get_ref(), &video).await;
+	let source_filename = video.relative;
 
 	// Create a new conversion request for this video
 	let now = std::time::SystemTime::now()
@@ -23,6 +39,7 @@ pub async fn categorize_video(
 		time_requested: Set(now),
 		time_completed: Set(None),
 		status: Set("pending".to_string()),
+		media_id: Set(media_id),
 		..Default::default()
 	};
 
@@ -37,6 +54,7 @@ pub async fn categorize_video(
 	}
 }
 
+// No permission check: OpenAI fetches this frame by URL while categorizing.
 #[actix_web::get("/categorize/{id}.jpg")]
 pub async fn get_categorize_jpg(
 	id: web::Path<i32>,
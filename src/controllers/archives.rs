@@ -0,0 +1,216 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use serde_json::json;
+use std::collections::HashSet;
+use crate::models::{clip, point, tag};
+use crate::models::user::Permission;
+use crate::utils::archive::{self, Entry, Format};
+use crate::utils::auth::CurrentUser;
+use crate::utils::libraries::Libraries;
+use crate::utils::media_index::walk;
+use crate::utils::paths::ServedPath;
+use crate::utils::time::{iso8601, now};
+
+fn internal_error(context: &str, err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}: {}", context, err);
+    HttpResponse::InternalServerError().body("Internal server error")
+}
+
+// `web::Query` can't take a parameter more than once, so the query string is read by hand.
+fn query_values(query: &str, key: &str) -> Vec<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(k, _)| *k == key)
+        .filter_map(|(_, v)| urlencoding::decode(&v.replace('+', " ")).ok().map(|v| v.into_owned()))
+        .collect()
+}
+
+/// A file found in what was asked for: its URL path and where it is on disk.
+struct Found {
+    relative: String,
+    absolute: std::path::PathBuf,
+    name: String, // inside the archive
+}
+
+/// The files in `items`, named in the archive after the item they're in.
+async fn collect(items: Vec<ServedPath>) -> Result<Vec<Found>, String> {
+    web::block(move || {
+        let mut found = Vec::new();
+        for item in items {
+            if item.absolute.is_dir() {
+                // Clips come in separately, next to the videos they were cut from.
+                for (relative, absolute) in walk(&item.absolute, &item.relative)? {
+                    let inside = relative[item.relative.len()..].trim_start_matches('/');
+                    let name = match item.file_name() {
+                        "" => inside.to_string(),
+                        dir => format!("{}/{}", dir, inside),
+                    };
+                    found.push(Found { relative, absolute, name });
+                }
+            } else if item.absolute.is_file() {
+                let name = item.file_name().to_string();
+                found.push(Found { relative: item.relative, absolute: item.absolute, name });
+            }
+        }
+        Ok::<_, std::io::Error>(found)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("Error reading files: {}", e))
+}
+
+/// The archive entries for `files` plus the clips cut from them, and the JSON sidecar
+/// with their clips, points and tags.
+async fn with_metadata(db: &DatabaseConnection, files: Vec<Found>) -> Result<(Vec<Entry>, Vec<u8>), sea_orm::DbErr> {
+    let mut entries = Vec::new();
+    let mut sidecar = Vec::new();
+    let mut added = HashSet::new();
+    // A chunk at a time, to stay under SQLite's limit on query parameters.
+    for chunk in files.chunks(500) {
+        let paths: Vec<&str> = chunk.iter().map(|file| file.relative.as_str()).collect();
+        let clips = clip::Entity::find()
+            .filter(clip::Column::SourceFilename.is_in(paths.clone()))
+            .filter(clip::Column::DeletedAt.is_null())
+            .order_by_asc(clip::Column::Start)
+            .all(db)
+            .await?;
+        let points = point::Entity::find()
+            .filter(point::Column::SourceFilename.is_in(paths.clone()))
+            .filter(point::Column::DeletedAt.is_null())
+            .order_by_asc(point::Column::Time)
+            .all(db)
+            .await?;
+        let tags = tag::Entity::find()
+            .filter(tag::Column::SourceFilename.is_in(paths))
+            .filter(tag::Column::DeletedAt.is_null())
+            .all(db)
+            .await?;
+
+        for file in chunk {
+            if !added.insert(file.name.clone()) {
+                continue;
+            }
+            entries.push(Entry { absolute: file.absolute.clone(), name: file.name.clone() });
+            let file_clips: Vec<_> = clips.iter().filter(|c| c.source_filename == file.relative).collect();
+            for clip in &file_clips {
+                let absolute = file.absolute.with_file_name("segments").join(&clip.clip_filename);
+                let name = match file.name.rsplit_once('/') {
+                    Some((dir, _)) => format!("{}/segments/{}", dir, clip.clip_filename),
+                    None => format!("segments/{}", clip.clip_filename),
+                };
+                if absolute.is_file() && added.insert(name.clone()) {
+                    entries.push(Entry { absolute, name });
+                }
+            }
+            sidecar.push(json!({
+                "name": file.name,
+                "path": file.relative,
+                "clips": file_clips,
+                "points": points.iter().filter(|p| p.source_filename == file.relative).collect::<Vec<_>>(),
+                "tags": tags.iter().filter(|t| t.source_filename == file.relative).collect::<Vec<_>>(),
+            }));
+        }
+    }
+    let sidecar = json!({ "created_at": iso8601(now()), "files": sidecar });
+    Ok((entries, serde_json::to_vec_pretty(&sidecar).unwrap_or_default()))
+}
+
+/// Download as a zip or tar, built as it's sent. Takes one of:
+/// - `path`, once or more: those files and folders, e.g. what's ticked in a listing
+/// - `folder`: everything in a folder, used when nothing is ticked
+/// - `tag`: every file tagged with that slug
+///
+/// and `format`, `zip` (the default) or `tar`.
+#[get("/archive")]
+pub async fn download(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let query = req.query_string();
+    let format = match query_values(query, "format").first().map(String::as_str) {
+        None | Some("") => Format::Zip,
+        Some(format) => match Format::from_str_case_insensitive(format) {
+            Some(format) => format,
+            None => return HttpResponse::BadRequest().body("Format should be zip or tar"),
+        },
+    };
+
+    let (files, archive_name) = if let Some(slug) = query_values(query, "tag").first() {
+        let slug = tag::Model::normalize_tag(slug);
+        let tagged = match tag::Entity::find()
+            .filter(tag::Column::Slug.eq(&slug))
+            .filter(tag::Column::DeletedAt.is_null())
+            .order_by_asc(tag::Column::SourceFilename)
+            .all(db.get_ref())
+            .await
+        {
+            Ok(tagged) => tagged,
+            Err(err) => return internal_error("Error fetching tags", err),
+        };
+        // Full paths, since tagged files come from all over.
+        let files = tagged
+            .into_iter()
+            .filter(|tag| user.can_at(Permission::View, &tag.source_filename))
+            .filter_map(|tag| libraries.resolve(&tag.source_filename).ok())
+            .filter(|file| file.absolute.is_file())
+            .map(|file| Found { name: file.relative.clone(), relative: file.relative, absolute: file.absolute })
+            .collect();
+        (files, format!("tag-{}", slug))
+    } else {
+        let mut paths = query_values(query, "path");
+        let selected = !paths.is_empty();
+        if !selected {
+            paths = query_values(query, "folder");
+        }
+        let mut items = Vec::new();
+        for path in &paths {
+            let item = match libraries.resolve(path.trim()) {
+                Ok(item) => item,
+                Err(e) => return e.response(),
+            };
+            if let Err(resp) = user.require_at(Permission::View, &item.relative) {
+                return resp;
+            }
+            if !item.absolute.exists() {
+                return HttpResponse::NotFound().body(format!("{} not found", path));
+            }
+            items.push(item);
+        }
+        let archive_name = match items.as_slice() {
+            [] => return HttpResponse::BadRequest().body("Nothing to download"),
+            [item] if item.relative.is_empty() => "root".to_string(),
+            [item] => item.file_name().to_string(),
+            [item, ..] => match item.parent_relative().rsplit('/').next() {
+                Some("") | None => "selection".to_string(),
+                Some(parent) => format!("{}-selection", parent),
+            },
+        };
+        let files = match collect(items).await {
+            Ok(files) => files,
+            Err(e) => return internal_error("Error collecting files", e),
+        };
+        // Grants can reach into a folder the user can otherwise only see through.
+        let files = files.into_iter().filter(|file| user.can_at(Permission::View, &file.relative)).collect();
+        (files, archive_name)
+    };
+
+    let (entries, sidecar) = match with_metadata(db.get_ref(), files).await {
+        Ok(archive) => archive,
+        Err(err) => return internal_error("Error fetching clips, points and tags", err),
+    };
+    let filename = format!("{}.{}", archive_name, format.as_str());
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename*=UTF-8''{}", urlencoding::encode(&filename)),
+        ))
+        .streaming(archive::stream(format, entries, sidecar))
+}
+
+pub fn archives_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(download);
+}
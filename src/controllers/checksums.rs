@@ -0,0 +1,237 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use serde::Deserialize;
+use std::time::UNIX_EPOCH;
+use crate::models::{conversion, file_checksum};
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+use crate::utils::time::{iso8601, now};
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+
+fn internal_error(context: &str, err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}: {}", context, err);
+    HttpResponse::InternalServerError().body("Internal server error")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Checksums of every file inside `folder`, or all of them for the root.
+async fn checksums_under(db: &DatabaseConnection, folder: &str) -> Result<Vec<file_checksum::Model>, sea_orm::DbErr> {
+    let mut query = file_checksum::Entity::find().order_by_asc(file_checksum::Column::Path);
+    if folder.is_empty() {
+        return query.all(db).await;
+    }
+    let prefix = format!("{}/", folder);
+    query = query.filter(file_checksum::Column::Path.starts_with(&prefix));
+    // LIKE treats `_` and `%` as wildcards, so check the prefix properly too.
+    Ok(query.all(db).await?.into_iter().filter(|c| c.path.starts_with(&prefix)).collect())
+}
+
+// ---- Admin page ----
+
+#[derive(Deserialize)]
+pub struct ChecksumsQuery {
+    #[serde(default)]
+    pub path: String,
+}
+
+#[get("/admin/checksums")]
+pub async fn index(
+    query: web::Query<ChecksumsQuery>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    render(db.get_ref(), &query.path, "").await
+}
+
+async fn render(db: &DatabaseConnection, path: &str, notice: &str) -> HttpResponse {
+    let checksums = match checksums_under(db, path).await {
+        Ok(checksums) => checksums,
+        Err(err) => return internal_error("Error fetching checksums", err),
+    };
+    let count = |status| checksums.iter().filter(|c| c.status() == status).count();
+    let unverified = checksums.iter().filter(|c| c.verified_at.is_none()).count();
+
+    let mut html = String::from(notice);
+    html += &format!(
+        "<div class='card mb-4'><div class='card-header'>Verify</div><div class='card-body'>\
+        <form action='/admin/checksums/verify' method='post' class='d-flex flex-wrap gap-2 align-items-center'>\
+        <span>/</span><input type='text' name='path' value='{path}' placeholder='folder' class='form-control form-control-sm w-auto'>\
+        <button type='submit' class='btn btn-success btn-sm'>Verify now</button>\
+        <a href='/checksums/mhl?path={encoded}' class='btn btn-outline-secondary btn-sm'>Export MHL</a></form>\
+        <small class='text-muted'>{total} files with checksums: {ok} ok, {corrupt} corrupt, {missing} missing, {unverified} never re-checked. \
+        Verifying re-reads every file in the background and compares it with the hashes taken at upload.</small>\
+        </div></div>",
+        path = escape(path),
+        encoded = urlencoding::encode(path),
+        total = checksums.len(),
+        ok = count(file_checksum::Status::Ok),
+        corrupt = count(file_checksum::Status::Corrupt),
+        missing = count(file_checksum::Status::Missing),
+        unverified = unverified,
+    );
+
+    html += "<div class='card mb-4'><div class='card-header'>Flagged files</div><ul class='list-group list-group-flush'>";
+    let flagged: Vec<_> = checksums.iter().filter(|c| c.status() != file_checksum::Status::Ok).collect();
+    if flagged.is_empty() {
+        html += "<li class='list-group-item text-muted'>No corrupt or missing files.</li>";
+    }
+    for checksum in flagged {
+        html += &format!(
+            "<li class='list-group-item'><span class='badge bg-danger me-2'>{status}</span><a href='/{path}'>{path}</a>\
+            <small class='text-muted d-block'>uploaded {hashed_at} · checked {verified_at} · xxh64 {xxh64} · sha256 {sha256}</small></li>",
+            status = checksum.status().as_str(),
+            path = escape(&checksum.path),
+            hashed_at = iso8601(checksum.hashed_at),
+            verified_at = checksum.verified_at.map(iso8601).unwrap_or_default(),
+            xxh64 = checksum.xxh64,
+            sha256 = checksum.sha256,
+        );
+    }
+    html += "</ul></div>";
+
+    let page = ADMIN_HTML.replace("{{title}}", "Checksums").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[derive(Deserialize)]
+pub struct VerifyForm {
+    #[serde(default)]
+    pub path: String,
+}
+
+#[post("/admin/checksums/verify")]
+pub async fn verify(
+    form: web::Form<VerifyForm>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let folder = match libraries.resolve(form.path.trim()) {
+        Ok(folder) => folder,
+        Err(e) => return e.response(),
+    };
+    let checksums = match checksums_under(db.get_ref(), &folder.relative).await {
+        Ok(checksums) => checksums,
+        Err(err) => return internal_error("Error fetching checksums", err),
+    };
+    let mut queued = 0;
+    for checksum in &checksums {
+        match conversion::Model::request_conversion(db.get_ref(), checksum.path.clone(), "verify".to_string()).await {
+            Ok(true) => queued += 1,
+            Ok(false) => {}
+            Err(err) => return internal_error("Error queueing verification", err),
+        }
+    }
+    let notice = format!(
+        "<div class='alert alert-success'>Queued {} of {} files for verification.</div>",
+        queued,
+        checksums.len()
+    );
+    render(db.get_ref(), &folder.relative, &notice).await
+}
+
+// ---- MHL export ----
+
+#[derive(Deserialize)]
+pub struct MhlQuery {
+    #[serde(default)]
+    pub path: String,
+}
+
+/// The upload checksums of everything in a folder as an MHL 1.1 manifest, the format
+/// camera-card offload tools read and write. Paths are relative to the folder.
+#[get("/checksums/mhl")]
+pub async fn mhl(
+    req: HttpRequest,
+    query: web::Query<MhlQuery>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let folder = match libraries.resolve(query.path.trim()) {
+        Ok(folder) => folder,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::View, &folder.relative) {
+        return resp;
+    }
+    let started = now();
+    let checksums = match checksums_under(db.get_ref(), &folder.relative).await {
+        Ok(checksums) => checksums,
+        Err(err) => return internal_error("Error fetching checksums", err),
+    };
+
+    let mut hashes = String::new();
+    for checksum in checksums {
+        if !user.can_at(Permission::View, &checksum.path) {
+            continue;
+        }
+        // Files deleted since they were uploaded are left out.
+        let Ok(file) = libraries.resolve(&checksum.path) else {
+            continue;
+        };
+        let Ok(metadata) = std::fs::metadata(&file.absolute) else {
+            continue;
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(checksum.hashed_at);
+        let relative = match folder.relative.as_str() {
+            "" => checksum.path.as_str(),
+            dir => &checksum.path[dir.len() + 1..],
+        };
+        hashes += &format!(
+            "  <hash>\n    <file>{}</file>\n    <size>{}</size>\n    <lastmodificationdate>{}</lastmodificationdate>\n    <xxhash64be>{}</xxhash64be>\n    <hashdate>{}</hashdate>\n  </hash>\n",
+            xml_escape(relative),
+            checksum.size,
+            iso8601(modified),
+            checksum.xxh64,
+            iso8601(checksum.hashed_at),
+        );
+    }
+
+    let host = req.connection_info().host().to_string();
+    let manifest = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<hashlist version=\"1.1\">\n  <creatorinfo>\n    <username>{}</username>\n    <hostname>{}</hostname>\n    <tool>ryancloud {}</tool>\n    <startdate>{}</startdate>\n    <finishdate>{}</finishdate>\n  </creatorinfo>\n{}</hashlist>\n",
+        xml_escape(&user.user.username),
+        xml_escape(&host),
+        env!("CARGO_PKG_VERSION"),
+        iso8601(started),
+        iso8601(now()),
+        hashes,
+    );
+    // MHL files are conventionally named after the folder and the time they were made.
+    let name = match folder.file_name() {
+        "" => "root",
+        name => name,
+    };
+    let filename = format!("{}_{}.mhl", name, iso8601(started).replace(['-', ':'], "").trim_end_matches('Z'));
+    HttpResponse::Ok()
+        .content_type("text/xml; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename*=UTF-8''{}", urlencoding::encode(&filename)),
+        ))
+        .body(manifest)
+}
+
+pub fn checksums_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index);
+    cfg.service(verify);
+    cfg.service(mhl);
+}
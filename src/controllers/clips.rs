@@ -1,20 +1,30 @@
 use actix_web::{get, post, web, HttpResponse};
 use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
 use serde::Deserialize;
-use std::path::PathBuf;
 use crate::models::clip;
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
 use crate::utils::makeclip::create_clip_video;
+use crate::utils::libraries::Libraries;
+use crate::utils::media_index::media_id;
 use regex::Regex;
 
 #[get("{video_path:.*}/clips")]
 pub async fn index(
-    video_path: web::Path<PathBuf>,
+    video_path: web::Path<String>,
+    libraries: web::Data<Libraries>,
     db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
 ) -> HttpResponse {
-    let video_path_str = video_path.display().to_string();
-    let video_path_obj = PathBuf::from(&video_path_str);
-    let videopath = video_path_obj.parent().map(|p| p.display().to_string()).unwrap_or_default();
-    // let filename = video_path_obj.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+    let video = match libraries.resolve(&video_path) {
+        Ok(video) => video,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::View, &video.relative) {
+        return resp;
+    }
+    let video_path_str = video.relative.clone();
+    let videopath = video.parent_relative();
 
     // Fetch all clips associated with the given video path
     let clips = clip::Entity::find()
@@ -24,11 +34,16 @@ pub async fn index(
 
     match clips {
         Ok(clips) => {
-            let filename = video_path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+            let filename = video.file_name();
             let clips_html = if !clips.is_empty() {
                 clips
                     .into_iter()
                     .map(|clip| {
+                        let share = if user.can_at(Permission::Share, &clip.source_filename) {
+                            format!(" <a href='/shares?clip={}' class='small'>share</a>", clip.id)
+                        } else {
+                            String::new()
+                        };
                         // Build the video src as /{videopath}/segments/{clip_filename}
                         let src = if !videopath.is_empty() {
                             format!("/{}/segments/thumbs/{}.webp", videopath, clip.clip_filename)
@@ -36,8 +51,9 @@ pub async fn index(
                             format!("/segments/thumbs/{}.webp", clip.clip_filename)
                         };
                         format!(
-                            "<div class='video-thumb'><b>{}</b><p>{}</p><img src='{}' class='w-100 isavideo' onclick='replaceImgWithVideo();return false;'></div>",
+                            "<div class='video-thumb'><b>{}</b>{}<p>{}</p><img src='{}' class='w-100 isavideo' onclick='replaceImgWithVideo();return false;'></div>",
                             clip.name.unwrap_or_else(|| "Untitled".to_string()),
+                            share,
                             clip.description.unwrap_or_else(|| "No description available.".to_string()),
                             src,
                         )
@@ -69,14 +85,24 @@ pub struct ClipForm {
 
 #[post("{video_path:.*}/clips")]
 pub async fn create(
-    video_path: web::Path<PathBuf>,
+    video_path: web::Path<String>,
+    libraries: web::Data<Libraries>,
     form: web::Form<ClipForm>,
     db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
 ) -> HttpResponse {
-    let source_filename = video_path.display().to_string();
-    let working_directory = video_path.parent()
-        .map(|p| p.display().to_string())
-        .unwrap_or_else(|| "".to_string());
+    let video = match libraries.resolve(&video_path) {
+        Ok(video) => video,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::Edit, &video.relative) {
+        return resp;
+    }
+    if !video.absolute.is_file() {
+        return HttpResponse::NotFound().body("Video not found");
+    }
+    let source_filename = video.relative.clone();
+    let working_directory = video.parent_relative().to_string();
 
     // Log incoming data for debugging
     eprintln!("Received POST clip for video_path: {}", source_filename);
@@ -87,7 +113,7 @@ pub async fn create(
         return HttpResponse::BadRequest().body("Invalid clip range: 'start' must be less than 'end'");
     }
 
-    if form.name.as_ref().map_or(true, |name| name.trim().is_empty()) {
+    if form.name.as_ref().is_none_or(|name| name.trim().is_empty()) {
         return HttpResponse::BadRequest().body("Clip name cannot be blank");
     }
 
@@ -101,6 +127,7 @@ pub async fn create(
         .unwrap_or_else(|| "clip.mp4".to_string());
 
     // Insert into DB
+    let media_id = media_id(db.get_ref(), &video).await;
     let new_clip = clip::ActiveModel {
         source_filename: Set(source_filename.clone()),
         clip_filename: Set(clip_filename.clone()),
@@ -109,6 +136,7 @@ pub async fn create(
         name: Set(form.name.clone()),
         description: Set(form.description.clone()),
         working_directory: Set(working_directory), // Set working directory to the directory path
+        media_id: Set(media_id),
         ..Default::default()
     };
 
@@ -119,18 +147,18 @@ pub async fn create(
 
     // Kick off ffmpeg (async fire-and-forget)
     // Clip in the same directory + "/segments/"
-    let clip_filepath = video_path.parent()
+    let clip_filepath = video.absolute.parent()
         .map(|p| {
             let clips_dir = p.join("segments");
-            if !clips_dir.exists() {
-                if let Err(err) = std::fs::create_dir_all(&clips_dir) {
-                    eprintln!("Failed to create segments directory: {}", err);
-                }
+            if !clips_dir.exists()
+                && let Err(err) = std::fs::create_dir_all(&clips_dir)
+            {
+                eprintln!("Failed to create segments directory: {}", err);
             }
             clips_dir.join(&clip_filename).display().to_string()
         })
         .unwrap_or_else(|| clip_filename.clone());
-    match create_clip_video(&source_filename, form.start, form.end, &clip_filepath) {
+    match create_clip_video(&video.absolute.display().to_string(), form.start, form.end, &clip_filepath) {
         Ok(output_path) => {
             HttpResponse::Created().body(format!("Clip creation started: {}", output_path.display()))
         }
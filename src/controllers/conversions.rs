@@ -0,0 +1,481 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use sea_orm::{ActiveEnum, ColumnTrait, DatabaseConnection, EntityTrait, Iterable, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde::Deserialize;
+use serde_json::json;
+use crate::models::conversion::{self, ErrorKind, Operation, Status, MAX_TRIES};
+use crate::models::user::Permission;
+use crate::tools::conversions::RunningConversions;
+use crate::utils::auth::CurrentUser;
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+use crate::utils::time::{iso8601, now};
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+
+fn internal_error(context: &str, err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}: {}", context, err);
+    HttpResponse::InternalServerError().body("Internal server error")
+}
+
+fn badge(status: &str) -> &'static str {
+    match Status::try_from_value(&status.to_string()) {
+        Ok(Status::Pending) => "bg-secondary",
+        Ok(Status::Running) => "bg-primary",
+        Ok(Status::Completed) => "bg-success",
+        Ok(Status::Cancelled) => "bg-dark",
+        _ => "bg-danger",
+    }
+}
+
+fn is_active(conversion: &conversion::Model) -> bool {
+    conversion.status == Status::Pending.to_value() || conversion.status == Status::Running.to_value()
+}
+
+/// Seconds left until a running conversion's `eta`.
+fn seconds_left(conversion: &conversion::Model) -> Option<i64> {
+    conversion.eta.filter(|_| conversion.status == Status::Running.to_value()).map(|eta| (eta - now()).max(0))
+}
+
+// "1h 5m", "3m 20s" or "45s".
+fn human(seconds: i64) -> String {
+    match seconds {
+        s if s >= 3600 => format!("{}h {}m", s / 3600, s % 3600 / 60),
+        s if s >= 60 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
+
+fn to_json(conversion: &conversion::Model) -> serde_json::Value {
+    json!({
+        "id": conversion.id,
+        "path": conversion.source_filename,
+        "operation": conversion.operation,
+        "status": conversion.status,
+        "priority": conversion.priority,
+        "times_tried": conversion.times_tried,
+        "time_requested": conversion.time_requested,
+        "time_started": conversion.time_started,
+        "time_completed": conversion.time_completed,
+        "retry_at": conversion.retry_at,
+        "progress": conversion.progress,
+        "eta": conversion.eta,
+        "seconds_left": seconds_left(conversion),
+        "error_kind": conversion.error_kind,
+        "error": conversion.error,
+    })
+}
+
+/// How a conversion is doing, as JSON: `progress` is percent done and `eta` when it
+/// should finish, both only known while running and only for operations that can tell.
+#[get("/conversions/{id}")]
+pub async fn show(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let conversion = match conversion::Entity::find_by_id(*id).one(db.get_ref()).await {
+        Ok(Some(conversion)) => conversion,
+        Ok(None) => return HttpResponse::NotFound().body("Conversion not found"),
+        Err(err) => return internal_error("Error fetching conversion", err),
+    };
+    if let Err(resp) = user.require_at(Permission::View, &conversion.source_filename) {
+        return resp;
+    }
+    HttpResponse::Ok().json(to_json(&conversion))
+}
+
+// HTMX endpoint: the video's queued and running conversions and the last few finished,
+// polling for updates for as long as any are still going.
+#[get("{video_path:.*}/conversions")]
+pub async fn index(
+    video_path: web::Path<String>,
+    libraries: web::Data<Libraries>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let video = match libraries.resolve(&video_path) {
+        Ok(video) => video,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::View, &video.relative) {
+        return resp;
+    }
+    let conversions = conversion::Entity::find()
+        .filter(conversion::Column::SourceFilename.eq(&video.relative))
+        .order_by_desc(conversion::Column::TimeRequested)
+        .order_by_desc(conversion::Column::Id)
+        .limit(20)
+        .all(db.get_ref())
+        .await;
+    let conversions = match conversions {
+        Ok(conversions) => conversions,
+        Err(err) => return internal_error("Error fetching conversions", err),
+    };
+    let (active, finished): (Vec<_>, Vec<_>) = conversions.into_iter().partition(is_active);
+
+    let polling = if active.is_empty() {
+        String::new()
+    } else {
+        format!(" hx-get=\"{}/conversions\" hx-trigger=\"every 2s\" hx-swap=\"outerHTML\"", video.url())
+    };
+    let mut html = format!("<div class='conversions-list'{}>", polling);
+    if !active.is_empty() || !finished.is_empty() {
+        html += "<div class='text-muted mt-3'>Conversions</div>";
+    }
+    for conversion in &active {
+        let mut detail = conversion.status.clone();
+        if let Some(progress) = conversion.progress {
+            detail += &format!(" · {:.0}%", progress);
+        }
+        if let Some(left) = seconds_left(conversion) {
+            detail += &format!(" · about {} left", human(left));
+        }
+        if conversion.times_tried > 1 {
+            detail += &format!(" · try {}", conversion.times_tried);
+        }
+        let bar = match conversion.progress {
+            Some(progress) => format!(
+                "<div class='progress' style='height:4px'><div class='progress-bar' style='width:{:.0}%'></div></div>",
+                progress
+            ),
+            None if conversion.status == Status::Running.to_value() => "<div class='progress' style='height:4px'>\
+                <div class='progress-bar progress-bar-striped progress-bar-animated' style='width:100%'></div></div>".to_string(),
+            None => String::new(),
+        };
+        html += &format!("<div class='small mb-1'><b>{}</b> <span class='text-muted'>{}</span>{}</div>", conversion.operation, detail, bar);
+    }
+    for conversion in finished.iter().take(5) {
+        html += &format!(
+            "<div class='small mb-1'><b>{}</b> <span class='badge {}' title=\"{}\">{}</span></div>",
+            conversion.operation,
+            badge(&conversion.status),
+            escape(conversion.error.as_deref().unwrap_or_default()),
+            conversion.status
+        );
+    }
+    html += "</div>";
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub status: Option<String>,
+    pub operation: Option<String>,
+    pub kind: Option<String>,
+}
+
+impl ListQuery {
+    fn status(&self) -> Option<&str> {
+        self.status.as_deref().filter(|s| !s.is_empty())
+    }
+
+    fn operation(&self) -> Option<&str> {
+        self.operation.as_deref().filter(|s| !s.is_empty())
+    }
+
+    fn kind(&self) -> Option<&str> {
+        self.kind.as_deref().filter(|s| !s.is_empty())
+    }
+}
+
+/// Up to 500 conversions matching `query`, newest first.
+async fn find(db: &DatabaseConnection, query: &ListQuery) -> Result<Vec<conversion::Model>, sea_orm::DbErr> {
+    let mut find = conversion::Entity::find()
+        .order_by_desc(conversion::Column::TimeRequested)
+        .order_by_desc(conversion::Column::Id)
+        .limit(500);
+    if let Some(status) = query.status() {
+        find = find.filter(conversion::Column::Status.eq(status));
+    }
+    if let Some(operation) = query.operation() {
+        find = find.filter(conversion::Column::Operation.eq(operation));
+    }
+    if let Some(kind) = query.kind() {
+        find = find.filter(conversion::Column::ErrorKind.eq(kind));
+    }
+    find.all(db).await
+}
+
+fn options<T: ActiveEnum<Value = String> + Iterable>(selected: Option<&str>) -> String {
+    let mut html = String::new();
+    for each in T::iter() {
+        let value = each.to_value();
+        let chosen = if selected == Some(value.as_str()) { " selected" } else { "" };
+        html += &format!("<option value='{0}'{1}>{0}</option>", value, chosen);
+    }
+    html
+}
+
+/// Admin page: the conversion queue, filtered by status, operation and error kind, with
+/// buttons to cancel, retry and reprioritize conversions and to clear out completed ones.
+/// Failures show what ffmpeg was asked to do and what it said, so bad files can be looked
+/// into from here.
+#[get("/admin/conversions")]
+pub async fn dashboard(
+    query: web::Query<ListQuery>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let conversions = match find(db.get_ref(), &query).await {
+        Ok(conversions) => conversions,
+        Err(err) => return internal_error("Error fetching conversions", err),
+    };
+    let mut counts = Vec::new();
+    for status in Status::iter() {
+        let count = conversion::Entity::find()
+            .filter(conversion::Column::Status.eq(status.to_value()))
+            .count(db.get_ref())
+            .await;
+        match count {
+            Ok(count) => counts.push(format!("{} {}", count, status.to_value())),
+            Err(err) => return internal_error("Error counting conversions", err),
+        }
+    }
+
+    let operation = query.operation();
+    let mut completed = conversion::Entity::find().filter(conversion::Column::Status.eq(Status::Completed.to_value()));
+    if let Some(operation) = operation {
+        completed = completed.filter(conversion::Column::Operation.eq(operation));
+    }
+    let completed = completed.count(db.get_ref()).await.unwrap_or(0);
+    let delete_button = if completed > 0 {
+        format!(
+            "<button class='btn btn-outline-danger btn-sm' hx-delete='/conversions/completed?operation={}' \
+            hx-confirm='Delete {} completed {}conversions?'>Delete completed</button>",
+            urlencoding::encode(operation.unwrap_or_default()),
+            completed,
+            operation.map(|operation| format!("{} ", escape(operation))).unwrap_or_default(),
+        )
+    } else {
+        String::new()
+    };
+    let mut html = format!(
+        "<form method='get' class='d-flex flex-wrap gap-2 align-items-center mb-3'>\
+        <select name='status' class='form-select form-select-sm w-auto' onchange='this.form.submit()'><option value=''>Any status</option>{}</select>\
+        <select name='operation' class='form-select form-select-sm w-auto' onchange='this.form.submit()'><option value=''>Any operation</option>{}</select>\
+        <select name='kind' class='form-select form-select-sm w-auto' onchange='this.form.submit()'><option value=''>Any error</option>{}</select>\
+        {}<small class='text-muted ms-auto'>{}</small></form>",
+        options::<Status>(query.status()),
+        options::<Operation>(operation),
+        options::<ErrorKind>(query.kind()),
+        delete_button,
+        counts.join(" · "),
+    );
+    html += "<div class='card mb-4'><ul class='list-group list-group-flush'>";
+    if conversions.is_empty() {
+        html += "<li class='list-group-item text-muted'>No conversions.</li>";
+    }
+    for conversion in conversions {
+        let mut actions = format!(
+            "<input type='number' name='priority' value='{}' title='Priority, higher goes first' \
+            class='form-control form-control-sm' style='width:5em' hx-post='/conversions/{}/priority' hx-trigger='change'>",
+            conversion.priority, conversion.id
+        );
+        if is_active(&conversion) {
+            actions += &format!(
+                "<button class='btn btn-outline-danger btn-sm' hx-post='/conversions/{}/cancel' hx-confirm='Cancel this conversion?'>Cancel</button>",
+                conversion.id
+            );
+        } else if conversion.status != Status::Completed.to_value() {
+            actions += &format!("<button class='btn btn-outline-secondary btn-sm' hx-post='/conversions/{}/retry'>Retry</button>", conversion.id);
+        }
+
+        let mut timings = vec![format!("try {} of {}", conversion.times_tried, MAX_TRIES), format!("requested {}", iso8601(conversion.time_requested))];
+        if let Some(started) = conversion.time_started {
+            timings.push(format!("started {}", iso8601(started)));
+            if let Some(completed) = conversion.time_completed {
+                timings.push(format!("took {}", human(completed - started)));
+            }
+        }
+        if let Some(progress) = conversion.progress.filter(|_| conversion.status == Status::Running.to_value()) {
+            timings.push(format!("{:.0}%", progress));
+        }
+        if let Some(left) = seconds_left(&conversion) {
+            timings.push(format!("about {} left", human(left)));
+        }
+        if let Some(retry_at) = conversion.retry_at.filter(|_| conversion.status == Status::Pending.to_value()) {
+            timings.push(format!("retrying at {}", iso8601(retry_at)));
+        }
+
+        let mut error = String::new();
+        if let Some(message) = &conversion.error {
+            error += &format!(
+                "<div class='small text-danger'><span class='badge bg-danger'>{}</span> {}</div>",
+                conversion.error_kind.as_deref().unwrap_or("other"),
+                escape(message)
+            );
+        }
+        let mut details = String::new();
+        if let Some(command) = &conversion.command {
+            details += &format!("<div class='small text-muted mt-2'>Command</div><pre class='small bg-white border p-2 mb-0'>{}</pre>", escape(command));
+        }
+        if let Some(output) = conversion.error_output.as_deref().filter(|output| !output.is_empty()) {
+            details += &format!("<div class='small text-muted mt-2'>ffmpeg output</div><pre class='small bg-white border p-2 mb-0'>{}</pre>", escape(output));
+        }
+        if !details.is_empty() {
+            error += &format!("<details><summary class='small text-muted'>ffmpeg</summary>{}</details>", details);
+        }
+
+        html += &format!(
+            "<li class='list-group-item'><div class='d-flex flex-wrap gap-2 align-items-center'>\
+            <span class='text-muted small'>#{}</span><code>/{}</code><b>{}</b><span class='badge {}'>{}</span>\
+            <span class='ms-auto d-flex gap-2'>{}</span></div>\
+            <small class='text-muted d-block'>{}</small>{}</li>",
+            conversion.id,
+            escape(&conversion.source_filename),
+            conversion.operation,
+            badge(&conversion.status),
+            conversion.status,
+            actions,
+            timings.join(" · "),
+            error,
+        );
+    }
+    html += "</ul></div>";
+
+    let page = ADMIN_HTML.replace("{{title}}", "Conversions").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+/// The same conversions as the dashboard, as JSON for scripts.
+#[get("/conversions")]
+pub async fn list(
+    query: web::Query<ListQuery>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    match find(db.get_ref(), &query).await {
+        Ok(conversions) => HttpResponse::Ok().json(conversions.iter().map(to_json).collect::<Vec<_>>()),
+        Err(err) => internal_error("Error fetching conversions", err),
+    }
+}
+
+/// After a change: the dashboard reloads, scripts get the conversion as it is now.
+async fn changed(req: &HttpRequest, db: &DatabaseConnection, id: i32) -> HttpResponse {
+    if req.headers().contains_key("HX-Request") {
+        return HttpResponse::Ok().insert_header(("HX-Refresh", "true")).finish();
+    }
+    match conversion::Entity::find_by_id(id).one(db).await {
+        Ok(Some(conversion)) => HttpResponse::Ok().json(to_json(&conversion)),
+        Ok(None) => HttpResponse::NotFound().body("Conversion not found"),
+        Err(err) => internal_error("Error fetching conversion", err),
+    }
+}
+
+async fn exists(db: &DatabaseConnection, id: i32) -> Result<(), HttpResponse> {
+    match conversion::Entity::find_by_id(id).one(db).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(HttpResponse::NotFound().body("Conversion not found")),
+        Err(err) => Err(internal_error("Error fetching conversion", err)),
+    }
+}
+
+/// Stop a pending or running conversion; a running one's ffmpeg is killed.
+#[post("/conversions/{id}/cancel")]
+pub async fn cancel(
+    req: HttpRequest,
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    running: web::Data<RunningConversions>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    if let Err(resp) = exists(db.get_ref(), *id).await {
+        return resp;
+    }
+    match conversion::Model::cancel(db.get_ref(), *id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Conflict().body("Only pending and running conversions can be cancelled"),
+        Err(err) => return internal_error("Error cancelling conversion", err),
+    }
+    running.cancel(*id);
+    changed(&req, db.get_ref(), *id).await
+}
+
+/// Queue a failed or cancelled conversion again, from the first try.
+#[post("/conversions/{id}/retry")]
+pub async fn retry(
+    req: HttpRequest,
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    if let Err(resp) = exists(db.get_ref(), *id).await {
+        return resp;
+    }
+    match conversion::Model::retry(db.get_ref(), *id).await {
+        Ok(true) => changed(&req, db.get_ref(), *id).await,
+        Ok(false) => HttpResponse::Conflict().body("Only failed and cancelled conversions can be retried"),
+        Err(err) => internal_error("Error retrying conversion", err),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PriorityForm {
+    pub priority: i32,
+}
+
+/// Set a conversion's priority; pending ones with a higher priority are run first.
+#[post("/conversions/{id}/priority")]
+pub async fn priority(
+    req: HttpRequest,
+    id: web::Path<i32>,
+    form: web::Form<PriorityForm>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    match conversion::Model::set_priority(db.get_ref(), *id, form.priority).await {
+        Ok(true) => changed(&req, db.get_ref(), *id).await,
+        Ok(false) => HttpResponse::NotFound().body("Conversion not found"),
+        Err(err) => internal_error("Error setting conversion priority", err),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DeleteCompletedQuery {
+    pub operation: Option<String>,
+}
+
+/// Delete completed conversions, only those of `operation` if given.
+#[delete("/conversions/completed")]
+pub async fn delete_completed(
+    req: HttpRequest,
+    query: web::Query<DeleteCompletedQuery>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let operation = query.operation.as_deref().filter(|operation| !operation.is_empty());
+    match conversion::Model::delete_completed(db.get_ref(), operation).await {
+        Ok(_) if req.headers().contains_key("HX-Request") => HttpResponse::Ok().insert_header(("HX-Refresh", "true")).finish(),
+        Ok(deleted) => HttpResponse::Ok().json(json!({ "deleted": deleted })),
+        Err(err) => internal_error("Error deleting conversions", err),
+    }
+}
+
+pub fn conversions_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(dashboard);
+    cfg.service(list); // Before `{video_path}/conversions`, which would take it for the root.
+    cfg.service(delete_completed);
+    cfg.service(cancel);
+    cfg.service(retry);
+    cfg.service(priority);
+    cfg.service(show);
+    cfg.service(index);
+}
@@ -0,0 +1,424 @@
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::Error as ActixError;
+use futures_util::stream::StreamExt as _;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set};
+use sea_orm::sea_query::Expr;
+use serde::Deserialize;
+use crate::controllers::files::{read_text_field, save_upload};
+use crate::models::{dropbox, dropbox_upload};
+use crate::models::user::Permission;
+use crate::utils::auth::{random_token, CurrentUser};
+use crate::utils::checksums::ExpectedChecksum;
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+use crate::utils::time::now;
+use crate::utils::uploads::ConflictPolicy;
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+const PUBLIC_HTML: &str = include_str!("../views/shares/show.html");
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+fn internal_error(context: &str, err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}: {}", context, err);
+    HttpResponse::InternalServerError().body("Internal server error")
+}
+
+fn format_size(bytes: i64) -> String {
+    if bytes as f64 >= GB {
+        format!("{:.1} GB", bytes as f64 / GB)
+    } else {
+        format!("{:.1} MB", bytes as f64 / 1_048_576.0)
+    }
+}
+
+// ---- Admin pages ----
+
+#[derive(Deserialize)]
+pub struct DropboxesQuery {
+    #[serde(default)]
+    pub path: String,
+}
+
+#[get("/admin/dropboxes")]
+pub async fn index(
+    query: web::Query<DropboxesQuery>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    render(db.get_ref(), &query.path, "").await
+}
+
+async fn render(db: &DatabaseConnection, path: &str, notice: &str) -> HttpResponse {
+    let dropboxes = match dropbox::Entity::find()
+        .filter(dropbox::Column::RevokedAt.is_null())
+        .order_by_desc(dropbox::Column::CreatedAt)
+        .all(db)
+        .await
+    {
+        Ok(dropboxes) => dropboxes,
+        Err(err) => return internal_error("Error fetching drop-boxes", err),
+    };
+    let uploads = match dropbox_upload::Entity::find()
+        .filter(dropbox_upload::Column::DropboxId.is_in(dropboxes.iter().map(|d| d.id)))
+        .order_by_desc(dropbox_upload::Column::CreatedAt)
+        .all(db)
+        .await
+    {
+        Ok(uploads) => uploads,
+        Err(err) => return internal_error("Error fetching drop-box uploads", err),
+    };
+
+    let mut html = String::from(notice);
+    html += &format!(
+        "<div class='card mb-4'><div class='card-header'>New drop-box</div><div class='card-body'>\
+        <form action='/admin/dropboxes' method='post' class='d-flex flex-wrap gap-2 align-items-center'>\
+        <input type='text' name='name' placeholder='Name, e.g. Smith wedding' class='form-control form-control-sm w-auto' required>\
+        <span>/</span><input type='text' name='path' value='{path}' placeholder='target folder' class='form-control form-control-sm w-auto'>\
+        <input type='number' name='max_gb' min='0' step='any' placeholder='quota in GB' class='form-control form-control-sm w-auto'>\
+        <input type='text' name='extensions' placeholder='extensions, e.g. mp4 mov' class='form-control form-control-sm w-auto'>\
+        <input type='number' name='expires_in_days' min='1' placeholder='expires in days' class='form-control form-control-sm w-auto'>\
+        <button type='submit' class='btn btn-success btn-sm'>Create</button></form>\
+        <small class='text-muted'>Anyone with the link can upload into the folder without an account, \
+        but can't see what's in it. Existing files are never overwritten.</small>\
+        </div></div>",
+        path = escape(path),
+    );
+
+    let now = now();
+    html += "<div class='card mb-4'><div class='card-header'>Drop-boxes</div><ul class='list-group list-group-flush'>";
+    let active: Vec<_> = dropboxes.into_iter().filter(|d| d.is_active(now)).collect();
+    if active.is_empty() {
+        html += "<li class='list-group-item text-muted'>No active drop-boxes.</li>";
+    }
+    for dropbox in active {
+        let received: Vec<_> = uploads.iter().filter(|u| u.dropbox_id == dropbox.id).collect();
+        let used: i64 = received.iter().map(|u| u.size).sum();
+        let mut details = vec![match dropbox.max_bytes {
+            Some(max) => format!("{} of {} used", format_size(used), format_size(max)),
+            None => format!("{} used", format_size(used)),
+        }];
+        if !dropbox.allowed_extensions.is_empty() {
+            details.push(dropbox.extensions().join(", "));
+        }
+        if let Some(expires_at) = dropbox.expires_at {
+            details.push(format!("expires in {}h", (expires_at - now) / 3600));
+        }
+        html += &format!(
+            "<li class='list-group-item'><div class='d-flex align-items-center gap-2'><b>{name}</b> → /{path}\
+            <a href='/d/{token}' class='small'>/d/{token}</a><small class='text-muted me-auto'>{details}</small>\
+            <form action='/admin/dropboxes/{id}/revoke' method='post' onsubmit=\"return confirm('Close this drop-box?')\"><button type='submit' class='btn btn-outline-danger btn-sm'>Close</button></form></div>",
+            name = escape(&dropbox.name),
+            path = escape(&dropbox.path),
+            token = dropbox.token,
+            details = details.join(" · "),
+            id = dropbox.id,
+        );
+        if !received.is_empty() {
+            html += "<ul class='small mt-2 mb-0'>";
+            for received_file in received {
+                html += &format!(
+                    "<li><a href='/{}'>{}</a> ({}) from {} &lt;{}&gt;, {}h ago</li>",
+                    escape(&received_file.filename),
+                    escape(&received_file.filename),
+                    format_size(received_file.size),
+                    escape(&received_file.uploader_name),
+                    escape(&received_file.uploader_email),
+                    (now - received_file.created_at) / 3600
+                );
+            }
+            html += "</ul>";
+        }
+        html += "</li>";
+    }
+    html += "</ul></div>";
+
+    let page = ADMIN_HTML.replace("{{title}}", "Drop-boxes").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[derive(Deserialize)]
+pub struct DropboxForm {
+    pub name: String,
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub max_gb: String,
+    #[serde(default)]
+    pub extensions: String,
+    #[serde(default)]
+    pub expires_in_days: String,
+}
+
+#[post("/admin/dropboxes")]
+pub async fn create(
+    form: web::Form<DropboxForm>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let name = form.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Drop-boxes need a name");
+    }
+    let folder = match libraries.resolve(form.path.trim()) {
+        Ok(folder) => folder,
+        Err(e) => return e.response(),
+    };
+    if !folder.absolute.is_dir() {
+        return HttpResponse::BadRequest().body("Drop-boxes must point at an existing folder");
+    }
+    // Empty fields mean no quota and no expiry.
+    let max_bytes = match form.max_gb.trim() {
+        "" => None,
+        gb => match gb.parse::<f64>() {
+            Ok(gb) if gb > 0.0 => Some((gb * GB) as i64),
+            _ => return HttpResponse::BadRequest().body("Quota must be a positive number of GB"),
+        },
+    };
+    let expires_in_days = match form.expires_in_days.trim() {
+        "" => None,
+        days => match days.parse::<i64>() {
+            Ok(days) if days > 0 => Some(days),
+            _ => return HttpResponse::BadRequest().body("Expiry must be a positive number of days"),
+        },
+    };
+
+    let token = random_token();
+    let created_at = now();
+    let new_dropbox = dropbox::ActiveModel {
+        token: Set(token.clone()),
+        name: Set(name.to_string()),
+        path: Set(folder.relative),
+        max_bytes: Set(max_bytes),
+        allowed_extensions: Set(dropbox::normalize_extensions(&form.extensions)),
+        expires_at: Set(expires_in_days.map(|days| created_at + days * 24 * 3600)),
+        created_by: Set(user.user.id),
+        created_at: Set(created_at),
+        revoked_at: Set(None),
+        ..Default::default()
+    };
+    if let Err(err) = new_dropbox.insert(db.get_ref()).await {
+        return internal_error("Error creating drop-box", err);
+    }
+
+    let notice = format!(
+        "<div class='alert alert-success'>Drop-box created: <a href='/d/{0}'><code>/d/{0}</code></a></div>",
+        token
+    );
+    render(db.get_ref(), "", &notice).await
+}
+
+#[post("/admin/dropboxes/{id}/revoke")]
+pub async fn revoke(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    if let Err(err) = dropbox::Entity::update_many()
+        .col_expr(dropbox::Column::RevokedAt, Expr::value(now()))
+        .filter(dropbox::Column::Id.eq(*id))
+        .filter(dropbox::Column::RevokedAt.is_null())
+        .exec(db.get_ref())
+        .await
+    {
+        return internal_error("Error closing drop-box", err);
+    }
+    HttpResponse::SeeOther().append_header(("Location", "/admin/dropboxes")).finish()
+}
+
+// ---- Public /d/{token} pages ----
+
+async fn find_dropbox(db: &DatabaseConnection, token: &str) -> Result<dropbox::Model, HttpResponse> {
+    match dropbox::Model::find_active(db, token, now()).await {
+        Ok(Some(dropbox)) => Ok(dropbox),
+        Ok(None) => Err(HttpResponse::NotFound().body("This drop-box is closed or does not exist")),
+        Err(err) => Err(internal_error("Error fetching drop-box", err)),
+    }
+}
+
+fn public_page(dropbox: &dropbox::Model, contents: &str) -> HttpResponse {
+    let page = PUBLIC_HTML.replace("{{contents}}", contents).replace("{{title}}", &escape(&dropbox.name));
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[get("/d/{token}")]
+pub async fn show(
+    token: web::Path<String>,
+    db: web::Data<DatabaseConnection>,
+) -> HttpResponse {
+    let dropbox = match find_dropbox(db.get_ref(), &token).await {
+        Ok(dropbox) => dropbox,
+        Err(resp) => return resp,
+    };
+    let mut hints = Vec::new();
+    if !dropbox.allowed_extensions.is_empty() {
+        hints.push(format!("Accepted file types: {}", dropbox.extensions().join(", ")));
+    }
+    if let Some(max) = dropbox.max_bytes {
+        hints.push(format!("Space left: {}", format_size((max - dropbox.reserved_bytes).max(0))));
+    }
+    let accept = dropbox.extensions().iter().map(|ext| format!(".{}", ext)).collect::<Vec<_>>().join(",");
+    // Name and email come first so they arrive before the files in the multipart body.
+    let contents = format!(
+        "<form action='/d/{token}' method='post' enctype='multipart/form-data' style='max-width:500px'>\
+        <input type='text' name='name' placeholder='Your name' class='form-control mb-2' required>\
+        <input type='email' name='email' placeholder='Your email' class='form-control mb-2' required>\
+        <input type='file' name='files' multiple accept='{accept}' class='form-control mb-2' required>\
+        <button type='submit' class='btn btn-success'>Upload</button></form>\
+        <p class='text-muted small mt-2'>{hints}</p>",
+        token = dropbox.token,
+        accept = accept,
+        hints = escape(&hints.join("\n")).replace('\n', "<br>"),
+    );
+    public_page(&dropbox, &contents)
+}
+
+#[post("/d/{token}")]
+pub async fn upload(
+    req: HttpRequest,
+    token: web::Path<String>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    payload: Multipart,
+) -> Result<HttpResponse, ActixError> {
+    let dropbox = match find_dropbox(db.get_ref(), &token).await {
+        Ok(dropbox) => dropbox,
+        Err(resp) => return Ok(resp),
+    };
+    // The request can't hold more files than its whole body, so that's all it reserves.
+    let wanted = req
+        .headers()
+        .get("Content-Length")
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok())
+        .unwrap_or(u64::MAX);
+    let reserved = match dropbox.reserve(db.get_ref(), wanted).await {
+        Ok(reserved) => reserved,
+        Err(err) => return Ok(internal_error("Error reserving drop-box space", err)),
+    };
+    let mut remaining = dropbox.max_bytes.map(|_| reserved);
+    let received = receive(db.get_ref(), &libraries, &dropbox, payload, &mut remaining).await;
+    if let Err(err) = dropbox.release(db.get_ref(), remaining.unwrap_or(0)).await {
+        eprintln!("Error releasing drop-box space: {}", err);
+    }
+    let results = received?;
+
+    let mut html = String::from("<ul class='list-group mb-3' style='max-width:500px'>");
+    if results.is_empty() {
+        html += "<li class='list-group-item'>No files were received.</li>";
+    }
+    for (file, status) in results {
+        html += &format!("<li class='list-group-item'>{}: {}</li>", escape(&file), escape(&status));
+    }
+    html += &format!("</ul><a class='btn btn-primary' href='/d/{}'>Send more</a>", dropbox.token);
+    Ok(public_page(&dropbox, &html))
+}
+
+// Saves the files in an upload form into the drop-box's folder, within `remaining` bytes
+// of its quota, and says what happened to each.
+async fn receive(
+    db: &DatabaseConnection,
+    libraries: &Libraries,
+    dropbox: &dropbox::Model,
+    mut payload: Multipart,
+    remaining: &mut Option<u64>,
+) -> Result<Vec<(String, String)>, ActixError> {
+    let mut uploader_name = String::new();
+    let mut uploader_email = String::new();
+    let mut checksum = None;
+    let mut results = Vec::new();
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        let filename = field.content_disposition().and_then(|cd| cd.get_filename().map(|f| f.to_string()));
+        let Some(filename) = filename else {
+            match field.name() {
+                Some("name") => uploader_name = read_text_field(&mut field).await?,
+                Some("email") => uploader_email = read_text_field(&mut field).await?,
+                Some("checksum") => checksum = Some(read_text_field(&mut field).await?).filter(|value| !value.is_empty()),
+                _ => {
+                    read_text_field(&mut field).await?;
+                }
+            }
+            continue;
+        };
+        if filename.is_empty() {
+            continue;
+        }
+        let expected = match checksum.take().map(|value| ExpectedChecksum::parse(&value)).transpose() {
+            Ok(expected) => expected,
+            Err(e) => {
+                results.push((filename, e));
+                continue;
+            }
+        };
+
+        if uploader_name.is_empty() || !uploader_email.contains('@') {
+            results.push((filename, "Please enter your name and email".to_string()));
+            continue;
+        }
+        if !dropbox.allows_file(&filename) {
+            results.push((filename, "This file type is not accepted".to_string()));
+            continue;
+        }
+        if *remaining == Some(0) {
+            results.push((filename, "The drop-box is full".to_string()));
+            continue;
+        }
+        let target = match libraries.resolve_child(&dropbox.path, &filename) {
+            Ok(target) => target,
+            Err(e) => {
+                results.push((filename, e.to_string()));
+                continue;
+            }
+        };
+
+        if target.absolute.exists() {
+            results.push((filename, "File exists, skipped".to_string()));
+            continue;
+        }
+        let (target, size) = match save_upload(db, libraries, &mut field, target, ConflictPolicy::Skip, *remaining, expected.as_ref()).await {
+            Ok((target, digest)) => (target, digest.size),
+            Err(e) => {
+                results.push((filename, e));
+                continue;
+            }
+        };
+        *remaining = remaining.map(|left| left.saturating_sub(size));
+
+        let record = dropbox_upload::ActiveModel {
+            dropbox_id: Set(dropbox.id),
+            filename: Set(target.relative),
+            size: Set(size as i64),
+            uploader_name: Set(uploader_name.clone()),
+            uploader_email: Set(uploader_email.clone()),
+            created_at: Set(now()),
+            ..Default::default()
+        };
+        if let Err(err) = record.insert(db).await {
+            eprintln!("Error recording drop-box upload: {}", err);
+        }
+        results.push((filename, "Uploaded".to_string()));
+    }
+    Ok(results)
+}
+
+pub fn dropboxes_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index);
+    cfg.service(create);
+    cfg.service(revoke);
+    cfg.service(show);
+    cfg.service(upload);
+}
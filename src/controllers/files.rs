@@ -1,43 +1,66 @@
 use actix_web::{web, HttpResponse, HttpRequest, Result};
-use actix_session::Session;
-use actix_multipart::Multipart;
+use actix_multipart::{Field, Multipart};
 use futures_util::stream::StreamExt as _;
 use actix_files::NamedFile;
 use std::fs;
+use std::path::Path;
 use actix_web::Error as ActixError;
-use crate::is_logged_in;
-use std::path::PathBuf;
+use crate::models::file_checksum;
+use crate::models::rendition::PROXIES_DIR;
+use crate::models::trash_item::TRASH_DIR;
+use crate::models::upload_session::STAGING_DIR;
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::checksums::{Digest, ExpectedChecksum, Hasher};
+use crate::utils::html::{escape, url_path};
+use crate::utils::libraries::Libraries;
+use crate::utils::paths::{child_path, ServedPath};
+use crate::utils::time::now;
+use crate::utils::uploads::{create_staging_file, place_file, remove_staging_file, upload_target, ConflictPolicy, VERSIONS_DIR};
 use sea_orm::DatabaseConnection;
 
 // Unified handler: serve file if path is file, list if directory
 pub async fn browse(
     db: web::Data<DatabaseConnection>,
-    folder: web::Data<PathBuf>,
+    libraries: web::Data<Libraries>,
     req: HttpRequest,
     path: Option<web::Path<String>>,
-    session: Session,
+    user: CurrentUser,
 ) -> HttpResponse {
-    if !is_logged_in(&session) {
-        let template = include_str!("../views/files/index.html");
-        let response_html = template.replace("{{contents}}", "<a class='btn btn-primary mt-3' href='/login'>Login</a>");
-        return HttpResponse::Ok().content_type("text/html").body(response_html);
+    if let Err(resp) = user.require(Permission::View) {
+        return resp;
     }
 
-    let mut target = folder.get_ref().clone();
-    let subpath = path.as_ref().map(|p| p.as_str()).unwrap_or("");
-    if !subpath.is_empty() {
-        target = target.join(subpath);
+    let resolved = match libraries.resolve(path.as_ref().map(|p| p.as_str()).unwrap_or("")) {
+        Ok(resolved) => resolved,
+        Err(e) => return e.response(),
+    };
+    let subpath = resolved.relative.as_str();
+    let target = resolved.absolute.clone();
+    // Folders on the way to a granted folder can be listed; files need the grant itself.
+    if target.is_dir() && !user.can_see(subpath) {
+        return HttpResponse::Forbidden().body("You do not have access to this folder");
+    }
+    if !target.is_dir()
+        && let Err(resp) = user.require_at(Permission::View, subpath)
+    {
+        return resp;
     }
     if subpath.contains("thumbs/") && !target.exists() {
         // Create a conversion request for thumbnail generation
-        let original_file_name = target.file_name().and_then(|n| n.to_str()).unwrap_or("")
-            .replace(".webp", "");
-        let original_path = target.parent().unwrap().parent().unwrap().join(&original_file_name);
-        
-        if original_path.exists() {
+        // `dir/thumbs/video.mp4.webp` is the thumbnail for `dir/video.mp4`.
+        let original_file_name = resolved.file_name().replace(".webp", "");
+        let thumbs_dir = resolved.parent_relative();
+        let original_dir = thumbs_dir.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+        let original = child_path(original_dir, &original_file_name)
+            .and_then(|original| libraries.resolve(&original));
+
+        if let Ok(original) = original
+            && original.absolute.exists()
+        {
             use crate::models::conversion;
-            
-            let source_filename = original_path.to_string_lossy().to_string();
+
+            let source_filename = original.relative;
 
             match conversion::Model::request_conversion(
                 db.get_ref(),
@@ -45,18 +68,18 @@ pub async fn browse(
                 "thumbnail".to_string(),
             ).await {
                 Ok(true) => {
-                    return HttpResponse::Accepted().body("Thumbnail generation queued. Please refresh in a moment.");
+                    HttpResponse::Accepted().body("Thumbnail generation queued. Please refresh in a moment.")
                 }
                 Ok(false) => {
-                    return HttpResponse::Accepted().body("Thumbnail generation already in progress. Please check back in a moment.");
+                    HttpResponse::Accepted().body("Thumbnail generation already in progress. Please check back in a moment.")
                 }
                 Err(e) => {
                     eprintln!("Error creating thumbnail conversion: {}", e);
-                    return HttpResponse::InternalServerError().body("Failed to queue thumbnail generation");
+                    HttpResponse::InternalServerError().body("Failed to queue thumbnail generation")
                 }
             }
         } else {
-            return HttpResponse::NotFound().body("Original file not found for thumbnail generation");
+            HttpResponse::NotFound().body("Original file not found for thumbnail generation")
         }
     } else if target.is_file() {
         // Serve file for download
@@ -66,117 +89,212 @@ pub async fn browse(
     } else {
         // Use helper to render directory contents
         let template = include_str!("../views/files/index.html");
-        let html = generate_files_list_html(&target, subpath, &session);
+        let html = generate_files_list_html(&libraries, &resolved, &user);
         let response_html = template.replace("{{contents}}", &html);
         HttpResponse::Ok().content_type("text/html").body(response_html)
     }
 }
 
-// Handle folder creation
+// Handle folder creation inside the folder being viewed
 pub async fn create_folder(
-    folder: web::Data<PathBuf>,
+    libraries: web::Data<Libraries>,
     form: web::Form<std::collections::HashMap<String, String>>,
-    session: Session,
+    user: CurrentUser,
 ) -> Result<HttpResponse, ActixError> {
-    if !is_logged_in(&session) {
-        return Ok(HttpResponse::Unauthorized().body("Login required"));
+    let parent = match libraries.resolve(form.get("folder").map(|s| s.as_str()).unwrap_or("")) {
+        Ok(parent) => parent,
+        Err(e) => return Ok(e.response()),
+    };
+    if let Err(resp) = user.require_at(Permission::CreateFolder, &parent.relative) {
+        return Ok(resp);
     }
     let folder_name = form.get("folder_name").map(|s| s.trim()).filter(|s| !s.is_empty());
     let folder_name = match folder_name {
         Some(name) => name,
         None => return Ok(HttpResponse::BadRequest().body("Invalid folder name")),
     };
-    // Basic validation: no path traversal, only allow simple names
-    if folder_name.contains('/') || folder_name.contains('\\') || folder_name.contains("..") {
-        return Ok(HttpResponse::BadRequest().body("Invalid folder name"));
-    }
-    let mut target = folder.get_ref().clone();
-    target = target.join(folder_name);
+    let target = match libraries.resolve_child(&parent.relative, folder_name) {
+        Ok(resolved) => resolved.absolute,
+        Err(e) => return Ok(e.response()),
+    };
     if target.exists() {
         return Ok(HttpResponse::BadRequest().body("Folder already exists"));
     }
     match std::fs::create_dir(&target) {
-        Ok(_) => Ok(HttpResponse::Found().append_header(("Location", "/")).finish()),
+        Ok(_) => Ok(HttpResponse::Found().append_header(("Location", parent.url())).finish()),
         Err(e) => Ok(HttpResponse::InternalServerError().body(format!("Error creating folder: {}", e))),
     }
 }
 
-// Handle file uploads
+// Handle file uploads into the folder being viewed
 pub async fn upload(
-    folder: web::Data<PathBuf>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
     mut payload: Multipart,
-    session: Session,
+    user: CurrentUser,
 ) -> Result<HttpResponse, ActixError> {
-    if !is_logged_in(&session) {
-        let template = include_str!("../views/files/upload.html");
-        let response_html = template.replace("{{contents}}", "<a class='btn btn-primary mt-3' href='/login'>Login</a>");
-        return Ok(HttpResponse::Ok().content_type("text/html").body(response_html));
+    if let Err(resp) = user.require(Permission::Upload) {
+        return Ok(resp);
     }
 
+    // The form puts `folder` and `conflict` before the files, so they're known by the time files arrive.
+    // Scripts can send a `checksum` field right before a file to have it verified.
+    let mut folder = String::new();
+    let mut conflict = ConflictPolicy::Skip;
+    let mut checksum = None;
     let mut results = Vec::new();
-    let target_dir = folder.get_ref();
 
     while let Some(item) = payload.next().await {
         let mut field = item?;
 
         // Extract filename into an owned String
-        let filename: String = match field.content_disposition()
-            .and_then(|cd| cd.get_filename().map(|f| f.to_string()))
-        {
-            Some(fname) => fname,
-            None => {
-                results.push(("<unknown>".to_string(), "No filename".to_string()));
-                continue;
+        let filename = field.content_disposition().and_then(|cd| cd.get_filename().map(|f| f.to_string()));
+        let Some(filename) = filename else {
+            let value = read_text_field(&mut field).await?;
+            match field.name() {
+                Some("folder") => folder = value,
+                Some("conflict") => conflict = ConflictPolicy::from_str_case_insensitive(&value).unwrap_or(ConflictPolicy::Skip),
+                Some("checksum") if !value.is_empty() => checksum = Some(ExpectedChecksum::parse(&value)),
+                _ => {}
             }
+            continue;
         };
-
-        let filepath = target_dir.join(&filename);
-        if filepath.exists() {
-            results.push((filename.clone(), "File exists, skipped".to_string()));
+        // A file input left empty still sends a field.
+        if filename.is_empty() {
             continue;
         }
-
-        let mut f = match std::fs::File::create(&filepath) {
-            Ok(file) => file,
+        let expected = match checksum.take().transpose() {
+            Ok(expected) => expected,
             Err(e) => {
-                results.push((filename.clone(), format!("Error creating '{}': {}", filepath.display(), e)));
+                results.push((filename, e));
                 continue;
             }
         };
 
-        // Now safely consume the stream
-        while let Some(chunk) = field.next().await {
-            let data = chunk?;
-            use std::io::Write;
-            if let Err(e) = f.write_all(&data) {
-                results.push((filename.clone(), format!("Write error to '{}': {}", filepath.display(), e)));
-                break;
+        let dir = match libraries.resolve(&folder) {
+            Ok(dir) => dir,
+            Err(e) => {
+                results.push((filename, e.to_string()));
+                continue;
+            }
+        };
+        // Folder uploads send names like `card1/DCIM/clip.mp4`.
+        let target = match upload_target(&libraries, &dir, &filename, &user) {
+            Ok(target) => target,
+            Err(e) => {
+                results.push((filename, e));
+                continue;
             }
+        };
+        let existed = target.absolute.exists();
+        if existed && conflict == ConflictPolicy::Skip {
+            results.push((filename, "File exists, skipped".to_string()));
+            continue;
         }
 
-        results.push((filename.clone(), "Uploaded".to_string()));
+        match save_upload(&db, &libraries, &mut field, target.clone(), conflict, None, expected.as_ref()).await {
+            Ok((placed, _)) if placed.relative != target.relative => {
+                results.push((filename, format!("Uploaded as {}", placed.file_name())));
+            }
+            Ok(_) if existed => results.push((filename, "Replaced, previous version kept".to_string())),
+            Ok(_) => results.push((filename, "Uploaded".to_string())),
+            Err(e) => results.push((filename, e)),
+        }
     }
 
+    let back = libraries.resolve(&folder).map(|dir| dir.url()).unwrap_or_else(|_| "/".to_string());
     let mut html = String::new();
     html += "<div class='card'><div class='card-header'>Upload Results</div><ul class='list-group list-group-flush'>";
     for (file, status) in results {
-        html += &format!("<li class='list-group-item'>{}: {}</li>", file, status);
+        html += &format!("<li class='list-group-item'>{}: {}</li>", escape(&file), escape(&status));
     }
-    html += "</ul></div><a class='btn btn-primary mt-3' href='/'>Back</a>";
+    html += &format!("</ul></div><a class='btn btn-primary mt-3' href='{}'>Back</a>", escape(&back));
     let template = include_str!("../views/files/upload.html");
     let response_html = template.replace("{{contents}}", &html);
 
     Ok(HttpResponse::Ok().content_type("text/html").body(response_html))
 }
 
+/// Read a small text field of a multipart form; anything past 1 KB is cut off.
+pub async fn read_text_field(field: &mut Field) -> Result<String, ActixError> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk?;
+        if bytes.len() < 1024 {
+            bytes.extend_from_slice(&chunk);
+        }
+    }
+    bytes.truncate(1024);
+    Ok(String::from_utf8_lossy(&bytes).trim().to_string())
+}
+
+/// Receive one multipart file field into a staging file next to `target`, then move it
+/// into place according to `conflict`. Returns where it ended up and its hashes, which are
+/// also recorded. Nothing shows up at the target until the whole file has arrived, and
+/// nothing at all if it doesn't match the `expected` checksum.
+pub async fn save_upload(
+    db: &DatabaseConnection,
+    libraries: &Libraries,
+    field: &mut Field,
+    target: ServedPath,
+    conflict: ConflictPolicy,
+    max_bytes: Option<u64>,
+    expected: Option<&ExpectedChecksum>,
+) -> Result<(ServedPath, Digest), String> {
+    let staging = create_staging_file(libraries, target.parent_relative())
+        .map_err(|e| format!("Error creating '{}': {}", target.url(), e))?;
+    let result = match write_field(field, &staging.absolute, max_bytes).await {
+        Ok(digest) => match expected.map_or(Ok(()), |expected| expected.check(&digest)) {
+            Ok(()) => match place_file(libraries, &staging.absolute, target, conflict) {
+                Ok(placed) => Ok((placed, digest)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err("File exists, skipped".to_string()),
+                Err(e) => Err(format!("Error saving upload: {}", e)),
+            },
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    remove_staging_file(&staging);
+    if let Ok((placed, digest)) = &result
+        && let Err(err) = file_checksum::Model::record(db, &placed.relative, digest, now()).await
+    {
+        eprintln!("Error recording checksum of {}: {}", placed.relative, err);
+    }
+    result
+}
+
+/// Stream one multipart file field into the existing file at `path`, hashing it on the way.
+/// Stops once more than `max_bytes` arrive, or on any write or connection error.
+pub async fn write_field(field: &mut Field, path: &Path, max_bytes: Option<u64>) -> Result<Digest, String> {
+    use std::io::Write;
+
+    let mut f = match fs::OpenOptions::new().write(true).truncate(true).open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Error opening '{}': {}", path.display(), e)),
+    };
+    let mut hasher = Hasher::default();
+    while let Some(chunk) = field.next().await {
+        let data = chunk.map_err(|e| format!("Upload interrupted: {}", e))?;
+        if max_bytes.is_some_and(|max| hasher.size() + data.len() as u64 > max) {
+            return Err("File is larger than the space left".to_string());
+        }
+        f.write_all(&data).map_err(|e| format!("Write error to '{}': {}", path.display(), e))?;
+        hasher.update(&data);
+    }
+    Ok(hasher.finish())
+}
+
 // Helper function to generate files list HTML
-pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Session) -> String {
+pub fn generate_files_list_html(libraries: &Libraries, dir: &ServedPath, user: &CurrentUser) -> String {
+    let target = &dir.absolute;
+    let subpath = dir.relative.as_str();
     let mut html = String::new();
     let video_extensions = ["mp4", "avi", "mov", "mkv", "webm"];
 
     // Check for internal folders
     let mut has_thumbs = false;
     let mut has_segments = false;
+    let mut has_proxies = false;
     if let Ok(entries) = fs::read_dir(target) {
         for entry in entries.flatten() {
             let file_name = entry.file_name().to_string_lossy().to_string();
@@ -185,6 +303,8 @@ pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Sessi
                     has_thumbs = true;
                 } else if file_name == "segments" {
                     has_segments = true;
+                } else if file_name == PROXIES_DIR {
+                    has_proxies = true;
                 }
             }
         }
@@ -208,12 +328,12 @@ pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Sessi
 
             if i == parts.len() - 1 {
                 // Last part = current folder (active)
-                breadcrumb.push_str(&format!("<li class='breadcrumb-item active' aria-current='page'>{}</li>", part));
+                breadcrumb.push_str(&format!("<li class='breadcrumb-item active' aria-current='page'>{}</li>", escape(part)));
             } else {
                 // Intermediate part = link
                 breadcrumb.push_str(&format!(
                     "<li class='breadcrumb-item'><a href='/{}'>{}</a></li>",
-                    accumulated, part
+                    url_path(&accumulated), escape(part)
                 ));
             }
         }
@@ -228,7 +348,7 @@ pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Sessi
         } else {
             format!("/{}/thumbs", subpath)
         };
-        internal_links += &format!("<a href='{}' class='badge bg-secondary ms-2' style='font-size:0.5em;text-decoration:none;'>Thumbs</a>", thumbs_link);
+        internal_links += &format!("<a href='{}' class='badge bg-secondary ms-2' style='font-size:0.5em;text-decoration:none;'>Thumbs</a>", url_path(&thumbs_link));
     }
     if has_segments {
         let segments_link = if subpath.is_empty() {
@@ -236,7 +356,15 @@ pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Sessi
         } else {
             format!("/{}/segments", subpath)
         };
-        internal_links += &format!("<a href='{}' class='badge bg-secondary ms-2' style='font-size:0.5em;text-decoration:none;'>Segments</a>", segments_link);
+        internal_links += &format!("<a href='{}' class='badge bg-secondary ms-2' style='font-size:0.5em;text-decoration:none;'>Segments</a>", url_path(&segments_link));
+    }
+    if has_proxies {
+        let proxies_link = if subpath.is_empty() {
+            format!("/{}", PROXIES_DIR)
+        } else {
+            format!("/{}/{}", subpath, PROXIES_DIR)
+        };
+        internal_links += &format!("<a href='{}' class='badge bg-secondary ms-2' style='font-size:0.5em;text-decoration:none;'>Proxies</a>", url_path(&proxies_link));
     }
     if !internal_links.is_empty() {
         breadcrumb.push_str(&format!("<span>{}</span>", internal_links));
@@ -247,9 +375,17 @@ pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Sessi
     html += &format!("<div class='card'><div class='card-header'>{}</div><ul class='list-group list-group-flush'>", breadcrumb);
 
     let mut video_files = Vec::new();
-    let mut dir_entries: Vec<(String, String)> = Vec::new(); // (link, file_name)
+    let mut dir_entries: Vec<(String, String, bool)> = Vec::new(); // (link, file_name, is_library)
     let mut file_entries: Vec<(String, String, bool)> = Vec::new(); // (link, file_name, is_video)
 
+    // Named libraries show up as folders at the top level.
+    let at_top = subpath.is_empty();
+    if at_top {
+        for library in libraries.named.iter().filter(|library| user.can_see(&library.name)) {
+            dir_entries.push((format!("/{}", library.name), format!("{}/", library.name), true));
+        }
+    }
+
     match fs::read_dir(target) {
         Ok(entries) => {
             let mut all_entries: Vec<_> = entries.flatten().collect();
@@ -262,7 +398,11 @@ pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Sessi
             for entry in all_entries {
                 let file_name = entry.file_name().to_string_lossy().to_string();
                 // Skip internal folders from main list
-                if file_name == "thumbs" || file_name == "segments" {
+                if file_name == "thumbs" || file_name == "segments" || file_name == PROXIES_DIR || file_name == STAGING_DIR || file_name == VERSIONS_DIR || file_name == TRASH_DIR {
+                    continue;
+                }
+                // Shadowed by a library of the same name.
+                if at_top && libraries.named.iter().any(|library| library.name == file_name) {
                     continue;
                 }
                 let link = if subpath.is_empty() {
@@ -271,9 +411,20 @@ pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Sessi
                     format!("/{}/{}", subpath, file_name)
                 };
 
-                if entry.path().is_dir() {
+                // Hide what the user's folder grants don't reach.
+                let is_dir = entry.path().is_dir();
+                let visible = if is_dir {
+                    user.can_see(&link[1..])
+                } else {
+                    user.can_at(Permission::View, &link[1..])
+                };
+                if !visible {
+                    continue;
+                }
+
+                if is_dir {
                     let display_name = format!("{}/", file_name);
-                    dir_entries.push((link.clone(), display_name));
+                    dir_entries.push((link.clone(), display_name, false));
                 } else {
                     let is_video = entry
                         .path()
@@ -299,10 +450,13 @@ pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Sessi
         if *is_video {
             video_files.push(file_name.clone());
         }
-        html += &crate::models::file::File::file_preview(link, file_name, *is_video);
+        let manage = user.can_at(Permission::ManageFiles, &link[1..]);
+        html += &crate::models::file::File::file_preview(link, file_name, *is_video, manage, true);
     }
-    for (link, file_name) in dir_entries {
-        html += &crate::models::file::File::file_preview(&link, &file_name, false);
+    for (link, file_name, is_library) in dir_entries {
+        let manage = !is_library && user.can_at(Permission::ManageFiles, &link[1..]);
+        let select = user.can_at(Permission::View, &link[1..]);
+        html += &crate::models::file::File::file_preview(&link, &file_name, false, manage, select);
     }
 
     html += "</ul></div>";
@@ -315,36 +469,137 @@ pub fn generate_files_list_html(target: &PathBuf, subpath: &str, session: &Sessi
         html += "</div></div></div>";
     }
 
-    if !is_logged_in(session) {
-        html += r#"<a class='btn btn-primary mt-3' href="/login">Login</a>"#;
-    }
-
-    if is_logged_in(session) {
-        html += ACTIONS_HTML;
-    }
+    html += &actions_html(user, subpath);
 
     html
 }
 
+// Only offer the actions the user's role and folder grants allow in this folder.
+fn actions_html(user: &CurrentUser, subpath: &str) -> String {
+    let can_upload = user.can_at(Permission::Upload, subpath);
+    let can_create_folder = user.can_at(Permission::CreateFolder, subpath);
+    let mut html = String::new();
+    if can_upload || can_create_folder {
+        html += "<div class=\"actions py-4\">";
+        if can_upload {
+            html += UPLOAD_BUTTON_HTML;
+        }
+        if can_create_folder {
+            html += FOLDER_BUTTON_HTML;
+        }
+        html += "</div>";
+        if can_upload {
+            let conflict_options: String = ConflictPolicy::ALL
+                .iter()
+                .map(|policy| format!("<option value='{0}'>{0}</option>", policy.as_str()))
+                .collect();
+            html += &UPLOAD_FORM_HTML
+                .replace("{{folder}}", &escape(subpath))
+                .replace("{{conflict_options}}", &conflict_options);
+        }
+        if can_create_folder {
+            html += &FOLDER_FORM_HTML.replace("{{folder}}", &escape(subpath));
+        }
+    }
+    if user.can(Permission::ManageUsers) {
+        html += USERS_LINK_HTML;
+        html += &SHARING_LINK_HTML.replace("{path}", &urlencoding::encode(subpath));
+        html += &DROPBOXES_LINK_HTML.replace("{path}", &urlencoding::encode(subpath));
+        html += &CHECKSUMS_LINK_HTML.replace("{path}", &urlencoding::encode(subpath));
+        html += MEDIA_LINK_HTML;
+        html += CONVERSIONS_LINK_HTML;
+    }
+    if user.can_at(Permission::Share, subpath) {
+        html += &SHARE_FOLDER_LINK_HTML.replace("{path}", &urlencoding::encode(subpath));
+    }
+    if user.can(Permission::ManageFiles) {
+        html += TRASH_LINK_HTML;
+    }
+    if user.can_at(Permission::View, subpath) {
+        html += &ARCHIVE_FORM_HTML.replace("{folder}", &escape(subpath));
+        html += &MHL_LINK_HTML.replace("{path}", &urlencoding::encode(subpath));
+    }
+    if user.is_session() {
+        html += TOKENS_LINK_HTML;
+    }
+    html += &LOGOUT_HTML.replace("{username}", &escape(&user.user.username));
+    html
+}
 
-const ACTIONS_HTML: &str = r#"
-<div class="actions py-4">
+const UPLOAD_BUTTON_HTML: &str = r#"
     <button class='btn btn-success mt-2' type='button' data-bs-toggle='collapse' data-bs-target='#uploadForm' aria-expanded='false' aria-controls='uploadForm'>Upload Files</button>
+"#;
+const FOLDER_BUTTON_HTML: &str = r#"
     <button class='btn btn-secondary mt-2' type='button' data-bs-toggle='collapse' data-bs-target='#folderForm' aria-expanded='false' aria-controls='folderForm'>New Folder</button>
-</div>
+"#;
+const UPLOAD_FORM_HTML: &str = concat!(r#"
 <div class='collapse my-4' id='uploadForm'>
-    <form action='/upload' method='post' enctype='multipart/form-data' class='mb-2'>
+    <form action='/upload' method='post' enctype='multipart/form-data' class='mb-2' id='uploadFormElement'>
+        <input type='hidden' name='folder' value='{{folder}}'>
+        <div class='d-flex gap-2 align-items-center mb-2'>
+            <label for='uploadConflict' class='text-nowrap'>If a file exists</label>
+            <select name='conflict' id='uploadConflict' class='form-select w-auto'>{{conflict_options}}</select>
+        </div>
+        <label class='form-label small text-muted mb-0'>Files</label>
         <input type='file' name='files' multiple class='form-control mb-2'>
+        <label class='form-label small text-muted mb-0'>Or a whole folder</label>
+        <input type='file' name='files' webkitdirectory class='form-control mb-2'>
         <button type='submit' class='btn btn-success'>Upload</button>
     </form>
+    <ul class='list-group' id='uploadStatus'></ul>
 </div>
+<script>
+"#, include_str!("../views/files/resumable_upload.js"), r#"
+</script>
+"#);
+const FOLDER_FORM_HTML: &str = r#"
 <div class='collapse my-4' id='folderForm'>
     <form action='/create_folder' method='post' class='mb-2'>
+        <input type='hidden' name='folder' value='{{folder}}'>
         <input type='text' name='folder_name' placeholder='New folder name' required class='form-control mb-2'>
         <button type='submit' class='btn btn-secondary'>Create Folder</button>
     </form>
 </div>
-<form action='/logout' method='post' class='mt-4'><button type='submit' class='btn btn-outline-danger'>Logout</button></form>
+"#;
+const USERS_LINK_HTML: &str = r#"
+<a href='/admin/users' class='btn btn-outline-secondary mt-4'>Manage Users</a>
+"#;
+const SHARING_LINK_HTML: &str = r#"
+<a href='/admin/sharing?path={path}' class='btn btn-outline-secondary mt-4'>Sharing</a>
+"#;
+const DROPBOXES_LINK_HTML: &str = r#"
+<a href='/admin/dropboxes?path={path}' class='btn btn-outline-secondary mt-4'>Drop-boxes</a>
+"#;
+const CHECKSUMS_LINK_HTML: &str = r#"
+<a href='/admin/checksums?path={path}' class='btn btn-outline-secondary mt-4'>Checksums</a>
+"#;
+const MEDIA_LINK_HTML: &str = r#"
+<a href='/admin/media' class='btn btn-outline-secondary mt-4'>Media</a>
+"#;
+const CONVERSIONS_LINK_HTML: &str = r#"
+<a href='/admin/conversions' class='btn btn-outline-secondary mt-4'>Conversions</a>
+"#;
+const SHARE_FOLDER_LINK_HTML: &str = r#"
+<a href='/shares?path={path}' class='btn btn-outline-secondary mt-4'>Share Links</a>
+"#;
+const TRASH_LINK_HTML: &str = r#"
+<a href='/trash' class='btn btn-outline-secondary mt-4'>Trash</a>
+"#;
+const ARCHIVE_FORM_HTML: &str = r#"
+<form action='/archive' method='get' id='archiveForm' class='d-inline-flex gap-2 align-items-center mt-4' title='Downloads the ticked entries, or the whole folder if none are ticked'>
+    <input type='hidden' name='folder' value='{folder}'>
+    <select name='format' class='form-select w-auto'><option value='zip'>zip</option><option value='tar'>tar</option></select>
+    <button type='submit' class='btn btn-outline-secondary'>Download</button>
+</form>
+"#;
+const MHL_LINK_HTML: &str = r#"
+<a href='/checksums/mhl?path={path}' class='btn btn-outline-secondary mt-4'>MHL Manifest</a>
+"#;
+const TOKENS_LINK_HTML: &str = r#"
+<a href='/tokens' class='btn btn-outline-secondary mt-4'>API Tokens</a>
+"#;
+const LOGOUT_HTML: &str = r#"
+<form action='/logout' method='post' class='mt-4'><button type='submit' class='btn btn-outline-danger'>Logout {username}</button></form>
 <script src='https://cdn.jsdelivr.net/npm/bootstrap@5.3.0/dist/js/bootstrap.bundle.min.js'></script>
 "#;
 
@@ -352,13 +607,13 @@ pub fn files_routes(cfg: &mut web::ServiceConfig) {
     cfg
         .route("/upload", web::post().to(upload))
         .route("/create_folder", web::post().to(create_folder))
-        .route("/", web::get().to(|db: web::Data<DatabaseConnection>, folder: web::Data<PathBuf>, req: HttpRequest, session: Session| {
-            browse(db, folder, req, None, session)
+        .route("/", web::get().to(|db: web::Data<DatabaseConnection>, libraries: web::Data<Libraries>, req: HttpRequest, user: CurrentUser| {
+            browse(db, libraries, req, None, user)
         }))
         .route(
             "/{path:.*}",
-            web::get().to(|db: web::Data<DatabaseConnection>, folder: web::Data<PathBuf>, req: HttpRequest, path: web::Path<String>, session: Session| {
-                browse(db, folder, req, Some(path), session)
+            web::get().to(|db: web::Data<DatabaseConnection>, libraries: web::Data<Libraries>, req: HttpRequest, path: web::Path<String>, user: CurrentUser| {
+                browse(db, libraries, req, Some(path), user)
             }),
         );
 }
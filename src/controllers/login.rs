@@ -1,8 +1,11 @@
-use actix_web::{web, HttpResponse, Error};
+use actix_web::{web, HttpRequest, HttpResponse, Error};
+use actix_web::http::StatusCode;
 use actix_session::Session;
 use sea_orm::{EntityTrait, QueryFilter, ColumnTrait, DatabaseConnection};
 use serde::Deserialize;
-use crate::models::user;
+use crate::models::{login_attempt, user};
+use crate::models::login_attempt::AttemptKind;
+use crate::utils::time::now;
 use bcrypt::verify;
 
 #[derive(Deserialize)]
@@ -11,13 +14,29 @@ pub struct LoginForm {
     pub password: String,
 }
 
+const LOGIN_TEMPLATE: &str = include_str!("../views/login/login_form.html");
+
 // Serve login form (GET)
 pub async fn login_form() -> HttpResponse {
-    let template = include_str!("../views/login/login_form.html");
-    let html = template.replace("{{error}}", "");
+    let html = LOGIN_TEMPLATE.replace("{{error}}", "");
     HttpResponse::Ok().content_type("text/html").body(html)
 }
 
+// The login form again, with a message in the {{error}} slot.
+fn login_error(status: StatusCode, level: &str, message: &str) -> HttpResponse {
+    let html = LOGIN_TEMPLATE.replace("{{error}}", &format!("<div class='alert alert-{}'>{}</div>", level, message));
+    HttpResponse::build(status).content_type("text/html").body(html)
+}
+
+pub fn describe_wait(seconds: i64) -> String {
+    let (count, unit) = if seconds >= 60 {
+        ((seconds + 59) / 60, "minute")
+    } else {
+        (seconds.max(1), "second")
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
 
 // Helper: verify password
 pub fn verify_password(hash: &str, password: &str) -> bool {
@@ -28,25 +47,51 @@ pub fn verify_password(hash: &str, password: &str) -> bool {
 pub async fn login(
     db: web::Data<DatabaseConnection>,
     session: Session,
+    req: HttpRequest,
     form: web::Form<LoginForm>,
 ) -> Result<HttpResponse, Error> {
     let username = form.username.clone();
     let password = form.password.clone();
+    let ip = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+
+    // Don't even check the password while this username or IP is backing off.
+    let wait = login_attempt::Model::seconds_until_allowed(db.get_ref(), AttemptKind::Login, &username, &ip, now())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if wait > 0 {
+        let message = format!("Too many failed attempts. Try again in {}.", describe_wait(wait));
+        return Ok(login_error(StatusCode::TOO_MANY_REQUESTS, "danger", &message));
+    }
 
     let user = user::Entity::find()
         .filter(user::Column::Username.eq(username.clone()))
         .one(db.get_ref())
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let verified = user.filter(|u| verify_password(&u.password_hash, &password));
+    let now = now();
+    if let Err(e) = login_attempt::Model::record(db.get_ref(), AttemptKind::Login, &username, &ip, verified.is_some(), now).await {
+        eprintln!("Failed to record login attempt: {}", e);
+    }
 
-    if let Some(u) = user {
-        if verify_password(&u.password_hash, &password) {
-            session.insert("user_id", u.id)?;
-            return Ok(HttpResponse::Found().append_header(("Location", "/")).finish());
+    if let Some(u) = verified {
+        if u.is_pending() {
+            return Ok(login_error(StatusCode::FORBIDDEN, "warning", "Your account is waiting for an admin to approve it."));
         }
+        session.renew();
+        session.insert("user_id", u.id)?;
+        return Ok(HttpResponse::Found().append_header(("Location", "/")).finish());
     }
 
-    Ok(HttpResponse::Unauthorized().body("Invalid credentials"))
+    let wait = login_attempt::Model::seconds_until_allowed(db.get_ref(), AttemptKind::Login, &username, &ip, now)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if wait >= login_attempt::WINDOW_SECS {
+        let message = format!("Invalid username or password. Too many failed attempts; login is locked for {}.", describe_wait(wait));
+        return Ok(login_error(StatusCode::TOO_MANY_REQUESTS, "danger", &message));
+    }
+    Ok(login_error(StatusCode::UNAUTHORIZED, "danger", "Invalid username or password."))
 }
 
 // Logout handler
@@ -55,11 +100,6 @@ pub async fn logout(session: Session) -> Result<HttpResponse, Error> {
     Ok(HttpResponse::Found().append_header(("Location", "/")).finish())
 }
 
-// Check if logged in
-pub fn is_logged_in(session: &Session) -> bool {
-    session.get::<i32>("user_id").unwrap_or(None).is_some()
-}
-
 pub fn login_routes(cfg: &mut web::ServiceConfig) {
     cfg
         .route("/login", web::get().to(login_form))
@@ -67,4 +107,20 @@ pub fn login_routes(cfg: &mut web::ServiceConfig) {
         .route("/logout", web::post().to(|session: Session| async move {
             logout(session).await
         }));
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_wait_uses_singular_for_one() {
+        assert_eq!(describe_wait(0), "1 second");
+        assert_eq!(describe_wait(1), "1 second");
+        assert_eq!(describe_wait(2), "2 seconds");
+        assert_eq!(describe_wait(59), "59 seconds");
+        assert_eq!(describe_wait(60), "1 minute");
+        assert_eq!(describe_wait(61), "2 minutes");
+        assert_eq!(describe_wait(15 * 60), "15 minutes");
+    }
+}
@@ -0,0 +1,207 @@
+use actix_web::{get, post, web, HttpResponse};
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::file_ops::{copy_path, is_mount, move_path, trash_path};
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+use crate::utils::paths::ServedPath;
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+
+fn resolve_source(libraries: &Libraries, path: &str, user: &CurrentUser) -> Result<ServedPath, HttpResponse> {
+    let source = libraries.resolve(path.trim()).map_err(|e| e.response())?;
+    user.require_at(Permission::ManageFiles, &source.relative)?;
+    if is_mount(libraries, &source.relative) {
+        return Err(HttpResponse::BadRequest().body("Libraries and the root folder can't be changed here"));
+    }
+    Ok(source)
+}
+
+/// `name` inside the folder `folder`, where the user must be allowed to manage files too.
+fn resolve_destination(libraries: &Libraries, folder: &str, name: &str, user: &CurrentUser) -> Result<ServedPath, HttpResponse> {
+    let folder = libraries.resolve(folder.trim()).map_err(|e| e.response())?;
+    user.require_at(Permission::ManageFiles, &folder.relative)?;
+    if !folder.absolute.is_dir() {
+        return Err(HttpResponse::BadRequest().body("The destination folder does not exist"));
+    }
+    libraries.resolve_child(&folder.relative, name).map_err(|e| e.response())
+}
+
+// Back to the folder the result is in.
+fn done(destination: &ServedPath) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .append_header(("Location", format!("/{}", destination.parent_relative())))
+        .finish()
+}
+
+#[derive(Deserialize)]
+pub struct ManageQuery {
+    pub path: String,
+}
+
+#[get("/manage")]
+pub async fn show(
+    query: web::Query<ManageQuery>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let source = match resolve_source(&libraries, &query.path, &user) {
+        Ok(source) => source,
+        Err(resp) => return resp,
+    };
+    if !source.absolute.exists() {
+        return HttpResponse::NotFound().body("Not found");
+    }
+    let kind = if source.absolute.is_dir() { "folder" } else { "file" };
+    let html = format!(
+        "<p><a href='/{parent}'>/{parent}</a> · <b>{name}</b></p>\
+        <div class='card mb-4'><div class='card-header'>Rename</div><div class='card-body'>\
+        <form action='/manage/rename' method='post' class='d-flex flex-wrap gap-2'>\
+        <input type='hidden' name='path' value='{path}'>\
+        <input type='text' name='name' value='{name}' class='form-control form-control-sm w-auto' required>\
+        <button type='submit' class='btn btn-primary btn-sm'>Rename</button></form></div></div>\
+        <div class='card mb-4'><div class='card-header'>Move or copy</div><div class='card-body'>\
+        <form method='post' class='d-flex flex-wrap gap-2 align-items-center'>\
+        <input type='hidden' name='path' value='{path}'>\
+        <span>to /</span><input type='text' name='folder' value='{parent}' placeholder='folder' class='form-control form-control-sm w-auto'>\
+        <input type='text' name='name' value='{name}' class='form-control form-control-sm w-auto' required>\
+        <button type='submit' formaction='/manage/move' class='btn btn-primary btn-sm'>Move</button>\
+        <button type='submit' formaction='/manage/copy' class='btn btn-outline-primary btn-sm'>Copy</button></form>\
+        <small class='text-muted'>Clips, points, tags and thumbnails go along with the {kind}.</small></div></div>\
+        <div class='card mb-4'><div class='card-header'>Delete</div><div class='card-body'>\
+        <form action='/manage/delete' method='post' data-name='{name}' onsubmit=\"return confirm('Move ' + this.dataset.name + ' to the trash?')\">\
+        <input type='hidden' name='path' value='{path}'>\
+        <button type='submit' class='btn btn-danger btn-sm'>Move {kind} to trash</button></form>\
+        <small class='text-muted'>It can be restored from the <a href='/trash'>trash</a> until it's purged.</small></div></div>",
+        parent = escape(source.parent_relative()),
+        name = escape(source.file_name()),
+        path = escape(&source.relative),
+        kind = kind,
+    );
+    let page = ADMIN_HTML.replace("{{title}}", "Manage").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[derive(Deserialize)]
+pub struct RenameForm {
+    pub path: String,
+    pub name: String,
+}
+
+#[post("/manage/rename")]
+pub async fn rename(
+    form: web::Form<RenameForm>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let source = match resolve_source(&libraries, &form.path, &user) {
+        Ok(source) => source,
+        Err(resp) => return resp,
+    };
+    let destination = match libraries.resolve_child(source.parent_relative(), &form.name) {
+        Ok(destination) => destination,
+        Err(e) => return e.response(),
+    };
+    match move_path(&db, &libraries, &source, &destination).await {
+        Ok(()) => done(&destination),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TransferForm {
+    pub path: String,
+    #[serde(default)]
+    pub folder: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+impl TransferForm {
+    // The name is optional; without one the file keeps its own.
+    fn name<'a>(&'a self, source: &'a ServedPath) -> &'a str {
+        match self.name.trim() {
+            "" => source.file_name(),
+            name => name,
+        }
+    }
+}
+
+#[post("/manage/move")]
+pub async fn move_to(
+    form: web::Form<TransferForm>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let source = match resolve_source(&libraries, &form.path, &user) {
+        Ok(source) => source,
+        Err(resp) => return resp,
+    };
+    let destination = match resolve_destination(&libraries, &form.folder, form.name(&source), &user) {
+        Ok(destination) => destination,
+        Err(resp) => return resp,
+    };
+    match move_path(&db, &libraries, &source, &destination).await {
+        Ok(()) => done(&destination),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+#[post("/manage/copy")]
+pub async fn copy(
+    form: web::Form<TransferForm>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    // Copying only reads the original.
+    let source = match libraries.resolve(form.path.trim()) {
+        Ok(source) => source,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::View, &source.relative) {
+        return resp;
+    }
+    let destination = match resolve_destination(&libraries, &form.folder, form.name(&source), &user) {
+        Ok(destination) => destination,
+        Err(resp) => return resp,
+    };
+    match copy_path(&db, &libraries, &source, &destination).await {
+        Ok(()) => done(&destination),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DeleteForm {
+    pub path: String,
+}
+
+#[post("/manage/delete")]
+pub async fn delete(
+    form: web::Form<DeleteForm>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let target = match resolve_source(&libraries, &form.path, &user) {
+        Ok(target) => target,
+        Err(resp) => return resp,
+    };
+    match trash_path(&db, &libraries, &target, user.user.id).await {
+        Ok(_) => done(&target),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+pub fn manage_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(show);
+    cfg.service(rename);
+    cfg.service(move_to);
+    cfg.service(copy);
+    cfg.service(delete);
+}
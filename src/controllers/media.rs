@@ -0,0 +1,108 @@
+use actix_web::{get, post, web, HttpResponse};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder};
+use crate::models::{clip, media, point, tag};
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+use crate::utils::media_index::rescan as rescan_media;
+use crate::utils::time::iso8601;
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+
+fn internal_error(context: &str, err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}: {}", context, err);
+    HttpResponse::InternalServerError().body("Internal server error")
+}
+
+#[get("/admin/media")]
+pub async fn index(
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    render(db.get_ref(), "").await
+}
+
+async fn render(db: &DatabaseConnection, notice: &str) -> HttpResponse {
+    let total = media::Entity::find().count(db).await;
+    let missing = media::Entity::find()
+        .filter(media::Column::MissingSince.is_not_null())
+        .order_by_asc(media::Column::Path)
+        .all(db)
+        .await;
+    let (total, missing) = match (total, missing) {
+        (Ok(total), Ok(missing)) => (total, missing),
+        (Err(err), _) | (_, Err(err)) => return internal_error("Error fetching media", err),
+    };
+
+    let mut html = String::from(notice);
+    html += &format!(
+        "<div class='card mb-4'><div class='card-header'>Rescan</div><div class='card-body'>\
+        <form action='/admin/media/rescan' method='post'><button type='submit' class='btn btn-success btn-sm'>Rescan now</button></form>\
+        <small class='text-muted'>{} files are tracked. The libraries are watched for changes and rescanned every hour; \
+        rescanning now catches up on anything missed. Files moved or renamed outside the app are found again by size and \
+        a hash of their first and last megabyte, and their clips, points, tags and thumbnails move along.</small></div></div>",
+        total
+    );
+
+    html += "<div class='card mb-4'><div class='card-header'>Missing files</div><ul class='list-group list-group-flush'>";
+    if missing.is_empty() {
+        html += "<li class='list-group-item text-muted'>No missing files.</li>";
+    }
+    for media in missing {
+        let clips = clip::Entity::find().filter(clip::Column::MediaId.eq(media.id)).count(db).await;
+        let points = point::Entity::find().filter(point::Column::MediaId.eq(media.id)).count(db).await;
+        let tags = tag::Entity::find().filter(tag::Column::MediaId.eq(media.id)).count(db).await;
+        let counts = match (clips, points, tags) {
+            (Ok(clips), Ok(points), Ok(tags)) => format!("{} clips, {} points, {} tags", clips, points, tags),
+            _ => "clips, points and tags unknown".to_string(),
+        };
+        html += &format!(
+            "<li class='list-group-item'><code>/{}</code><small class='text-muted d-block'>missing since {} · {}</small></li>",
+            escape(&media.path),
+            media.missing_since.map(iso8601).unwrap_or_default(),
+            counts,
+        );
+    }
+    html += "</ul></div>";
+
+    let page = ADMIN_HTML.replace("{{title}}", "Media").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[post("/admin/media/rescan")]
+pub async fn rescan(
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let report = match rescan_media(db.get_ref(), &libraries).await {
+        Ok(report) => report,
+        Err(e) => return internal_error("Error rescanning media", e),
+    };
+    let moved: String = report
+        .relinked
+        .iter()
+        .map(|(from, to)| format!("<li><code>/{}</code> → <code>/{}</code></li>", escape(from), escape(to)))
+        .collect();
+    let notice = format!(
+        "<div class='alert alert-success'>Rescan done: {} new files, {} linked to their clips, points and tags, {} found after moving, {} missing.<ul class='mb-0'>{}</ul></div>",
+        report.added,
+        report.linked,
+        report.relinked.len(),
+        report.missing.len(),
+        moved,
+    );
+    render(db.get_ref(), &notice).await
+}
+
+pub fn media_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index);
+    cfg.service(rescan);
+}
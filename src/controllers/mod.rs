@@ -1,9 +1,22 @@
 pub mod ai;
+pub mod archives;
+pub mod checksums;
 pub mod clips;
+pub mod conversions;
+pub mod dropboxes;
 pub mod files;
 pub mod login;
+pub mod manage;
+pub mod media;
 pub mod points;
+pub mod renditions;
 pub mod search;
+pub mod shares;
+pub mod sharing;
 pub mod signup;
 pub mod tags;
+pub mod tokens;
+pub mod trash;
+pub mod uploads;
+pub mod users;
 pub mod videos;
@@ -3,16 +3,27 @@ use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, Qu
 use serde::Deserialize;
 use std::path::PathBuf;
 use crate::models::point;
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
 use crate::utils::makepoint::create_point_video;
+use crate::utils::libraries::Libraries;
+use crate::utils::media_index::media_id;
 
 #[get("{video_path:.*}/points")]
 pub async fn index(
-	video_path: web::Path<PathBuf>,
+	video_path: web::Path<String>,
+	libraries: web::Data<Libraries>,
 	db: web::Data<DatabaseConnection>,
+	user: CurrentUser,
 ) -> HttpResponse {
-	let video_path_str = video_path.display().to_string();
-	// let video_path_obj = PathBuf::from(&video_path_str);
-	// let videopath = video_path_obj.parent().map(|p| p.display().to_string()).unwrap_or_default();
+	let video = match libraries.resolve(&video_path) {
+		Ok(video) => video,
+		Err(e) => return e.response(),
+	};
+	if let Err(resp) = user.require_at(Permission::View, &video.relative) {
+		return resp;
+	}
+	let video_path_str = video.relative.clone();
 
 	// Fetch all points associated with the given video path
 	let points = point::Entity::find()
@@ -22,9 +33,7 @@ pub async fn index(
 
 	match points {
 		Ok(points) => {
-			let filename = video_path.file_name()
-				.map(|f| f.to_string_lossy())
-				.unwrap_or_default();
+			let filename = video.file_name();
 			let points_html = if !points.is_empty() {
 				points
 					.into_iter()
@@ -84,11 +93,20 @@ pub struct PointForm {
 
 #[post("{video_path:.*}/points")]
 pub async fn create(
-	video_path: web::Path<PathBuf>,
+	video_path: web::Path<String>,
+	libraries: web::Data<Libraries>,
 	form: web::Form<PointForm>,
 	db: web::Data<DatabaseConnection>,
+	user: CurrentUser,
 ) -> HttpResponse {
-	let source_filename = video_path.display().to_string();
+	let video = match libraries.resolve(&video_path) {
+		Ok(video) => video,
+		Err(e) => return e.response(),
+	};
+	let source_filename = video.relative.clone();
+	if let Err(resp) = user.require_at(Permission::Edit, &source_filename) {
+		return resp;
+	}
 	// let working_directory = video_path.parent()
 	// 	.map(|p| p.display().to_string())
 	// 	.unwrap_or_else(|| "".to_string());
@@ -102,7 +120,7 @@ pub async fn create(
 		return HttpResponse::BadRequest().body("Invalid point: 'time' must be non-negative");
 	}
 
-	if form.name.as_ref().map_or(true, |name| name.trim().is_empty()) {
+	if form.name.as_ref().is_none_or(|name| name.trim().is_empty()) {
 		return HttpResponse::BadRequest().body("Point name cannot be blank");
 	}
 
@@ -112,6 +130,7 @@ pub async fn create(
 		time: Set(form.time),
 		name: Set(form.name.clone()),
 		// working_directory: Set(working_directory), // Uncomment if model has this field
+		media_id: Set(media_id(db.get_ref(), &video).await),
 		..Default::default()
 	};
 
@@ -134,8 +153,13 @@ pub fn points_routes(cfg: &mut web::ServiceConfig) {
 #[get("/points/download")]
 pub async fn download(
 	query: web::Query<std::collections::HashMap<String, String>>,
+	libraries: web::Data<Libraries>,
 	db: web::Data<DatabaseConnection>,
+	user: CurrentUser,
 ) -> HttpResponse {
+	if let Err(resp) = user.require(Permission::View) {
+		return resp;
+	}
 	// Try to get point_id from query params
 	let point_id = match query.get("point_id") {
 		Some(id_str) => match id_str.parse::<i32>() {
@@ -154,17 +178,23 @@ pub async fn download(
 			return HttpResponse::InternalServerError().body("Database error");
 		}
 	};
+	if let Err(resp) = user.require_at(Permission::View, &point.source_filename) {
+		return resp;
+	}
 
 	// Determine segments directory and output filename
-	let source_path = PathBuf::from(&point.source_filename);
-	let segments_dir = source_path.parent()
+	let source = match libraries.resolve(&point.source_filename) {
+		Ok(source) => source,
+		Err(e) => return e.response(),
+	};
+	let segments_dir = source.absolute.parent()
 		.map(|p| p.join("segments"))
 		.unwrap_or_else(|| PathBuf::from("segments"));
-	if !segments_dir.exists() {
-		if let Err(err) = std::fs::create_dir_all(&segments_dir) {
-			eprintln!("Failed to create segments directory: {}", err);
-			return HttpResponse::InternalServerError().body("Failed to create segments directory");
-		}
+	if !segments_dir.exists()
+		&& let Err(err) = std::fs::create_dir_all(&segments_dir)
+	{
+		eprintln!("Failed to create segments directory: {}", err);
+		return HttpResponse::InternalServerError().body("Failed to create segments directory");
 	}
 
 	// Generate output filename (point-{id}.mp4)
@@ -172,7 +202,7 @@ pub async fn download(
 	let raw_name = point.name.clone().unwrap_or_else(|| "untitled".to_string());
 	let sanitized_name = raw_name
 	    .to_lowercase()
-	    .replace(|c: char| c == ' ' || c == '_' || c == ',' || c == '(' || c == ')', "-")
+	    .replace([' ', '_', ',', '(', ')'], "-")
 	    .chars()
 	    .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
 	    .collect::<String>();
@@ -181,7 +211,7 @@ pub async fn download(
 
 	// If file doesn't exist, create it and wait for completion
 	if !output_path.exists() {
-		match create_point_video(&point.source_filename, point.time, &output_path.display().to_string()) {
+		match create_point_video(&source.absolute.display().to_string(), point.time, &output_path.display().to_string()).await {
 			Ok(_) => {
 				// File creation finished, continue
 			}
@@ -195,16 +225,7 @@ pub async fn download(
 	// Serve the file if it exists
 	if output_path.exists() {
 		// Build the URL based on the relative path from the source filename
-		let mut web_path = String::from("");
-		if let Some(parent) = source_path.parent() {
-			// Remove any Windows drive letter and convert to web path
-			let rel_path = parent.strip_prefix(std::env::current_dir().unwrap_or_default()).unwrap_or(parent);
-			web_path = rel_path.to_string_lossy().replace('\\', "/");
-			if !web_path.is_empty() && !web_path.starts_with('/') {
-				web_path = format!("/{}", web_path);
-			}
-		}
-		let file_url = format!("{}/segments/{}", web_path, output_filename);
+		let file_url = format!("/{}/segments/{}", source.parent_relative(), output_filename);
 		// Remove double slashes except for protocol
 		let file_url = file_url.replace("//", "/");
 		HttpResponse::Found().append_header(("Location", file_url)).finish()
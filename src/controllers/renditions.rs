@@ -0,0 +1,134 @@
+use actix_web::{get, post, web, HttpResponse};
+use sea_orm::{ActiveEnum, DatabaseConnection};
+use std::fs;
+use crate::models::conversion::{self, Operation};
+use crate::models::rendition;
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::database::get_proxy_presets;
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+
+fn internal_error(context: &str, err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}: {}", context, err);
+    HttpResponse::InternalServerError().body("Internal server error")
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes as f64 {
+        b if b >= 1_073_741_824.0 => format!("{:.1} GB", b / 1_073_741_824.0),
+        b => format!("{:.1} MB", b / 1_048_576.0),
+    }
+}
+
+// HTMX endpoint: the video's original and proxies, to play in the player or download,
+// and a button to make the proxies it doesn't have yet.
+#[get("{video_path:.*}/renditions")]
+pub async fn index(
+    video_path: web::Path<String>,
+    libraries: web::Data<Libraries>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let video = match libraries.resolve(&video_path) {
+        Ok(video) => video,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::View, &video.relative) {
+        return resp;
+    }
+    let made = match rendition::Model::for_source(db.get_ref(), &video.relative).await {
+        Ok(made) => made,
+        Err(err) => return internal_error("Error fetching renditions", err),
+    };
+    // Only proxies whose file is still there.
+    let proxies: Vec<_> = made
+        .into_iter()
+        .filter_map(|made| {
+            let preset = made.preset()?;
+            let proxy = libraries.resolve(&made.path()?).ok().filter(|proxy| proxy.absolute.is_file())?;
+            Some((preset, proxy, made))
+        })
+        .collect();
+    let missing: Vec<_> = get_proxy_presets(db.get_ref())
+        .await
+        .into_iter()
+        .filter(|preset| !proxies.iter().any(|(made, _, _)| made.name == preset.name))
+        .collect();
+    let can_make = !missing.is_empty() && user.can_at(Permission::Edit, &video.relative);
+    if proxies.is_empty() && !can_make {
+        return HttpResponse::Ok().content_type("text/html").body("");
+    }
+
+    let original = escape(&video.url());
+    let mut choices = format!(
+        "<button class='btn btn-outline-secondary rendition-choice active' data-preset='original' data-src=\"{}\" \
+        onclick='playRendition(this.dataset.src, this.dataset.preset)'>Original</button>",
+        original
+    );
+    let original_size = fs::metadata(&video.absolute).map(|meta| meta.len()).unwrap_or(0);
+    let mut downloads = vec![format!("<a href=\"{}\" download>Original</a> ({})", original, format_size(original_size))];
+    for (preset, proxy, made) in &proxies {
+        let url = escape(&proxy.url());
+        if preset.playable {
+            choices += &format!(
+                "<button class='btn btn-outline-secondary rendition-choice' data-preset='{}' data-src=\"{}\" \
+                onclick='playRendition(this.dataset.src, this.dataset.preset)'>{}</button>",
+                preset.name, url, preset.name
+            );
+        }
+        let size = match (made.width, made.height) {
+            (Some(width), Some(height)) => format!("{}x{}, {}", width, height, format_size(made.size as u64)),
+            _ => format_size(made.size as u64),
+        };
+        downloads.push(format!("<a href=\"{}\" download>{}</a> ({})", url, preset.label, size));
+    }
+
+    let mut html = "<div class='text-muted mt-3'>Renditions</div>".to_string();
+    if proxies.iter().any(|(preset, _, _)| preset.playable) {
+        html += &format!("<div class='btn-group btn-group-sm w-100 mb-2'>{}</div>", choices);
+    }
+    html += &format!("<div class='small'>Download: {}</div>", downloads.join(" · "));
+    if can_make {
+        let labels: Vec<&str> = missing.iter().map(|preset| preset.label).collect();
+        html += &format!(
+            "<button class='btn btn-outline-secondary btn-sm mt-2 w-100' hx-post=\"{}/renditions\" hx-swap='outerHTML'>Make {}</button>",
+            original,
+            labels.join(", ")
+        );
+    }
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+// HTMX endpoint: queue a scaledown to make the video's proxies.
+#[post("{video_path:.*}/renditions")]
+pub async fn create(
+    video_path: web::Path<String>,
+    libraries: web::Data<Libraries>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let video = match libraries.resolve(&video_path) {
+        Ok(video) => video,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::Edit, &video.relative) {
+        return resp;
+    }
+    if !video.absolute.is_file() {
+        return HttpResponse::NotFound().body("Video not found");
+    }
+    if get_proxy_presets(db.get_ref()).await.is_empty() {
+        return HttpResponse::Conflict().body("No proxy presets are enabled");
+    }
+    match conversion::Model::request_conversion(db.get_ref(), video.relative.clone(), Operation::Scaledown.to_value()).await {
+        Ok(true) => HttpResponse::Accepted().body("<div class='small text-muted mt-2'>Proxies queued.</div>"),
+        Ok(false) => HttpResponse::Accepted().body("<div class='small text-muted mt-2'>Proxies are already being made.</div>"),
+        Err(err) => internal_error("Error queueing proxies", err),
+    }
+}
+
+pub fn renditions_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index);
+    cfg.service(create);
+}
@@ -1,8 +1,11 @@
-use actix_session::Session; // Import Session
 use actix_web::{get, web, HttpResponse};
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
-use crate::models::clip;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use crate::models::{clip, media};
 use crate::models::file::File;
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::libraries::Libraries;
+use crate::utils::media_index::{is_video, INTERNAL_DIRS};
 use crate::controllers::files::generate_files_list_html; // Import the helper function
 use std::fs;
 
@@ -10,13 +13,19 @@ use std::fs;
 pub async fn index(
     query: web::Query<std::collections::HashMap<String, String>>,
     db: web::Data<DatabaseConnection>,
-    session: Session, // Accept session as a parameter
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
 ) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::View) {
+        return resp;
+    }
     let search_term = query.get("q").unwrap_or(&"".to_string()).to_lowercase();
 
     if search_term.is_empty() {
-        let folder = std::env::current_dir().unwrap(); // Use the current directory as the folder
-        let html = generate_files_list_html(&folder, "", &session);
+        let html = match libraries.resolve("") {
+            Ok(root) => generate_files_list_html(&libraries, &root, &user),
+            Err(e) => return e.response(),
+        };
         return HttpResponse::Ok().content_type("text/html").body(html);
     }
 
@@ -27,26 +36,49 @@ pub async fn index(
                 .or(clip::Column::Description.contains(&search_term))
                 .or(clip::Column::SourceFilename.contains(&search_term))
         )
+        .filter(clip::Column::DeletedAt.is_null())
         .all(db.get_ref())
         .await;
 
-    let clips = match clips {
-        Ok(clips) => clips,
-        Err(_) => vec![],
-    };
+    // Only show results from folders the user's grants reach.
+    let clips: Vec<_> = clips.unwrap_or_default()
+        .into_iter()
+        .filter(|clip| user.can_at(Permission::View, &clip.source_filename))
+        .collect();
 
-    // Search file system
-    let mut file_results = vec![];
-    if let Ok(entries) = fs::read_dir(".") {
-        for entry in entries.flatten() {
-            if let Ok(file_name) = entry.file_name().into_string() {
-                if file_name.to_lowercase().contains(&search_term) {
-                    file_results.push(file_name);
+    // Folders at the top level of the root and every library
+    let mut folder_results = vec![];
+    for (prefix, dir) in libraries.mounts() {
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                if let Ok(file_name) = entry.file_name().into_string()
+                    && file_name.to_lowercase().contains(&search_term)
+                    && entry.path().is_dir()
+                    && !INTERNAL_DIRS.contains(&file_name.as_str())
+                {
+                    let relative = if prefix.is_empty() { file_name.clone() } else { format!("{}/{}", prefix, file_name) };
+                    if user.can_see(&relative) {
+                        folder_results.push(relative);
+                    }
                 }
             }
         }
     }
 
+    // Files anywhere, from the media index the watcher keeps
+    let file_results: Vec<String> = media::Entity::find()
+        .filter(media::Column::Path.contains(&search_term))
+        .filter(media::Column::MissingSince.is_null())
+        .order_by_asc(media::Column::Path)
+        .all(db.get_ref())
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|media| media.path)
+        // Trashed files don't resolve.
+        .filter(|path| libraries.resolve(path).is_ok() && user.can_at(Permission::View, path))
+        .collect();
+
     // Combine results into HTML
     let mut html = String::new();
     html += "<div class='card'><div class='card-header'>Search Results</div><ul class='list-group list-group-flush'>";
@@ -60,11 +92,12 @@ pub async fn index(
             crate::models::point::Column::Name.contains(&search_term)
             .or(crate::models::point::Column::SourceFilename.contains(&search_term))
         )
+        .filter(crate::models::point::Column::DeletedAt.is_null())
         .all(db.get_ref())
         .await
         .unwrap_or_default();
 
-    for point in points.iter() {
+    for point in points.iter().filter(|point| user.can_at(Permission::View, &point.source_filename)) {
         html += &File::point_preview(point);
     }
 
@@ -74,30 +107,32 @@ pub async fn index(
             crate::models::tag::Column::Tag.contains(&search_term)
             .or(crate::models::tag::Column::SourceFilename.contains(&search_term))
         )
+        .filter(crate::models::tag::Column::DeletedAt.is_null())
         .all(db.get_ref())
         .await
         .unwrap_or_default();
-    for tag in tags.iter() {
+    for tag in tags.iter().filter(|tag| user.can_at(Permission::View, &tag.source_filename)) {
         html += &File::tag_preview(tag);
     }
     
-    let video_extensions = ["mp4", "avi", "mov", "mkv", "webm"];
-    let mut videos = Vec::new(); // Placeholder for video files if needed
-
-    for file in file_results {
-        let ext = file.split('.').last().unwrap_or("").to_lowercase();
-        let is_video = video_extensions.contains(&ext.as_str());
-        html += &File::file_preview(&file, &file, is_video);
+    let mut videos = Vec::new();
+    for path in file_results {
+        let is_video = is_video(&path);
+        html += &File::file_preview(&format!("/{}", path), &path, is_video, false, false);
         if is_video {
-            videos.push(file);
+            videos.push(path);
         }
     }
+    for folder in folder_results {
+        html += &File::file_preview(&format!("/{}", folder), &format!("{}/", folder), false, false, false);
+    }
 
     html += "</ul></div>";
     if !videos.is_empty() {
         html += "<div class='card mt-4'><div class='card-header'>Videos</div><div class='card-body'><div class='flex flex-wrap gap-3'>";
         for video in videos {
-            html += &File::video_preview("", &video);
+            let (dir, name) = video.rsplit_once('/').unwrap_or(("", &video));
+            html += &File::video_preview(dir, name);
         }
         html += "</div></div></div>";
     }
@@ -0,0 +1,639 @@
+use actix_files::NamedFile;
+use actix_session::Session;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set};
+use sea_orm::sea_query::Expr;
+use serde::Deserialize;
+use std::fs;
+use crate::controllers::login::{describe_wait, verify_password};
+use crate::controllers::videos::player_html;
+use crate::models::{clip, login_attempt, share_link, user};
+use crate::models::login_attempt::AttemptKind;
+use crate::models::rendition::PROXIES_DIR;
+use crate::models::share_link::{ShareKind, ShareMode};
+use crate::models::user::Permission;
+use crate::utils::auth::{random_token, CurrentUser};
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+use crate::utils::paths::{child_path, ServedPath};
+use crate::utils::time::now;
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+const SHARE_HTML: &str = include_str!("../views/shares/show.html");
+
+// Session key holding the ids of password-protected links this browser has unlocked.
+const UNLOCKED_KEY: &str = "unlocked_shares";
+
+// Session key holding the ids of download links this browser has used up a download of.
+const COUNTED_KEY: &str = "counted_shares";
+
+const VIDEO_EXTENSIONS: [&str; 5] = ["mp4", "avi", "mov", "mkv", "webm"];
+
+fn is_video(name: &str) -> bool {
+    let ext = name.rsplit('.').next().unwrap_or("").to_lowercase();
+    VIDEO_EXTENSIONS.contains(&ext.as_str())
+}
+
+fn join(dir: &str, rest: &str) -> String {
+    match (dir.is_empty(), rest.is_empty()) {
+        (true, _) => rest.to_string(),
+        (_, true) => dir.to_string(),
+        _ => format!("{}/{}", dir, rest),
+    }
+}
+
+fn internal_error(context: &str, err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}: {}", context, err);
+    HttpResponse::InternalServerError().body("Internal server error")
+}
+
+// ---- Owner pages ----
+
+#[derive(Deserialize)]
+pub struct SharesQuery {
+    #[serde(default)]
+    pub path: String,
+    pub clip: Option<i32>,
+}
+
+async fn render(db: &DatabaseConnection, user: &CurrentUser, query: &SharesQuery, notice: &str) -> HttpResponse {
+    // Admins see everyone's links, so they can revoke them when someone leaves.
+    let mut select = share_link::Entity::find()
+        .filter(share_link::Column::RevokedAt.is_null())
+        .order_by_desc(share_link::Column::CreatedAt);
+    if !user.can(Permission::ManageUsers) {
+        select = select.filter(share_link::Column::OwnerId.eq(user.user.id));
+    }
+    let links = match select.all(db).await {
+        Ok(links) => links,
+        Err(err) => return internal_error("Error fetching share links", err),
+    };
+    let owners = match user::Entity::find().all(db).await {
+        Ok(users) => users,
+        Err(err) => return internal_error("Error fetching users", err),
+    };
+
+    let mut html = String::from(notice);
+    if user.can(Permission::Share) {
+        let target = match query.clip {
+            Some(clip_id) => format!(
+                "<input type='hidden' name='clip' value='{}'><span class='me-2'>Clip #{}</span>",
+                clip_id, clip_id
+            ),
+            None => format!(
+                "<span>/</span><input type='text' name='path' value='{}' placeholder='file or folder' class='form-control form-control-sm' required>",
+                escape(&query.path)
+            ),
+        };
+        html += &format!(
+            "<div class='card mb-4'><div class='card-header'>New share link</div><div class='card-body'>\
+            <form action='/shares' method='post' class='d-flex flex-wrap gap-2 align-items-center'>{target}\
+            <select name='mode' class='form-select form-select-sm w-auto'>\
+            <option value='view'>view only</option><option value='download'>download</option></select>\
+            <input type='number' name='expires_in_days' min='1' placeholder='expires in days' class='form-control form-control-sm w-auto'>\
+            <input type='number' name='max_downloads' min='1' placeholder='max downloads' class='form-control form-control-sm w-auto'>\
+            <input type='password' name='password' placeholder='password (optional)' class='form-control form-control-sm w-auto' autocomplete='new-password'>\
+            <button type='submit' class='btn btn-success btn-sm'>Create</button></form>\
+            <small class='text-muted'>Anyone with the link can open it without an account. \
+            View-only links play in the browser; download links offer the file and count towards the limit.</small>\
+            </div></div>",
+        );
+    }
+
+    html += "<div class='card mb-4'><div class='card-header'>Active links</div><ul class='list-group list-group-flush'>";
+    let now = now();
+    let active: Vec<_> = links.into_iter().filter(|link| link.is_active(now)).collect();
+    if active.is_empty() {
+        html += "<li class='list-group-item text-muted'>No active links.</li>";
+    }
+    for link in active {
+        let target = match link.clip_id {
+            Some(clip_id) => format!("clip #{} of /{}", clip_id, escape(&link.path)),
+            None => format!("/{}", escape(&link.path)),
+        };
+        let mut details = vec![link.mode.clone()];
+        if let Some(expires_at) = link.expires_at {
+            details.push(format!("expires in {}h", (expires_at - now) / 3600));
+        }
+        if let Some(max) = link.max_downloads {
+            details.push(format!("{}/{} downloads", link.download_count, max));
+        } else if link.download_count > 0 {
+            details.push(format!("{} downloads", link.download_count));
+        }
+        if link.password_hash.is_some() {
+            details.push("password".to_string());
+        }
+        if link.owner_id != user.user.id {
+            let owner = owners.iter().find(|u| u.id == link.owner_id).map(|u| u.username.as_str()).unwrap_or("?");
+            details.push(format!("by {}", escape(owner)));
+        }
+        html += &format!(
+            "<li class='list-group-item d-flex align-items-center gap-2'><span class='badge bg-info'>{kind}</span> {target}\
+            <a href='/s/{token}' class='small'>/s/{token}</a><small class='text-muted me-auto'>{details}</small>\
+            <form action='/shares/{id}/revoke' method='post' onsubmit=\"return confirm('Revoke this link?')\"><button type='submit' class='btn btn-outline-danger btn-sm'>Revoke</button></form></li>",
+            kind = link.kind,
+            target = target,
+            token = link.token,
+            details = details.join(" · "),
+            id = link.id,
+        );
+    }
+    html += "</ul></div>";
+
+    let page = ADMIN_HTML.replace("{{title}}", "Share Links").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[get("/shares")]
+pub async fn index(
+    query: web::Query<SharesQuery>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::View) {
+        return resp;
+    }
+    render(db.get_ref(), &user, &query, "").await
+}
+
+#[derive(Deserialize)]
+pub struct ShareForm {
+    #[serde(default)]
+    pub path: String,
+    pub clip: Option<i32>,
+    pub mode: String,
+    #[serde(default)]
+    pub expires_in_days: String,
+    #[serde(default)]
+    pub max_downloads: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+// Empty form fields mean "no limit".
+fn optional_number<T: std::str::FromStr + PartialOrd + Default>(value: &str, field: &str) -> Result<Option<T>, HttpResponse> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(Some(n)),
+        _ => Err(HttpResponse::BadRequest().body(format!("{} must be a positive number", field))),
+    }
+}
+
+#[post("/shares")]
+pub async fn create(
+    form: web::Form<ShareForm>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::Share) {
+        return resp;
+    }
+    let mode = match ShareMode::from_str_case_insensitive(&form.mode) {
+        Some(mode) => mode,
+        None => return HttpResponse::BadRequest().body("Unknown mode"),
+    };
+    let expires_in_days = match optional_number::<i64>(&form.expires_in_days, "Expiry") {
+        Ok(days) => days,
+        Err(resp) => return resp,
+    };
+    let max_downloads = match optional_number::<i32>(&form.max_downloads, "Download limit") {
+        Ok(max) => max,
+        Err(resp) => return resp,
+    };
+
+    // A clip link points at the clip's source video, so the grant check covers it too.
+    let (kind, path) = if let Some(clip_id) = form.clip {
+        match clip::Entity::find_by_id(clip_id).one(db.get_ref()).await {
+            Ok(Some(clip)) => (ShareKind::Clip, clip.source_filename),
+            Ok(None) => return HttpResponse::NotFound().body("Clip not found"),
+            Err(err) => return internal_error("Error fetching clip", err),
+        }
+    } else {
+        let resolved = match libraries.resolve(&form.path) {
+            Ok(resolved) => resolved,
+            Err(e) => return e.response(),
+        };
+        if resolved.absolute.is_dir() {
+            (ShareKind::Folder, resolved.relative)
+        } else if resolved.absolute.is_file() {
+            (ShareKind::File, resolved.relative)
+        } else {
+            return HttpResponse::NotFound().body("No such file or folder");
+        }
+    };
+    if let Err(resp) = user.require_at(Permission::Share, &path) {
+        return resp;
+    }
+
+    let password_hash = if form.password.is_empty() {
+        None
+    } else {
+        match bcrypt::hash(&form.password, bcrypt::DEFAULT_COST) {
+            Ok(hash) => Some(hash),
+            Err(err) => return internal_error("Error hashing share password", err),
+        }
+    };
+
+    let token = random_token();
+    let created_at = now();
+    let new_link = share_link::ActiveModel {
+        token: Set(token.clone()),
+        owner_id: Set(user.user.id),
+        kind: Set(kind.as_str().to_string()),
+        path: Set(path),
+        clip_id: Set(form.clip),
+        mode: Set(mode.as_str().to_string()),
+        password_hash: Set(password_hash),
+        expires_at: Set(expires_in_days.map(|days| created_at + days * 24 * 3600)),
+        max_downloads: Set(max_downloads),
+        download_count: Set(0),
+        created_at: Set(created_at),
+        revoked_at: Set(None),
+        ..Default::default()
+    };
+    if let Err(err) = new_link.insert(db.get_ref()).await {
+        return internal_error("Error creating share link", err);
+    }
+
+    let notice = format!(
+        "<div class='alert alert-success'>Share link created: <a href='/s/{0}'><code>/s/{0}</code></a></div>",
+        token
+    );
+    let query = SharesQuery { path: String::new(), clip: None };
+    render(db.get_ref(), &user, &query, &notice).await
+}
+
+#[post("/shares/{id}/revoke")]
+pub async fn revoke(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::View) {
+        return resp;
+    }
+    let mut update = share_link::Entity::update_many()
+        .col_expr(share_link::Column::RevokedAt, Expr::value(now()))
+        .filter(share_link::Column::Id.eq(*id))
+        .filter(share_link::Column::RevokedAt.is_null());
+    // Only admins can revoke other people's links.
+    if !user.can(Permission::ManageUsers) {
+        update = update.filter(share_link::Column::OwnerId.eq(user.user.id));
+    }
+    if let Err(err) = update.exec(db.get_ref()).await {
+        return internal_error("Error revoking share link", err);
+    }
+    HttpResponse::SeeOther().append_header(("Location", "/shares")).finish()
+}
+
+// ---- Public /s/{token} pages ----
+
+fn share_page(title: &str, contents: &str) -> HttpResponse {
+    let page = SHARE_HTML.replace("{{contents}}", contents).replace("{{title}}", &escape(title));
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+// Expired, revoked, used up and unknown links all look the same from outside.
+async fn find_link(db: &DatabaseConnection, token: &str) -> Result<share_link::Model, HttpResponse> {
+    match share_link::Model::find_active(db, token, now()).await {
+        Ok(Some(link)) => Ok(link),
+        Ok(None) => Err(HttpResponse::NotFound().body("This link has expired or does not exist")),
+        Err(err) => Err(internal_error("Error fetching share link", err)),
+    }
+}
+
+fn is_unlocked(session: &Session, link: &share_link::Model) -> bool {
+    link.password_hash.is_none()
+        || session
+            .get::<Vec<i32>>(UNLOCKED_KEY)
+            .ok()
+            .flatten()
+            .is_some_and(|ids| ids.contains(&link.id))
+}
+
+fn password_form(link: &share_link::Model, error: &str) -> HttpResponse {
+    let contents = format!(
+        "{}<form action='/s/{}' method='post' class='d-flex gap-2' style='max-width:400px'>\
+        <input type='password' name='password' placeholder='Password' class='form-control' required autofocus>\
+        <button type='submit' class='btn btn-primary'>Open</button></form>",
+        error, link.token
+    );
+    share_page("Password required", &contents)
+}
+
+// Map a share link (and a path inside it, for folders) to the file or folder it serves.
+async fn resolve_target(
+    db: &DatabaseConnection,
+    libraries: &Libraries,
+    link: &share_link::Model,
+    subpath: &str,
+) -> Result<ServedPath, HttpResponse> {
+    let kind = link.kind().ok_or_else(|| HttpResponse::NotFound().finish())?;
+    if kind != ShareKind::Folder && !subpath.is_empty() {
+        return Err(HttpResponse::NotFound().finish());
+    }
+    match kind {
+        ShareKind::File => libraries.resolve(&link.path).map_err(|e| e.response()),
+        ShareKind::Clip => {
+            // Clips are written to `segments/` next to their source video.
+            let clip = match clip::Entity::find_by_id(link.clip_id.unwrap_or_default()).one(db).await {
+                Ok(Some(clip)) => clip,
+                Ok(None) => return Err(HttpResponse::NotFound().body("This clip no longer exists")),
+                Err(err) => return Err(internal_error("Error fetching clip", err)),
+            };
+            let source = libraries.resolve(&clip.source_filename).map_err(|e| e.response())?;
+            let segments = join(source.parent_relative(), "segments");
+            child_path(&segments, &clip.clip_filename)
+                .and_then(|path| libraries.resolve(&path))
+                .map_err(|e| e.response())
+        }
+        ShareKind::Folder => {
+            // Hidden folders like `.versions` and `.uploads` stay hidden, as in the listing.
+            if subpath.split(['/', '\\']).any(|part| part.starts_with('.')) {
+                return Err(HttpResponse::NotFound().body("Not found"));
+            }
+            let folder = libraries.resolve(&link.path).map_err(|e| e.response())?;
+            let target = libraries.resolve(&join(&link.path, subpath)).map_err(|e| e.response())?;
+            // The resolver only keeps us inside the served root; a symlink inside the
+            // shared folder must not lead to the rest of it.
+            match (folder.absolute.canonicalize(), target.absolute.canonicalize()) {
+                (Ok(folder_abs), Ok(target_abs)) if target_abs.starts_with(&folder_abs) => Ok(target),
+                (Ok(_), Ok(_)) => Err(HttpResponse::Forbidden().body("Path is outside the shared folder")),
+                _ => Err(HttpResponse::NotFound().body("Not found")),
+            }
+        }
+    }
+}
+
+fn folder_listing(link: &share_link::Model, target: &ServedPath, subpath: &str) -> String {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    if let Ok(entries) = fs::read_dir(&target.absolute) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Same internal folders the file browser hides.
+            if name == "thumbs" || name == "segments" || name == PROXIES_DIR || name.starts_with('.') {
+                continue;
+            }
+            if entry.path().is_dir() {
+                dirs.push(name);
+            } else {
+                files.push(name);
+            }
+        }
+    }
+    dirs.sort_by_key(|name| name.to_lowercase());
+    files.sort_by_key(|name| name.to_lowercase());
+
+    let mut html = String::from("<div class='card'><div class='card-header'>");
+    let folder_name = link.path.rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or("Shared folder");
+    html += &format!("<a href='/s/{}'>{}</a>", link.token, escape(folder_name));
+    if !subpath.is_empty() {
+        html += &format!(" / {}", escape(subpath));
+    }
+    html += "</div><ul class='list-group list-group-flush'>";
+    for name in files {
+        html += &format!(
+            "<li class='list-group-item'><a href='/s/{}/f/{}'>{}</a></li>",
+            link.token, escape(&join(subpath, &name)), escape(&name)
+        );
+    }
+    for name in dirs {
+        html += &format!(
+            "<li class='list-group-item'><a href='/s/{}/f/{}'>{}/</a></li>",
+            link.token, escape(&join(subpath, &name)), escape(&name)
+        );
+    }
+    html += "</ul></div>";
+    html
+}
+
+async fn show_target(
+    db: &DatabaseConnection,
+    libraries: &Libraries,
+    session: &Session,
+    token: &str,
+    subpath: &str,
+) -> HttpResponse {
+    let link = match find_link(db, token).await {
+        Ok(link) => link,
+        Err(resp) => return resp,
+    };
+    if !is_unlocked(session, &link) {
+        return password_form(&link, "");
+    }
+    let target = match resolve_target(db, libraries, &link, subpath).await {
+        Ok(target) => target,
+        Err(resp) => return resp,
+    };
+    if target.absolute.is_dir() {
+        return share_page("Shared folder", &folder_listing(&link, &target, subpath));
+    }
+    if !target.absolute.is_file() {
+        return HttpResponse::NotFound().body("Not found");
+    }
+
+    let raw_url = if subpath.is_empty() {
+        format!("/s/{}/raw", link.token)
+    } else {
+        format!("/s/{}/raw/{}", link.token, subpath)
+    };
+    let name = target.file_name();
+    match link.mode() {
+        ShareMode::View if is_video(name) => {
+            HttpResponse::Ok().content_type("text/html").body(player_html(name, &raw_url, &format!("/s/{}", link.token), None))
+        }
+        ShareMode::View => share_page(
+            name,
+            &format!("<a href='{}' class='btn btn-primary'>Open {}</a>", escape(&raw_url), escape(name)),
+        ),
+        ShareMode::Download => {
+            let size = fs::metadata(&target.absolute).map(|m| m.len()).unwrap_or(0);
+            let left = match link.downloads_left() {
+                Some(left) => format!(" · {} download{} left", left, if left == 1 { "" } else { "s" }),
+                None => String::new(),
+            };
+            share_page(
+                name,
+                &format!(
+                    "<p class='text-muted'>{:.1} MB{}</p><a href='{}' class='btn btn-primary'>Download {}</a>",
+                    size as f64 / 1_048_576.0, left, escape(&raw_url), escape(name)
+                ),
+            )
+        }
+    }
+}
+
+#[get("/s/{token}")]
+pub async fn show(
+    token: web::Path<String>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    session: Session,
+) -> HttpResponse {
+    show_target(db.get_ref(), &libraries, &session, &token, "").await
+}
+
+#[get("/s/{token}/f/{path:.*}")]
+pub async fn show_in_folder(
+    params: web::Path<(String, String)>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    session: Session,
+) -> HttpResponse {
+    let (token, path) = params.into_inner();
+    show_target(db.get_ref(), &libraries, &session, &token, &path).await
+}
+
+#[derive(Deserialize)]
+pub struct UnlockForm {
+    pub password: String,
+}
+
+#[post("/s/{token}")]
+pub async fn unlock(
+    req: HttpRequest,
+    token: web::Path<String>,
+    form: web::Form<UnlockForm>,
+    db: web::Data<DatabaseConnection>,
+    session: Session,
+) -> HttpResponse {
+    let link = match find_link(db.get_ref(), &token).await {
+        Ok(link) => link,
+        Err(resp) => return resp,
+    };
+    let Some(hash) = &link.password_hash else {
+        return HttpResponse::SeeOther().append_header(("Location", format!("/s/{}", link.token))).finish();
+    };
+
+    // Guesses back off like logins do, per link and per IP, kept apart from logins.
+    let ip = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    let wait = match login_attempt::Model::seconds_until_allowed(db.get_ref(), AttemptKind::Share, &link.token, &ip, now()).await {
+        Ok(wait) => wait,
+        Err(err) => return internal_error("Error checking share unlock attempts", err),
+    };
+    if wait > 0 {
+        let error = format!("<div class='alert alert-danger'>Too many wrong passwords. Try again in {}.</div>", describe_wait(wait));
+        let mut resp = password_form(&link, &error);
+        *resp.status_mut() = actix_web::http::StatusCode::TOO_MANY_REQUESTS;
+        return resp;
+    }
+    let verified = verify_password(hash, &form.password);
+    if let Err(err) = login_attempt::Model::record(db.get_ref(), AttemptKind::Share, &link.token, &ip, verified, now()).await {
+        eprintln!("Failed to record share unlock attempt: {}", err);
+    }
+    if !verified {
+        return password_form(&link, "<div class='alert alert-danger'>Wrong password.</div>");
+    }
+    let mut unlocked = session.get::<Vec<i32>>(UNLOCKED_KEY).ok().flatten().unwrap_or_default();
+    if !unlocked.contains(&link.id) {
+        unlocked.push(link.id);
+    }
+    if let Err(err) = session.insert(UNLOCKED_KEY, unlocked) {
+        return internal_error("Error saving share unlock", err);
+    }
+    HttpResponse::SeeOther().append_header(("Location", format!("/s/{}", link.token))).finish()
+}
+
+// A browser's first request for a download link is what counts, whatever range it asks
+// for, so leaving out the first bytes doesn't get the rest for free. Later requests from
+// the same session are that download resuming.
+fn is_counted(session: &Session, link: &share_link::Model) -> bool {
+    session
+        .get::<Vec<i32>>(COUNTED_KEY)
+        .ok()
+        .flatten()
+        .is_some_and(|ids| ids.contains(&link.id))
+}
+
+fn mark_counted(session: &Session, link: &share_link::Model) -> Result<(), actix_session::SessionInsertError> {
+    let mut counted = session.get::<Vec<i32>>(COUNTED_KEY).ok().flatten().unwrap_or_default();
+    counted.push(link.id);
+    session.insert(COUNTED_KEY, counted)
+}
+
+async fn serve_raw(
+    req: &HttpRequest,
+    db: &DatabaseConnection,
+    libraries: &Libraries,
+    session: &Session,
+    token: &str,
+    subpath: &str,
+) -> HttpResponse {
+    let link = match find_link(db, token).await {
+        Ok(link) => link,
+        Err(resp) => return resp,
+    };
+    if !is_unlocked(session, &link) {
+        return HttpResponse::Forbidden().body("This link needs a password");
+    }
+    let target = match resolve_target(db, libraries, &link, subpath).await {
+        Ok(target) => target,
+        Err(resp) => return resp,
+    };
+    let file = match NamedFile::open(&target.absolute) {
+        Ok(file) if target.absolute.is_file() => file,
+        _ => return HttpResponse::NotFound().body("Not found"),
+    };
+
+    let disposition = match link.mode() {
+        ShareMode::View => DispositionType::Inline,
+        ShareMode::Download => {
+            if !is_counted(session, &link) {
+                match link.claim_download(db).await {
+                    Ok(true) => {}
+                    Ok(false) => return HttpResponse::Gone().body("This link has no downloads left"),
+                    Err(err) => return internal_error("Error counting share download", err),
+                }
+                if let Err(err) = mark_counted(session, &link) {
+                    return internal_error("Error saving share download", err);
+                }
+            }
+            DispositionType::Attachment
+        }
+    };
+    file.set_content_disposition(ContentDisposition {
+        disposition,
+        parameters: vec![DispositionParam::Filename(target.file_name().to_string())],
+    })
+    .into_response(req)
+}
+
+#[get("/s/{token}/raw")]
+pub async fn raw(
+    req: HttpRequest,
+    token: web::Path<String>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    session: Session,
+) -> HttpResponse {
+    serve_raw(&req, db.get_ref(), &libraries, &session, &token, "").await
+}
+
+#[get("/s/{token}/raw/{path:.*}")]
+pub async fn raw_in_folder(
+    req: HttpRequest,
+    params: web::Path<(String, String)>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    session: Session,
+) -> HttpResponse {
+    let (token, path) = params.into_inner();
+    serve_raw(&req, db.get_ref(), &libraries, &session, &token, &path).await
+}
+
+pub fn shares_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index);
+    cfg.service(create);
+    cfg.service(revoke);
+    cfg.service(show);
+    cfg.service(unlock);
+    cfg.service(raw);
+    cfg.service(raw_in_folder);
+    cfg.service(show_in_folder);
+}
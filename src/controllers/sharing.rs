@@ -0,0 +1,401 @@
+use actix_web::{get, post, web, HttpResponse};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, Set};
+use sea_orm::sea_query::Expr;
+use serde::Deserialize;
+use crate::models::folder_grant::{self, covers, FolderRight};
+use crate::models::user::{self, Permission};
+use crate::models::{group, group_member};
+use crate::utils::auth::CurrentUser;
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+use crate::utils::time::now;
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+
+fn back_to_sharing(path: &str) -> HttpResponse {
+    let location = format!("/admin/sharing?path={}", urlencoding::encode(path));
+    HttpResponse::SeeOther().append_header(("Location", location)).finish()
+}
+
+#[derive(Deserialize)]
+pub struct SharingQuery {
+    pub path: Option<String>,
+}
+
+// Folder grants and groups. With ?path= it shows the grants that reach that folder.
+#[get("/admin/sharing")]
+pub async fn index(
+    query: web::Query<SharingQuery>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let path = query.path.as_deref().unwrap_or("").trim_matches('/');
+
+    let grants = folder_grant::Entity::find()
+        .order_by_asc(folder_grant::Column::Path)
+        .all(db.get_ref())
+        .await;
+    let users = user::Entity::find().order_by_asc(user::Column::Username).all(db.get_ref()).await;
+    let groups = group::Entity::find().order_by_asc(group::Column::Name).all(db.get_ref()).await;
+    let members = group_member::Entity::find().all(db.get_ref()).await;
+    let (grants, users, groups, members) = match (grants, users, groups, members) {
+        (Ok(grants), Ok(users), Ok(groups), Ok(members)) => (grants, users, groups, members),
+        (Err(err), ..) | (_, Err(err), ..) | (_, _, Err(err), _) | (.., Err(err)) => {
+            eprintln!("Error loading sharing page: {}", err);
+            return HttpResponse::InternalServerError().body("Internal server error");
+        }
+    };
+    let username = |id: i32| escape(users.iter().find(|u| u.id == id).map(|u| u.username.as_str()).unwrap_or("?"));
+    let group_name = |id: i32| escape(groups.iter().find(|g| g.id == id).map(|g| g.name.as_str()).unwrap_or("?"));
+
+    let mut html = String::new();
+
+    // New grant
+    let subjects: String = users.iter()
+        .filter(|u| !u.is_pending())
+        .map(|u| format!("<option value='user:{}'>{}</option>", u.id, escape(&u.username)))
+        .chain(groups.iter().map(|g| format!("<option value='group:{}'>group: {}</option>", g.id, escape(&g.name))))
+        .collect();
+    let rights: String = FolderRight::ALL
+        .iter()
+        .map(|r| format!("<option value='{0}'>{0}</option>", r.as_str()))
+        .collect();
+    html += &format!(
+        "<div class='card mb-4'><div class='card-header'>Share a folder</div><div class='card-body'>\
+        <form action='/admin/sharing/grants' method='post' class='d-flex gap-2 align-items-center'>\
+        <span>/</span><input type='text' name='path' value='{path}' placeholder='folder (empty for everything)' class='form-control form-control-sm'>\
+        <select name='subject' class='form-select form-select-sm w-auto'>{subjects}</select>\
+        <select name='rights' class='form-select form-select-sm w-auto'>{rights}</select>\
+        <button type='submit' class='btn btn-success btn-sm'>Grant</button></form>\
+        <small class='text-muted'>Users who were never given a grant keep their role's access everywhere. Once someone has a grant, \
+        directly or through a group, they only see the folders granted to them, and nothing once their grants are gone. \
+        Grants never go beyond the user's role.</small>\
+        </div></div>",
+        path = escape(path),
+    );
+
+    // Grants
+    let header = if path.is_empty() {
+        "All grants".to_string()
+    } else {
+        format!("Grants reaching /{} <a href='/admin/sharing' class='small ms-2'>show all</a>", escape(path))
+    };
+    html += &format!("<div class='card mb-4'><div class='card-header'>{}</div><ul class='list-group list-group-flush'>", header);
+    let shown: Vec<_> = grants.iter()
+        .filter(|grant| path.is_empty() || covers(&grant.path, path) || covers(path, &grant.path))
+        .collect();
+    if shown.is_empty() {
+        html += "<li class='list-group-item text-muted'>No grants.</li>";
+    }
+    for grant in shown {
+        let subject = match (grant.user_id, grant.group_id) {
+            (Some(id), _) => username(id),
+            (_, Some(id)) => format!("group: {}", group_name(id)),
+            _ => "?".to_string(),
+        };
+        html += &format!(
+            "<li class='list-group-item d-flex align-items-center gap-2'><code>/{}</code> <b>{}</b> <span class='badge bg-info me-auto'>{}</span>\
+            <form action='/admin/sharing/grants/{}/delete' method='post'><button type='submit' class='btn btn-link text-danger btn-sm'>&times;</button></form></li>",
+            escape(&grant.path), subject, grant.rights, grant.id
+        );
+    }
+    html += "</ul></div>";
+
+    // Confined users, with a way back to their role's access once nothing is granted to them
+    let confined: Vec<_> = users.iter().filter(|u| u.restricted).collect();
+    if !confined.is_empty() {
+        html += "<div class='card mb-4'><div class='card-header'>Confined users</div><ul class='list-group list-group-flush'>";
+        for u in confined {
+            let group_ids: Vec<i32> = members.iter().filter(|m| m.user_id == u.id).map(|m| m.group_id).collect();
+            let count = grants.iter()
+                .filter(|grant| grant.user_id == Some(u.id) || grant.group_id.is_some_and(|id| group_ids.contains(&id)))
+                .count();
+            let state = if count == 0 {
+                format!(
+                    "<span class='text-muted'>No access</span>\
+                    <form action='/admin/sharing/users/{}/unrestrict' method='post'><button type='submit' class='btn btn-outline-secondary btn-sm'>Give back full access</button></form>",
+                    u.id
+                )
+            } else {
+                format!("<span class='text-muted'>{} grants</span>", count)
+            };
+            html += &format!(
+                "<li class='list-group-item d-flex align-items-center gap-2'><b class='me-auto'>{}</b>{}</li>",
+                escape(&u.username), state
+            );
+        }
+        html += "</ul></div>";
+    }
+
+    // Groups
+    html += "<div class='card mb-4'><div class='card-header'>Groups</div><div class='card-body'>\
+        <form action='/admin/groups' method='post' class='d-flex gap-2'>\
+        <input type='text' name='name' placeholder='New group name' class='form-control form-control-sm' required>\
+        <button type='submit' class='btn btn-success btn-sm'>Create group</button></form></div><ul class='list-group list-group-flush'>";
+    for g in &groups {
+        let member_ids: Vec<i32> = members.iter().filter(|m| m.group_id == g.id).map(|m| m.user_id).collect();
+        let member_html: String = member_ids.iter()
+            .map(|id| format!(
+                "<span class='badge bg-secondary'>{} <form action='/admin/groups/{}/members/{}/delete' method='post' class='d-inline'>\
+                <button type='submit' class='btn btn-link text-white p-0 ms-1'>&times;</button></form></span> ",
+                username(*id), g.id, id
+            ))
+            .collect();
+        let options: String = users.iter()
+            .filter(|u| !u.is_pending() && !member_ids.contains(&u.id))
+            .map(|u| format!("<option value='{}'>{}</option>", u.id, escape(&u.username)))
+            .collect();
+        html += &format!(
+            "<li class='list-group-item'><div class='d-flex align-items-center gap-2'><b class='me-auto'>{name}</b>\
+            <form action='/admin/groups/{id}/members' method='post' class='d-flex gap-2'><select name='user_id' class='form-select form-select-sm'>{options}</select>\
+            <button type='submit' class='btn btn-primary btn-sm'>Add</button></form>\
+            <form action='/admin/groups/{id}/delete' method='post' data-name='{name}' onsubmit=\"return confirm('Delete group ' + this.dataset.name + ' and its grants?')\"><button type='submit' class='btn btn-outline-danger btn-sm'>Delete</button></form></div>\
+            <div class='mt-2'>{members}</div></li>",
+            id = g.id,
+            name = escape(&g.name),
+            options = options,
+            members = member_html,
+        );
+    }
+    html += "</ul></div>";
+
+    let page = ADMIN_HTML.replace("{{title}}", "Sharing").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[derive(Deserialize)]
+pub struct GrantForm {
+    pub path: String,
+    pub subject: String, // "user:ID" or "group:ID"
+    pub rights: String,
+}
+
+#[post("/admin/sharing/grants")]
+pub async fn create_grant(
+    form: web::Form<GrantForm>,
+    libraries: web::Data<Libraries>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let rights = match FolderRight::from_str_case_insensitive(&form.rights) {
+        Some(rights) => rights,
+        None => return HttpResponse::BadRequest().body("Unknown rights"),
+    };
+    let (user_id, group_id) = match form.subject.split_once(':') {
+        Some(("user", id)) => (id.parse().ok(), None),
+        Some(("group", id)) => (None, id.parse().ok()),
+        _ => (None, None),
+    };
+    if user_id.is_none() && group_id.is_none() {
+        return HttpResponse::BadRequest().body("Choose a user or group");
+    }
+    // Store the folder in the same normalized form the controllers check against.
+    let folder = match libraries.resolve(form.path.trim().trim_start_matches('/')) {
+        Ok(folder) => folder,
+        Err(e) => return e.response(),
+    };
+    if !folder.absolute.is_dir() {
+        return HttpResponse::BadRequest().body("That folder does not exist");
+    }
+
+    let grant = folder_grant::ActiveModel {
+        path: Set(folder.relative.clone()),
+        user_id: Set(user_id),
+        group_id: Set(group_id),
+        rights: Set(rights.as_str().to_string()),
+        created_by: Set(user.user.id),
+        created_at: Set(now()),
+        ..Default::default()
+    };
+    let result = async {
+        let subjects = folder_grant::Model::subjects(db.get_ref(), user_id, group_id).await?;
+        user::Model::restrict(db.get_ref(), subjects).await?;
+        grant.insert(db.get_ref()).await
+    }.await;
+    if let Err(err) = result {
+        eprintln!("Error creating grant: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to create grant");
+    }
+    back_to_sharing(&folder.relative)
+}
+
+#[post("/admin/sharing/grants/{id}/delete")]
+pub async fn delete_grant(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    if let Err(err) = folder_grant::Entity::delete_by_id(*id).exec(db.get_ref()).await {
+        eprintln!("Error deleting grant: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to delete grant");
+    }
+    back_to_sharing("")
+}
+
+#[derive(Deserialize)]
+pub struct GroupForm {
+    pub name: String,
+}
+
+#[post("/admin/groups")]
+pub async fn create_group(
+    form: web::Form<GroupForm>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let name = form.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Group name cannot be blank");
+    }
+    let new_group = group::ActiveModel {
+        name: Set(name.to_string()),
+        ..Default::default()
+    };
+    if let Err(err) = new_group.insert(db.get_ref()).await {
+        eprintln!("Error creating group: {}", err);
+        return HttpResponse::Conflict().body("A group with that name already exists");
+    }
+    back_to_sharing("")
+}
+
+#[post("/admin/groups/{id}/delete")]
+pub async fn delete_group(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let db = db.get_ref();
+    let result = async {
+        group_member::Entity::delete_many().filter(group_member::Column::GroupId.eq(*id)).exec(db).await?;
+        folder_grant::Entity::delete_many().filter(folder_grant::Column::GroupId.eq(*id)).exec(db).await?;
+        group::Entity::delete_by_id(*id).exec(db).await
+    }.await;
+    if let Err(err) = result {
+        eprintln!("Error deleting group: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to delete group");
+    }
+    back_to_sharing("")
+}
+
+#[derive(Deserialize)]
+pub struct MemberForm {
+    pub user_id: i32,
+}
+
+#[post("/admin/groups/{id}/members")]
+pub async fn add_member(
+    id: web::Path<i32>,
+    form: web::Form<MemberForm>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let member = group_member::ActiveModel {
+        group_id: Set(*id),
+        user_id: Set(form.user_id),
+        ..Default::default()
+    };
+    if let Err(err) = member.insert(db.get_ref()).await {
+        eprintln!("Error adding group member: {}", err);
+        return HttpResponse::Conflict().body("That user is already in the group");
+    }
+    // Joining a group with grants confines the user like a grant of their own.
+    let result = async {
+        let granted = folder_grant::Entity::find()
+            .filter(folder_grant::Column::GroupId.eq(*id))
+            .count(db.get_ref())
+            .await?;
+        if granted > 0 {
+            user::Model::restrict(db.get_ref(), vec![form.user_id]).await?;
+        }
+        Ok::<_, sea_orm::DbErr>(())
+    }.await;
+    if let Err(err) = result {
+        eprintln!("Error confining group member: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to add group member");
+    }
+    back_to_sharing("")
+}
+
+#[post("/admin/groups/{id}/members/{user_id}/delete")]
+pub async fn remove_member(
+    path: web::Path<(i32, i32)>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let (group_id, user_id) = path.into_inner();
+    if let Err(err) = group_member::Entity::delete_many()
+        .filter(group_member::Column::GroupId.eq(group_id))
+        .filter(group_member::Column::UserId.eq(user_id))
+        .exec(db.get_ref())
+        .await
+    {
+        eprintln!("Error removing group member: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to remove group member");
+    }
+    back_to_sharing("")
+}
+
+// Give a confined user their role's access everywhere again. Only once nothing is granted
+// to them, or their grants would confine them again straight away.
+#[post("/admin/sharing/users/{id}/unrestrict")]
+pub async fn unrestrict(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let grants = match folder_grant::Model::for_user(db.get_ref(), *id).await {
+        Ok(grants) => grants,
+        Err(err) => {
+            eprintln!("Error fetching grants: {}", err);
+            return HttpResponse::InternalServerError().body("Internal server error");
+        }
+    };
+    if !grants.is_empty() {
+        return HttpResponse::Conflict().body("Remove this user's grants first");
+    }
+    if let Err(err) = user::Entity::update_many()
+        .col_expr(user::Column::Restricted, Expr::value(false))
+        .filter(user::Column::Id.eq(*id))
+        .exec(db.get_ref())
+        .await
+    {
+        eprintln!("Error updating user: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to update user");
+    }
+    back_to_sharing("")
+}
+
+pub fn sharing_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index);
+    cfg.service(create_grant);
+    cfg.service(delete_grant);
+    cfg.service(create_group);
+    cfg.service(delete_group);
+    cfg.service(add_member);
+    cfg.service(remove_member);
+    cfg.service(unrestrict);
+}
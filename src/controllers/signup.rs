@@ -1,41 +1,227 @@
 use sea_orm::DatabaseConnection;
 use actix_web::{web, HttpResponse, Result};
 use actix_web::Error as ActixError;
-use crate::models::user::ActiveModel;
-use sea_orm::{Set, ActiveModelTrait};
+use crate::models::invite;
+use crate::models::settings::SignupMode;
+use crate::models::user::{self, ActiveModel, Role};
+use crate::utils::database::get_signup_mode;
+use crate::utils::html::escape;
+use crate::utils::time::now;
+use sea_orm::sea_query::Expr;
+use sea_orm::{Set, ActiveModelTrait, ColumnTrait, DbErr, EntityTrait, PaginatorTrait, QueryFilter, SqlErr, TransactionTrait};
+use serde::Deserialize;
 use bcrypt;
-use crate::controllers::login::LoginForm;
+
+const SIGNUP_TEMPLATE: &str = include_str!("../views/signup/signup_form.html");
+
+#[derive(Deserialize)]
+pub struct SignupForm {
+    pub username: String,
+    pub password: String,
+    pub invite: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SignupQuery {
+    pub invite: Option<String>,
+}
+
+/// Why a signup was turned away.
+#[derive(Debug)]
+pub enum SignupError {
+    MissingFields,
+    UsernameTaken,
+    SignupsClosed,
+    InviteRequired,
+    InvalidInvite,
+    Database(DbErr),
+}
+
+impl std::fmt::Display for SignupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignupError::MissingFields => write!(f, "Username and password are required."),
+            SignupError::UsernameTaken => write!(f, "That username is already taken."),
+            SignupError::SignupsClosed => write!(f, "Signups are closed."),
+            SignupError::InviteRequired => write!(f, "An invite code is required to sign up."),
+            SignupError::InvalidInvite => write!(f, "That invite code is invalid, used or expired."),
+            SignupError::Database(_) => write!(f, "Something went wrong creating your account."),
+        }
+    }
+}
+
+impl From<DbErr> for SignupError {
+    fn from(e: DbErr) -> Self {
+        // The unique index on users.username catches a name taken since it was checked.
+        match e.sql_err() {
+            Some(SqlErr::UniqueConstraintViolation(_)) => SignupError::UsernameTaken,
+            _ => SignupError::Database(e),
+        }
+    }
+}
+
+impl SignupError {
+    fn response(&self, mode: SignupMode, invite: Option<&str>) -> HttpResponse {
+        let mut builder = match self {
+            SignupError::MissingFields | SignupError::InvalidInvite | SignupError::InviteRequired => HttpResponse::BadRequest(),
+            SignupError::UsernameTaken => HttpResponse::Conflict(),
+            SignupError::SignupsClosed => HttpResponse::Forbidden(),
+            SignupError::Database(e) => {
+                eprintln!("Error creating user: {}", e);
+                HttpResponse::InternalServerError()
+            }
+        };
+        let error = format!("<div class='alert alert-danger'>{}</div>", self);
+        builder.content_type("text/html").body(render(&error, &form_html(mode, invite)))
+    }
+}
+
+fn render(error: &str, contents: &str) -> String {
+    SIGNUP_TEMPLATE
+        .replace("{{error}}", error)
+        .replace("{{contents}}", contents)
+}
+
+fn form_html(mode: SignupMode, invite: Option<&str>) -> String {
+    if mode == SignupMode::Closed {
+        return "<p>Signups are closed. Ask an admin for an account.</p><a href='/login' class='btn btn-secondary'>Login</a>".to_string();
+    }
+    let invite_field = if mode == SignupMode::InviteOnly {
+        format!(
+            "<div class='mb-3'><label for='invite' class='form-label'>Invite code</label><input type='text' class='form-control' id='invite' name='invite' value='{}' required></div>",
+            escape(invite.unwrap_or(""))
+        )
+    } else {
+        String::new()
+    };
+    format!(
+        r#"<form action="/signup" method="post">
+            <div class="mb-3">
+                <label for="username" class="form-label">Username</label>
+                <input type="text" class="form-control" id="username" name="username" placeholder="Username" required>
+            </div>
+            <div class="mb-3">
+                <label for="password" class="form-label">Password</label>
+                <input type="password" class="form-control" id="password" name="password" placeholder="Password" required>
+            </div>
+            {}
+            <button type="submit" class="btn btn-primary">Sign Up</button>
+            <a href="/" class="btn btn-secondary ms-2">Back</a>
+        </form>"#,
+        invite_field
+    )
+}
 
 // Signup form (GET)
-pub async fn signup_form() -> HttpResponse {
-    let html = r#"
-        <h1>Sign Up</h1>
-        <form action="/signup" method="post">
-            <input type="text" name="username" placeholder="Username" required><br>
-            <input type="password" name="password" placeholder="Password" required><br>
-            <button type="submit">Sign Up</button>
-        </form>
-        <a href="/">Back</a>
-    "#;
+pub async fn signup_form(
+    db: web::Data<DatabaseConnection>,
+    query: web::Query<SignupQuery>,
+) -> HttpResponse {
+    let mode = effective_mode(db.get_ref()).await;
+    let html = render("", &form_html(mode, query.invite.as_deref()));
     HttpResponse::Ok().content_type("text/html").body(html)
 }
 
 // Signup handler (POST)
 pub async fn signup(
     db: web::Data<DatabaseConnection>,
-    form: web::Form<LoginForm>,
+    form: web::Form<SignupForm>,
 ) -> Result<HttpResponse, ActixError> {
-    let password_hash = bcrypt::hash(&form.password, bcrypt::DEFAULT_COST).unwrap();
+    let mode = effective_mode(db.get_ref()).await;
+    match create_account(db.get_ref(), mode, &form).await {
+        Ok(created) if created.is_pending() => {
+            let html = render(
+                "<div class='alert alert-success'>Account created. An admin needs to approve it before you can log in.</div>",
+                "<a href='/login' class='btn btn-secondary'>Login</a>",
+            );
+            Ok(HttpResponse::Ok().content_type("text/html").body(html))
+        }
+        Ok(_) => Ok(HttpResponse::Found().append_header(("Location", "/login")).finish()),
+        Err(e) => Ok(e.response(mode, form.invite.as_deref())),
+    }
+}
+
+// A server with no users yet lets the first visitor sign up as admin, whatever the mode.
+async fn effective_mode(db: &DatabaseConnection) -> SignupMode {
+    if user::Entity::find().count(db).await.unwrap_or(1) == 0 {
+        return SignupMode::Open;
+    }
+    get_signup_mode(db).await
+}
+
+async fn create_account(
+    db: &DatabaseConnection,
+    mode: SignupMode,
+    form: &SignupForm,
+) -> Result<user::Model, SignupError> {
+    let username = form.username.trim();
+    if username.is_empty() || form.password.is_empty() {
+        return Err(SignupError::MissingFields);
+    }
+    if mode == SignupMode::Closed {
+        return Err(SignupError::SignupsClosed);
+    }
+
+    let taken = user::Entity::find()
+        .filter(user::Column::Username.eq(username))
+        .one(db)
+        .await?;
+    if taken.is_some() {
+        return Err(SignupError::UsernameTaken);
+    }
+
+    let invite = match mode {
+        SignupMode::InviteOnly => {
+            let token = form.invite.as_deref().map(str::trim).filter(|t| !t.is_empty())
+                .ok_or(SignupError::InviteRequired)?;
+            let invite = invite::Model::find_by_token(db, token).await?
+                .filter(|invite| invite.is_usable(now()))
+                .ok_or(SignupError::InvalidInvite)?;
+            Some(invite)
+        }
+        _ => None,
+    };
+
+    let access_level = match &invite {
+        Some(invite) => invite.access_level.clone(),
+        None if user::Entity::find().count(db).await? == 0 => Role::Admin.as_str().to_string(),
+        None => user::PENDING.to_string(),
+    };
+
+    let password_hash = bcrypt::hash(&form.password, bcrypt::DEFAULT_COST)
+        .map_err(|e| SignupError::Database(DbErr::Custom(e.to_string())))?;
+
+    // Claim the invite and create the account together: two people can't redeem it at
+    // once, and an account that can't be created gives the invite back.
+    let txn = db.begin().await?;
+    if let Some(invite) = &invite {
+        let claimed = invite::Entity::update_many()
+            .col_expr(invite::Column::UsedAt, Expr::value(now()))
+            .filter(invite::Column::Id.eq(invite.id))
+            .filter(invite::Column::UsedAt.is_null())
+            .exec(&txn)
+            .await?;
+        if claimed.rows_affected == 0 {
+            return Err(SignupError::InvalidInvite);
+        }
+    }
+
     let user = ActiveModel {
-        username: Set(form.username.clone()),
+        username: Set(username.to_string()),
         password_hash: Set(password_hash),
-        access_level: Set("None".to_string()),
+        access_level: Set(access_level),
         ..Default::default()
     };
-    match user.insert(db.get_ref()).await {
-        Ok(_) => Ok(HttpResponse::Found().append_header(("Location", "/login")).finish()),
-        Err(e) => Ok(HttpResponse::InternalServerError().body(format!("Error: {}", e))),
+    let created = user.insert(&txn).await?;
+
+    if let Some(invite) = invite {
+        let mut am: invite::ActiveModel = invite.into();
+        am.used_by = Set(Some(created.id));
+        am.update(&txn).await?;
     }
+    txn.commit().await?;
+
+    Ok(created)
 }
 
 pub fn signup_routes(cfg: &mut web::ServiceConfig) {
@@ -44,4 +230,4 @@ pub fn signup_routes(cfg: &mut web::ServiceConfig) {
             .route(web::get().to(signup_form))
             .route(web::post().to(signup))
     );
-}
\ No newline at end of file
+}
@@ -1,16 +1,29 @@
 use actix_web::{get, post, delete, web, HttpResponse, HttpRequest};
 use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
 use serde::Deserialize;
-use std::path::PathBuf;
 use crate::models::tag;
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+use crate::utils::media_index::media_id;
 
 #[get("{video_path:.*}/tags")]
 pub async fn index(
-	video_path: web::Path<PathBuf>,
+	video_path: web::Path<String>,
+	libraries: web::Data<Libraries>,
 	db: web::Data<DatabaseConnection>,
 	req: HttpRequest,
+	user: CurrentUser,
 ) -> HttpResponse {
-	let video_path_str = video_path.display().to_string();
+	let video = match libraries.resolve(&video_path) {
+		Ok(video) => video,
+		Err(e) => return e.response(),
+	};
+	if let Err(resp) = user.require_at(Permission::View, &video.relative) {
+		return resp;
+	}
+	let video_path_str = video.relative.clone();
 
 	// Fetch all tags associated with the given video path
 	let tags = tag::Entity::find()
@@ -23,7 +36,7 @@ pub async fn index(
 
 	match tags {
 		Ok(tags) => {
-			let filename = video_path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+			let filename = video.file_name();
 			let tags_html = if !tags.is_empty() {
 				tags
 					.into_iter()
@@ -33,8 +46,10 @@ pub async fn index(
 							tag.id
 						);
 						format!(
-							"<div><span class='badge bg-info'>{}</span> {}</div>",
-							tag.tag,
+							"<div><span class='badge bg-info'>{}</span> <a href='/archive?tag={}' class='small text-muted' title='Download everything tagged {}'>download</a> {}</div>",
+							escape(&tag.tag),
+							urlencoding::encode(&tag.slug),
+							escape(&tag.tag),
 							delete_button,
 						)
 					})
@@ -44,7 +59,7 @@ pub async fn index(
 			};
 			let mut html = format!(
 				"<div class='text-muted mt-3'>Tags for {}</div>{}<button class='badge bg-primary border-0' hx-get='/{}/tags/new' hx-target='#new-tag-form' hx-swap='innerHTML'>+ New</button><div id='new-tag-form' class='mt-2'>",
-				filename,
+				escape(filename),
 				tags_html,
 				video_path_str.trim_start_matches('/')
 			);
@@ -65,8 +80,14 @@ pub async fn index(
 
 // HTMX endpoint: returns a form for creating a new tag for a specific video path
 #[get("{video_path:.*}/tags/new")]
-pub async fn new(video_path: web::Path<PathBuf>) -> HttpResponse {
-	let video_path_str = video_path.display().to_string();
+pub async fn new(video_path: web::Path<String>, libraries: web::Data<Libraries>, user: CurrentUser) -> HttpResponse {
+	let video_path_str = match libraries.resolve(&video_path) {
+		Ok(video) => video.relative,
+		Err(e) => return e.response(),
+	};
+	if let Err(resp) = user.require_at(Permission::Edit, &video_path_str) {
+		return resp;
+	}
 	let action_path = format!("/{}/tags", video_path_str.trim_start_matches('/'));
 	let form_html = TAG_FORM_HTML.replace("{action_path}", &action_path);
 	HttpResponse::Ok().content_type("text/html").body(form_html)
@@ -79,11 +100,20 @@ pub struct TagForm {
 
 #[post("{video_path:.*}/tags")]
 pub async fn create(
-	video_path: web::Path<PathBuf>,
+	video_path: web::Path<String>,
+	libraries: web::Data<Libraries>,
 	form: web::Form<TagForm>,
 	db: web::Data<DatabaseConnection>,
+	user: CurrentUser,
 ) -> HttpResponse {
-	let source_filename = video_path.display().to_string();
+	let video = match libraries.resolve(&video_path) {
+		Ok(video) => video,
+		Err(e) => return e.response(),
+	};
+	let source_filename = video.relative.clone();
+	if let Err(resp) = user.require_at(Permission::Edit, &source_filename) {
+		return resp;
+	}
 
 	// Log incoming data for debugging
 	eprintln!("Received POST tag for video_path: {}", source_filename);
@@ -99,6 +129,7 @@ pub async fn create(
 		source_filename: Set(source_filename.clone()),
 		tag: Set(form.tag.clone()),
 		slug: Set(tag::Model::normalize_tag(&form.tag)),
+		media_id: Set(media_id(db.get_ref(), &video).await),
 		..Default::default()
 	};
 
@@ -118,7 +149,11 @@ pub async fn create(
 pub async fn delete(
 	tag_id: web::Path<i32>,
 	db: web::Data<DatabaseConnection>,
+	user: CurrentUser,
 ) -> HttpResponse {
+	if let Err(resp) = user.require(Permission::Edit) {
+		return resp;
+	}
 	use sea_orm::EntityTrait;
 	use crate::models::tag::Entity as TagEntity;
 
@@ -128,6 +163,9 @@ pub async fn delete(
 		Ok(Some(tag)) => tag.source_filename.clone(),
 		_ => return HttpResponse::NotFound().body("Tag not found"),
 	};
+	if let Err(resp) = user.require_at(Permission::Edit, &source_filename) {
+		return resp;
+	}
 
 	// Delete the tag
 	if let Err(err) = TagEntity::delete_by_id(*tag_id).exec(db.get_ref()).await {
@@ -156,8 +194,10 @@ pub async fn delete(
 							tag.id
 						);
 						format!(
-							"<div><span class='badge bg-info'>{}</span> {}</div>",
-							tag.tag,
+							"<div><span class='badge bg-info'>{}</span> <a href='/archive?tag={}' class='small text-muted' title='Download everything tagged {}'>download</a> {}</div>",
+							escape(&tag.tag),
+							urlencoding::encode(&tag.slug),
+							escape(&tag.tag),
 							delete_button,
 						)
 					})
@@ -167,7 +207,7 @@ pub async fn delete(
 			};
 			let html = format!(
 				"<div id='tags-list'><div class='text-muted mt-3'>Tags for {}</div>{}<button class='badge bg-primary border-0' hx-get='/{}'/tags/new' hx-target='#new-tag-form' hx-swap='innerHTML'>+ New</button><div id='new-tag-form' class='mt-2'></div></div>",
-				filename,
+				escape(&filename),
 				tags_html,
 				source_filename.trim_start_matches('/')
 			);
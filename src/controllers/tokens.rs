@@ -0,0 +1,165 @@
+use actix_web::{get, post, web, HttpResponse};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set};
+use sea_orm::sea_query::Expr;
+use serde::Deserialize;
+use crate::models::api_token::{self, TokenScope, TOKEN_PREFIX};
+use crate::models::user::Permission;
+use crate::utils::auth::{random_token, CurrentUser};
+use crate::utils::html::escape;
+use crate::utils::time::now;
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+
+// Tokens can only be managed from a logged-in browser, never with another token.
+fn require_session(user: &CurrentUser) -> Result<(), HttpResponse> {
+    user.require(Permission::View)?;
+    if !user.is_session() {
+        return Err(HttpResponse::Forbidden().body("API tokens cannot manage API tokens"));
+    }
+    Ok(())
+}
+
+fn scope_options() -> String {
+    TokenScope::ALL
+        .iter()
+        .map(|scope| format!("<option value='{0}'>{0}</option>", scope.as_str()))
+        .collect()
+}
+
+async fn render(db: &DatabaseConnection, user: &CurrentUser, notice: &str) -> HttpResponse {
+    let tokens = match api_token::Entity::find()
+        .filter(api_token::Column::UserId.eq(user.user.id))
+        .filter(api_token::Column::RevokedAt.is_null())
+        .order_by_desc(api_token::Column::CreatedAt)
+        .all(db)
+        .await
+    {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            eprintln!("Error fetching API tokens: {}", err);
+            return HttpResponse::InternalServerError().body("Internal server error");
+        }
+    };
+
+    let mut html = String::from(notice);
+    html += &format!(
+        "<div class='card mb-4'><div class='card-header'>New token</div><div class='card-body'>\
+        <form action='/tokens' method='post' class='d-flex gap-2 align-items-center'>\
+        <input type='text' name='name' placeholder='Name, e.g. laptop sync' class='form-control form-control-sm' required>\
+        <label>Scope</label><select name='scope' class='form-select form-select-sm w-auto'>{}</select>\
+        <button type='submit' class='btn btn-success btn-sm'>Create</button></form>\
+        <small class='text-muted'>Send it as <code>Authorization: Bearer &lt;token&gt;</code>. A token can never do more than your account can.</small>\
+        </div></div>",
+        scope_options()
+    );
+
+    html += "<div class='card mb-4'><div class='card-header'>Your tokens</div><ul class='list-group list-group-flush'>";
+    if tokens.is_empty() {
+        html += "<li class='list-group-item text-muted'>No tokens yet.</li>";
+    }
+    for token in tokens {
+        let last_used = match token.last_used_at {
+            Some(t) => format!("last used {}h ago", (now() - t) / 3600),
+            None => "never used".to_string(),
+        };
+        html += &format!(
+            "<li class='list-group-item d-flex align-items-center gap-2'><b>{}</b> <span class='badge bg-info'>{}</span>\
+            <small class='text-muted me-auto'>{}</small>\
+            <form action='/tokens/{}/revoke' method='post' onsubmit=\"return confirm('Revoke this token?')\"><button type='submit' class='btn btn-outline-danger btn-sm'>Revoke</button></form></li>",
+            escape(&token.name), token.scope, last_used, token.id
+        );
+    }
+    html += "</ul></div>";
+
+    let page = ADMIN_HTML.replace("{{title}}", "API Tokens").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[get("/tokens")]
+pub async fn index(
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = require_session(&user) {
+        return resp;
+    }
+    render(db.get_ref(), &user, "").await
+}
+
+#[derive(Deserialize)]
+pub struct TokenForm {
+    pub name: String,
+    pub scope: String,
+}
+
+// Create a token and show it once; only its hash is kept.
+#[post("/tokens")]
+pub async fn create(
+    form: web::Form<TokenForm>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = require_session(&user) {
+        return resp;
+    }
+    let scope = match TokenScope::from_str_case_insensitive(&form.scope) {
+        Some(scope) => scope,
+        None => return HttpResponse::BadRequest().body("Unknown scope"),
+    };
+    let name = form.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Tokens need a name");
+    }
+
+    let token = format!("{}{}", TOKEN_PREFIX, random_token());
+    let new_token = api_token::ActiveModel {
+        user_id: Set(user.user.id),
+        name: Set(name.to_string()),
+        token_hash: Set(api_token::hash_token(&token)),
+        scope: Set(scope.as_str().to_string()),
+        created_at: Set(now()),
+        last_used_at: Set(None),
+        revoked_at: Set(None),
+        ..Default::default()
+    };
+    if let Err(err) = new_token.insert(db.get_ref()).await {
+        eprintln!("Error creating API token: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to create token");
+    }
+
+    let notice = format!(
+        "<div class='alert alert-success'>Copy your new token now, it won't be shown again:<br><code>{}</code></div>",
+        token
+    );
+    render(db.get_ref(), &user, &notice).await
+}
+
+#[post("/tokens/{id}/revoke")]
+pub async fn revoke(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = require_session(&user) {
+        return resp;
+    }
+    // Scoped to the owner, so nobody can revoke someone else's token by id.
+    if let Err(err) = api_token::Entity::update_many()
+        .col_expr(api_token::Column::RevokedAt, Expr::value(now()))
+        .filter(api_token::Column::Id.eq(*id))
+        .filter(api_token::Column::UserId.eq(user.user.id))
+        .filter(api_token::Column::RevokedAt.is_null())
+        .exec(db.get_ref())
+        .await
+    {
+        eprintln!("Error revoking API token: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to revoke token");
+    }
+    HttpResponse::SeeOther().append_header(("Location", "/tokens")).finish()
+}
+
+pub fn tokens_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index);
+    cfg.service(create);
+    cfg.service(revoke);
+}
@@ -0,0 +1,176 @@
+use actix_web::{get, post, web, HttpResponse};
+use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder};
+use serde::Deserialize;
+use crate::models::{trash_item, user};
+use crate::models::trash_item::{retention_secs, MAX_RETENTION_DAYS};
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::database::{get_trash_retention_days, set_trash_retention_days};
+use crate::utils::file_ops::{purge_path, restore_path};
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
+use crate::utils::time::{iso8601, now};
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+
+fn internal_error(context: &str, err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}: {}", context, err);
+    HttpResponse::InternalServerError().body("Internal server error")
+}
+
+fn back_to_trash() -> HttpResponse {
+    HttpResponse::SeeOther().append_header(("Location", "/trash")).finish()
+}
+
+async fn find_item(db: &DatabaseConnection, id: i32) -> Result<trash_item::Model, HttpResponse> {
+    match trash_item::Entity::find_by_id(id).one(db).await {
+        Ok(Some(item)) => Ok(item),
+        Ok(None) => Err(HttpResponse::NotFound().body("Not in the trash")),
+        Err(err) => Err(internal_error("Error fetching trash item", err)),
+    }
+}
+
+// Everyone who can manage files sees what was deleted from the folders they could
+// manage it in; only admins can delete things for good before the retention runs out.
+#[get("/trash")]
+pub async fn index(
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageFiles) {
+        return resp;
+    }
+    let items = trash_item::Entity::find()
+        .order_by_desc(trash_item::Column::DeletedAt)
+        .all(db.get_ref())
+        .await;
+    let users = user::Entity::find().all(db.get_ref()).await;
+    let (items, users) = match (items, users) {
+        (Ok(items), Ok(users)) => (items, users),
+        (Err(err), _) | (_, Err(err)) => return internal_error("Error loading the trash", err),
+    };
+    let username = |id: i32| users.iter().find(|u| u.id == id).map(|u| u.username.as_str()).unwrap_or("?");
+    let retention_days = get_trash_retention_days(db.get_ref()).await;
+    let can_purge = user.can(Permission::ManageUsers);
+
+    let mut html = String::new();
+    if user.can(Permission::ManageSettings) {
+        html += &format!(
+            "<div class='card mb-4'><div class='card-header'>Retention</div><div class='card-body'>\
+            <form action='/trash/retention' method='post' class='d-flex gap-2 align-items-center'>\
+            <span>Delete items for good after</span><input type='number' name='days' value='{}' min='1' max='{}' class='form-control form-control-sm w-auto' required>\
+            <span>days</span><button type='submit' class='btn btn-primary btn-sm'>Save</button></form></div></div>",
+            retention_days, MAX_RETENTION_DAYS
+        );
+    }
+
+    html += "<div class='card mb-4'><div class='card-header'>Trash</div><ul class='list-group list-group-flush'>";
+    let shown: Vec<_> = items
+        .iter()
+        .filter(|item| user.can_at(Permission::ManageFiles, &item.original_path))
+        .collect();
+    if shown.is_empty() {
+        html += "<li class='list-group-item text-muted'>The trash is empty.</li>";
+    }
+    for item in shown {
+        let days_left = (item.deleted_at + retention_secs(retention_days) - now()).max(0) / (24 * 3600);
+        let purge_button = if can_purge {
+            format!(
+                "<form action='/trash/{}/purge' method='post' data-path='{}' onsubmit=\"return confirm('Delete ' + this.dataset.path + ' for good?')\">\
+                <button type='submit' class='btn btn-outline-danger btn-sm'>Delete forever</button></form>",
+                item.id, escape(&item.original_path)
+            )
+        } else {
+            String::new()
+        };
+        html += &format!(
+            "<li class='list-group-item d-flex align-items-center gap-2'><span class='badge bg-secondary'>{kind}</span><code>/{path}</code>\
+            <small class='text-muted me-auto'>deleted by {by} {at} · purged in {days_left} days</small>\
+            <form action='/trash/{id}/restore' method='post'><button type='submit' class='btn btn-primary btn-sm'>Restore</button></form>{purge_button}</li>",
+            kind = if item.is_dir { "folder" } else { "file" },
+            path = escape(&item.original_path),
+            by = escape(username(item.deleted_by)),
+            at = iso8601(item.deleted_at),
+            days_left = days_left,
+            id = item.id,
+            purge_button = purge_button,
+        );
+    }
+    html += "</ul></div>";
+
+    let page = ADMIN_HTML.replace("{{title}}", "Trash").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+// Back to the folder it was restored into.
+#[post("/trash/{id}/restore")]
+pub async fn restore(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let item = match find_item(db.get_ref(), *id).await {
+        Ok(item) => item,
+        Err(resp) => return resp,
+    };
+    if let Err(resp) = user.require_at(Permission::ManageFiles, &item.original_path) {
+        return resp;
+    }
+    match restore_path(db.get_ref(), &libraries, &item).await {
+        Ok(restored) => HttpResponse::SeeOther()
+            .append_header(("Location", format!("/{}", restored.parent_relative())))
+            .finish(),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+#[post("/trash/{id}/purge")]
+pub async fn purge(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let item = match find_item(db.get_ref(), *id).await {
+        Ok(item) => item,
+        Err(resp) => return resp,
+    };
+    match purge_path(db.get_ref(), &libraries, &item).await {
+        Ok(()) => back_to_trash(),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RetentionForm {
+    pub days: i64,
+}
+
+#[post("/trash/retention")]
+pub async fn retention(
+    form: web::Form<RetentionForm>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageSettings) {
+        return resp;
+    }
+    if !(1..=MAX_RETENTION_DAYS).contains(&form.days) {
+        return HttpResponse::BadRequest().body(format!("Items have to be kept between 1 and {} days", MAX_RETENTION_DAYS));
+    }
+    if let Err(err) = set_trash_retention_days(db.get_ref(), form.days).await {
+        return internal_error("Error saving trash retention", err);
+    }
+    back_to_trash()
+}
+
+pub fn trash_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index);
+    cfg.service(restore);
+    cfg.service(purge);
+    cfg.service(retention);
+}
@@ -0,0 +1,424 @@
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, patch, post, route, web, HttpRequest, HttpResponse, HttpResponseBuilder};
+use base64::Engine as _;
+use futures_util::stream::StreamExt as _;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sea_orm::sea_query::Expr;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::sync::{LazyLock, Mutex};
+use crate::models::{file_checksum, upload_session};
+use crate::models::user::Permission;
+use crate::utils::auth::{random_token, CurrentUser};
+use crate::utils::checksums::{hash_file, Digest, ExpectedChecksum, Hasher};
+use crate::utils::libraries::Libraries;
+use crate::utils::paths::ServedPath;
+use crate::utils::uploads::{create_staging_file, place_file, remove_staging_file, upload_target, ConflictPolicy};
+use crate::utils::time::now;
+
+// Resumable uploads, following the tus 1.0 core protocol with the creation and
+// termination extensions: POST creates an upload, HEAD reports how much has arrived,
+// PATCH appends a chunk at that offset and DELETE abandons it.
+
+const TUS_VERSION: &str = "1.0.0";
+
+// Uploads with a PATCH currently writing to them; a second one gets 423 Locked.
+static IN_PROGRESS: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+// Hashes of the bytes received so far, so a finished upload doesn't have to be read again.
+// Lost on restart; the next chunk then re-reads what's in the staging file.
+static HASHERS: LazyLock<Mutex<HashMap<String, Hasher>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// The tus checksum extension's status for a file that doesn't match its checksum.
+const CHECKSUM_MISMATCH: u16 = 460;
+
+struct ChunkLock(String);
+
+impl ChunkLock {
+    fn acquire(token: &str) -> Option<ChunkLock> {
+        let mut in_progress = IN_PROGRESS.lock().unwrap_or_else(|e| e.into_inner());
+        in_progress.insert(token.to_string()).then(|| ChunkLock(token.to_string()))
+    }
+}
+
+impl Drop for ChunkLock {
+    fn drop(&mut self) {
+        IN_PROGRESS.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.0);
+    }
+}
+
+fn tus(status: StatusCode) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::build(status);
+    builder.insert_header(("Tus-Resumable", TUS_VERSION));
+    builder
+}
+
+fn internal_error(context: &str, err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}: {}", context, err);
+    tus(StatusCode::INTERNAL_SERVER_ERROR).body("Internal server error")
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+// `Upload-Metadata: filename d29ybGQubXA0,folder cHJvamVjdHM=`
+fn metadata_value(req: &HttpRequest, key: &str) -> Option<String> {
+    header(req, "Upload-Metadata")?
+        .split(',')
+        .filter_map(|pair| pair.trim().split_once(' ').or(Some((pair.trim(), ""))))
+        .find(|(k, _)| *k == key)
+        .and_then(|(_, v)| base64::engine::general_purpose::STANDARD.decode(v.trim()).ok())
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+}
+
+fn staging_len(staging: &ServedPath) -> Option<u64> {
+    fs::metadata(&staging.absolute).ok().map(|m| m.len())
+}
+
+/// Remove an upload's row and staging file, and the staging folder once it's empty.
+pub async fn discard(db: &DatabaseConnection, libraries: &Libraries, upload: &upload_session::Model) -> Result<(), sea_orm::DbErr> {
+    HASHERS.lock().unwrap_or_else(|e| e.into_inner()).remove(&upload.token);
+    if let Ok(staging) = libraries.resolve(&upload.staging) {
+        remove_staging_file(&staging);
+    }
+    upload_session::Entity::delete_by_id(upload.id).exec(db).await?;
+    Ok(())
+}
+
+// Finish the upload once all bytes are in. Returns the response for the final request.
+async fn complete(
+    db: &DatabaseConnection,
+    libraries: &Libraries,
+    upload: &upload_session::Model,
+    staging: &ServedPath,
+    digest: Digest,
+) -> HttpResponse {
+    let target = match libraries.resolve(&upload.path) {
+        Ok(target) => target,
+        Err(e) => return e.response(),
+    };
+    let mismatch = upload
+        .checksum
+        .as_deref()
+        .and_then(|checksum| ExpectedChecksum::parse(checksum).ok())
+        .and_then(|expected| expected.check(&digest).err());
+    let result = match &mismatch {
+        Some(_) => Err(ErrorKind::InvalidData.into()),
+        None => place_file(libraries, &staging.absolute, target.clone(), upload.conflict()),
+    };
+    if let Err(err) = discard(db, libraries, upload).await {
+        eprintln!("Error removing finished upload {}: {}", upload.token, err);
+    }
+    if let Some(mismatch) = mismatch {
+        let status = StatusCode::from_u16(CHECKSUM_MISMATCH).unwrap_or(StatusCode::BAD_REQUEST);
+        return tus(status).body(mismatch);
+    }
+    if let Ok(placed) = &result
+        && let Err(err) = file_checksum::Model::record(db, &placed.relative, &digest, now()).await
+    {
+        eprintln!("Error recording checksum of {}: {}", placed.relative, err);
+    }
+    match result {
+        // Tell the client where the file ended up, in case it was renamed.
+        Ok(placed) => tus(StatusCode::NO_CONTENT)
+            .insert_header(("Upload-Offset", upload.length.to_string()))
+            .insert_header(("Content-Location", placed.url()))
+            .finish(),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            tus(StatusCode::CONFLICT).body(format!("{} already exists", target.url()))
+        }
+        Err(e) => internal_error("Error finishing upload", e),
+    }
+}
+
+async fn find_upload(db: &DatabaseConnection, token: &str, user: &CurrentUser) -> Result<upload_session::Model, HttpResponse> {
+    match upload_session::Entity::find()
+        .filter(upload_session::Column::Token.eq(token))
+        .filter(upload_session::Column::UserId.eq(user.user.id))
+        .one(db)
+        .await
+    {
+        Ok(Some(upload)) => Ok(upload),
+        Ok(None) => Err(tus(StatusCode::NOT_FOUND).body("No such upload")),
+        Err(err) => Err(internal_error("Error fetching upload", err)),
+    }
+}
+
+#[route("/uploads", method = "OPTIONS")]
+pub async fn options() -> HttpResponse {
+    tus(StatusCode::NO_CONTENT)
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", "creation,termination"))
+        .finish()
+}
+
+#[post("/uploads")]
+pub async fn create(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let length = match header(&req, "Upload-Length").map(|v| v.parse::<u64>()) {
+        Some(Ok(length)) if length <= i64::MAX as u64 => length,
+        _ => return tus(StatusCode::BAD_REQUEST).body("Upload-Length is required"),
+    };
+    let Some(filename) = metadata_value(&req, "filename") else {
+        return tus(StatusCode::BAD_REQUEST).body("Upload-Metadata needs a filename");
+    };
+    let conflict = match metadata_value(&req, "conflict") {
+        Some(conflict) => match ConflictPolicy::from_str_case_insensitive(&conflict) {
+            Some(conflict) => conflict,
+            None => return tus(StatusCode::BAD_REQUEST).body("conflict must be skip, rename or overwrite"),
+        },
+        None => ConflictPolicy::Skip,
+    };
+    // Checked once the last byte is in; a mismatch discards the upload.
+    let checksum = metadata_value(&req, "checksum");
+    if let Some(checksum) = &checksum
+        && let Err(e) = ExpectedChecksum::parse(checksum)
+    {
+        return tus(StatusCode::BAD_REQUEST).body(e);
+    }
+    let folder = match libraries.resolve(&metadata_value(&req, "folder").unwrap_or_default()) {
+        Ok(folder) => folder,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::Upload, &folder.relative) {
+        return resp;
+    }
+    if !folder.absolute.is_dir() {
+        return tus(StatusCode::NOT_FOUND).body("No such folder");
+    }
+    // `filename` may be a relative path when a whole folder is uploaded.
+    let target = match upload_target(&libraries, &folder, &filename, &user) {
+        Ok(target) => target,
+        Err(e) => return tus(StatusCode::BAD_REQUEST).body(e),
+    };
+    if conflict == ConflictPolicy::Skip && target.absolute.exists() {
+        return tus(StatusCode::CONFLICT).body(format!("{} already exists", target.url()));
+    }
+
+    let token = random_token();
+    let staging = match create_staging_file(&libraries, target.parent_relative()) {
+        Ok(staging) => staging,
+        Err(e) => return internal_error("Error creating upload staging file", e),
+    };
+
+    let created_at = now();
+    let upload = upload_session::ActiveModel {
+        token: Set(token.clone()),
+        user_id: Set(user.user.id),
+        path: Set(target.relative),
+        staging: Set(staging.relative.clone()),
+        length: Set(length as i64),
+        conflict: Set(conflict.as_str().to_string()),
+        checksum: Set(checksum),
+        created_at: Set(created_at),
+        updated_at: Set(created_at),
+        ..Default::default()
+    };
+    let upload = match upload.insert(db.get_ref()).await {
+        Ok(upload) => upload,
+        Err(err) => {
+            remove_staging_file(&staging);
+            return internal_error("Error creating upload", err);
+        }
+    };
+
+    // Nothing to send for an empty file, so it's done already.
+    if length == 0 {
+        let resp = complete(db.get_ref(), &libraries, &upload, &staging, Hasher::default().finish()).await;
+        if !resp.status().is_success() {
+            return resp;
+        }
+    }
+    tus(StatusCode::CREATED)
+        .insert_header(("Location", format!("/uploads/{}", token)))
+        .insert_header(("Upload-Offset", "0"))
+        .finish()
+}
+
+#[route("/uploads/{token}", method = "HEAD")]
+pub async fn head(
+    token: web::Path<String>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let upload = match find_upload(db.get_ref(), &token, &user).await {
+        Ok(upload) => upload,
+        Err(resp) => return resp,
+    };
+    let offset = libraries.resolve(&upload.staging).ok().and_then(|staging| staging_len(&staging));
+    match offset {
+        Some(offset) => tus(StatusCode::OK)
+            .insert_header(("Upload-Offset", offset.to_string()))
+            .insert_header(("Upload-Length", upload.length.to_string()))
+            .insert_header(("Cache-Control", "no-store"))
+            .finish(),
+        None => tus(StatusCode::NOT_FOUND).finish(),
+    }
+}
+
+// The same progress as HEAD, as JSON for scripts.
+#[get("/uploads/{token}")]
+pub async fn show(
+    token: web::Path<String>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let upload = match find_upload(db.get_ref(), &token, &user).await {
+        Ok(upload) => upload,
+        Err(resp) => return resp,
+    };
+    let offset = libraries.resolve(&upload.staging).ok().and_then(|staging| staging_len(&staging));
+    HttpResponse::Ok().json(serde_json::json!({
+        "path": upload.path,
+        "offset": offset,
+        "length": upload.length,
+    }))
+}
+
+#[patch("/uploads/{token}")]
+pub async fn append(
+    req: HttpRequest,
+    token: web::Path<String>,
+    mut body: web::Payload,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if header(&req, "Content-Type") != Some("application/offset+octet-stream") {
+        return tus(StatusCode::UNSUPPORTED_MEDIA_TYPE).body("Content-Type must be application/offset+octet-stream");
+    }
+    let Some(Ok(offset)) = header(&req, "Upload-Offset").map(|v| v.parse::<u64>()) else {
+        return tus(StatusCode::BAD_REQUEST).body("Upload-Offset is required");
+    };
+    let upload = match find_upload(db.get_ref(), &token, &user).await {
+        Ok(upload) => upload,
+        Err(resp) => return resp,
+    };
+    // Grants can change while a long upload is running.
+    if let Err(resp) = user.require_at(Permission::Upload, &upload.path) {
+        return resp;
+    }
+    let Some(_lock) = ChunkLock::acquire(&upload.token) else {
+        return tus(StatusCode::LOCKED).body("Another chunk is being written");
+    };
+    let staging = match libraries.resolve(&upload.staging) {
+        Ok(staging) => staging,
+        Err(e) => return e.response(),
+    };
+    let Some(current) = staging_len(&staging) else {
+        return tus(StatusCode::NOT_FOUND).body("No such upload");
+    };
+    if offset != current {
+        return tus(StatusCode::CONFLICT)
+            .insert_header(("Upload-Offset", current.to_string()))
+            .body("Upload-Offset does not match the bytes received");
+    }
+
+    let length = upload.length as u64;
+    if let Some(Ok(chunk_len)) = header(&req, "Content-Length").map(|v| v.parse::<u64>())
+        && offset + chunk_len > length
+    {
+        return tus(StatusCode::PAYLOAD_TOO_LARGE).body("Chunk runs past Upload-Length");
+    }
+
+    let cached = HASHERS.lock().unwrap_or_else(|e| e.into_inner()).remove(&upload.token);
+    let mut hasher = match cached {
+        Some(hasher) if hasher.size() == current => hasher,
+        _ => {
+            let path = staging.absolute.clone();
+            match web::block(move || hash_file(&path)).await {
+                Ok(Ok(hasher)) => hasher,
+                Ok(Err(e)) => return internal_error("Error hashing upload staging file", e),
+                Err(e) => return internal_error("Error hashing upload staging file", e),
+            }
+        }
+    };
+
+    let mut file = match fs::OpenOptions::new().append(true).open(&staging.absolute) {
+        Ok(file) => file,
+        Err(e) => return internal_error("Error opening upload staging file", e),
+    };
+    let mut written = current;
+    let mut failure = None;
+    while let Some(chunk) = body.next().await {
+        let data = match chunk {
+            Ok(data) => data,
+            // The client went away; keep what arrived so it can resume from there.
+            Err(e) => {
+                failure = Some(tus(StatusCode::BAD_REQUEST).body(format!("Upload interrupted: {}", e)));
+                break;
+            }
+        };
+        if written + data.len() as u64 > length {
+            failure = Some(tus(StatusCode::PAYLOAD_TOO_LARGE).body("Chunk runs past Upload-Length"));
+            break;
+        }
+        if let Err(e) = file.write_all(&data) {
+            failure = Some(internal_error("Error writing upload chunk", e));
+            break;
+        }
+        hasher.update(&data);
+        written += data.len() as u64;
+    }
+    // Cut back anything a failed write left half done, so the offset stays exact.
+    if let Err(e) = file.flush().and_then(|_| file.set_len(written)) {
+        return internal_error("Error saving upload chunk", e);
+    }
+    drop(file);
+
+    if let Err(err) = upload_session::Entity::update_many()
+        .col_expr(upload_session::Column::UpdatedAt, Expr::value(now()))
+        .filter(upload_session::Column::Id.eq(upload.id))
+        .exec(db.get_ref())
+        .await
+    {
+        eprintln!("Error touching upload {}: {}", upload.token, err);
+    }
+    if written == length && failure.is_none() {
+        return complete(db.get_ref(), &libraries, &upload, &staging, hasher.finish()).await;
+    }
+    HASHERS.lock().unwrap_or_else(|e| e.into_inner()).insert(upload.token.clone(), hasher);
+    if let Some(resp) = failure {
+        return resp;
+    }
+    tus(StatusCode::NO_CONTENT)
+        .insert_header(("Upload-Offset", written.to_string()))
+        .finish()
+}
+
+#[delete("/uploads/{token}")]
+pub async fn terminate(
+    token: web::Path<String>,
+    db: web::Data<DatabaseConnection>,
+    libraries: web::Data<Libraries>,
+    user: CurrentUser,
+) -> HttpResponse {
+    let upload = match find_upload(db.get_ref(), &token, &user).await {
+        Ok(upload) => upload,
+        Err(resp) => return resp,
+    };
+    let Some(_lock) = ChunkLock::acquire(&upload.token) else {
+        return tus(StatusCode::LOCKED).body("A chunk is being written");
+    };
+    match discard(db.get_ref(), &libraries, &upload).await {
+        Ok(()) => tus(StatusCode::NO_CONTENT).finish(),
+        Err(err) => internal_error("Error removing upload", err),
+    }
+}
+
+pub fn uploads_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(options);
+    cfg.service(create);
+    cfg.service(head);
+    cfg.service(show);
+    cfg.service(append);
+    cfg.service(terminate);
+}
@@ -0,0 +1,304 @@
+use actix_web::{get, post, web, HttpResponse};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set};
+use serde::Deserialize;
+use crate::models::invite;
+use crate::models::settings::SignupMode;
+use crate::models::user::{self, Permission, Role};
+use crate::utils::auth::{random_token, CurrentUser};
+use crate::utils::database::{get_signup_mode, set_signup_mode};
+use crate::utils::html::escape;
+use crate::utils::time::now;
+
+const ADMIN_HTML: &str = include_str!("../views/admin/index.html");
+
+/// Longest an invite can stay open, in days.
+const MAX_INVITE_DAYS: i64 = 365;
+
+fn back_to_users() -> HttpResponse {
+    HttpResponse::SeeOther().append_header(("Location", "/admin/users")).finish()
+}
+
+fn role_options(selected: Option<Role>) -> String {
+    Role::ALL
+        .iter()
+        .map(|role| {
+            format!(
+                "<option value='{0}'{1}>{0}</option>",
+                role.as_str(),
+                if Some(*role) == selected { " selected" } else { "" }
+            )
+        })
+        .collect()
+}
+
+// User management page: signup mode, approval queue, accounts and invites.
+#[get("/admin/users")]
+pub async fn index(
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+
+    let users = match user::Entity::find().order_by_asc(user::Column::Username).all(db.get_ref()).await {
+        Ok(users) => users,
+        Err(err) => {
+            eprintln!("Error fetching users: {}", err);
+            return HttpResponse::InternalServerError().body("Internal server error");
+        }
+    };
+    let invites = invite::Entity::find()
+        .order_by_desc(invite::Column::CreatedAt)
+        .all(db.get_ref())
+        .await
+        .unwrap_or_default();
+    let mode = get_signup_mode(db.get_ref()).await;
+
+    let mut html = String::new();
+
+    // Signup mode
+    html += "<div class='card mb-4'><div class='card-header'>Signups</div><div class='card-body'>";
+    html += "<form action='/admin/signup_mode' method='post' class='d-flex gap-2'><select name='mode' class='form-select w-auto'>";
+    for option in SignupMode::ALL {
+        html += &format!(
+            "<option value='{0}'{1}>{0}</option>",
+            option.as_str(),
+            if option == mode { " selected" } else { "" }
+        );
+    }
+    html += "</select><button type='submit' class='btn btn-primary'>Save</button></form></div></div>";
+
+    // Pending approval
+    let pending: Vec<_> = users.iter().filter(|u| u.is_pending()).collect();
+    if !pending.is_empty() {
+        html += "<div class='card mb-4'><div class='card-header'>Awaiting approval</div><ul class='list-group list-group-flush'>";
+        for u in pending {
+            html += &format!(
+                "<li class='list-group-item d-flex align-items-center gap-2'><b class='me-auto'>{username}</b>\
+                <form action='/admin/users/{id}/access' method='post' class='d-flex gap-2'><select name='access_level' class='form-select form-select-sm'>{options}</select>\
+                <button type='submit' class='btn btn-success btn-sm'>Approve</button></form>\
+                <form action='/admin/users/{id}/delete' method='post'><button type='submit' class='btn btn-outline-danger btn-sm'>Reject</button></form></li>",
+                id = u.id,
+                username = escape(&u.username),
+                options = role_options(Some(Role::Viewer)),
+            );
+        }
+        html += "</ul></div>";
+    }
+
+    // Accounts
+    html += "<div class='card mb-4'><div class='card-header'>Users</div><ul class='list-group list-group-flush'>";
+    for u in users.iter().filter(|u| !u.is_pending()) {
+        let controls = if u.id == user.user.id {
+            "<span class='text-muted'>(you)</span>".to_string()
+        } else {
+            format!(
+                "<form action='/admin/users/{id}/access' method='post' class='d-flex gap-2'><select name='access_level' class='form-select form-select-sm'>{options}</select>\
+                <button type='submit' class='btn btn-primary btn-sm'>Change</button></form>\
+                <form action='/admin/users/{id}/delete' method='post' data-username='{username}' onsubmit=\"return confirm('Delete ' + this.dataset.username + '?')\"><button type='submit' class='btn btn-outline-danger btn-sm'>Delete</button></form>",
+                id = u.id,
+                username = escape(&u.username),
+                options = role_options(u.role()),
+            )
+        };
+        html += &format!(
+            "<li class='list-group-item d-flex align-items-center gap-2'><b>{}</b> <span class='badge bg-secondary me-auto'>{}</span>{}</li>",
+            escape(&u.username), u.access_level, controls
+        );
+    }
+    html += "</ul></div>";
+
+    // Invites
+    let now = now();
+    html += "<div class='card mb-4'><div class='card-header'>Invites</div><div class='card-body'>";
+    html += &format!(
+        "<form action='/admin/invites' method='post' class='d-flex gap-2 align-items-center'>\
+        <label>Role</label><select name='access_level' class='form-select form-select-sm w-auto'>{}</select>\
+        <label>Expires in</label><input type='number' name='expires_in_days' value='7' min='1' max='{}' class='form-control form-control-sm' style='max-width:80px'> days\
+        <button type='submit' class='btn btn-success btn-sm'>Create invite</button></form></div><ul class='list-group list-group-flush'>",
+        role_options(Some(Role::Viewer)),
+        MAX_INVITE_DAYS
+    );
+    for inv in invites {
+        let state = if inv.used_at.is_some() {
+            let used_by = inv.used_by
+                .and_then(|id| users.iter().find(|u| u.id == id))
+                .map(|u| escape(&u.username))
+                .unwrap_or_else(|| "a deleted user".to_string());
+            format!("<span class='text-muted'>Used by {}</span>", used_by)
+        } else if inv.expires_at <= now {
+            "<span class='text-muted'>Expired</span>".to_string()
+        } else {
+            format!(
+                "<code>/signup?invite={}</code> <small class='text-muted'>expires in {}h</small>",
+                inv.token,
+                (inv.expires_at - now) / 3600
+            )
+        };
+        html += &format!(
+            "<li class='list-group-item d-flex align-items-center gap-2'><span class='badge bg-info'>{}</span> <span class='me-auto'>{}</span>\
+            <form action='/admin/invites/{}/delete' method='post'><button type='submit' class='btn btn-link text-danger btn-sm'>&times;</button></form></li>",
+            inv.access_level, state, inv.id
+        );
+    }
+    html += "</ul></div>";
+
+    let page = ADMIN_HTML.replace("{{title}}", "Users").replace("{{contents}}", &html);
+    HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[derive(Deserialize)]
+pub struct AccessForm {
+    pub access_level: String,
+}
+
+// Approve a pending account or change an existing account's role.
+#[post("/admin/users/{id}/access")]
+pub async fn set_access(
+    id: web::Path<i32>,
+    form: web::Form<AccessForm>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let role = match Role::from_access_level(&form.access_level) {
+        Some(role) => role,
+        None => return HttpResponse::BadRequest().body("Unknown access level"),
+    };
+    if *id == user.user.id {
+        return HttpResponse::BadRequest().body("You cannot change your own access level");
+    }
+
+    let target = match user::Entity::find_by_id(*id).one(db.get_ref()).await {
+        Ok(Some(target)) => target,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(err) => {
+            eprintln!("Error fetching user: {}", err);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+    let mut am: user::ActiveModel = target.into();
+    am.access_level = Set(role.as_str().to_string());
+    if let Err(err) = am.update(db.get_ref()).await {
+        eprintln!("Error updating user: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to update user");
+    }
+    back_to_users()
+}
+
+#[post("/admin/users/{id}/delete")]
+pub async fn delete(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    if *id == user.user.id {
+        return HttpResponse::BadRequest().body("You cannot delete your own account");
+    }
+    if let Err(err) = user::Model::delete_account(db.get_ref(), *id).await {
+        eprintln!("Error deleting user: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to delete user");
+    }
+    back_to_users()
+}
+
+#[derive(Deserialize)]
+pub struct SignupModeForm {
+    pub mode: String,
+}
+
+#[post("/admin/signup_mode")]
+pub async fn signup_mode(
+    form: web::Form<SignupModeForm>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageSettings) {
+        return resp;
+    }
+    let mode = match SignupMode::from_str_case_insensitive(&form.mode) {
+        Some(mode) => mode,
+        None => return HttpResponse::BadRequest().body("Unknown signup mode"),
+    };
+    if let Err(err) = set_signup_mode(db.get_ref(), mode).await {
+        eprintln!("Error saving signup mode: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to save signup mode");
+    }
+    back_to_users()
+}
+
+#[derive(Deserialize)]
+pub struct InviteForm {
+    pub access_level: String,
+    pub expires_in_days: i64,
+}
+
+#[post("/admin/invites")]
+pub async fn create_invite(
+    form: web::Form<InviteForm>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    let role = match Role::from_access_level(&form.access_level) {
+        Some(role) => role,
+        None => return HttpResponse::BadRequest().body("Unknown access level"),
+    };
+    if !(1..=MAX_INVITE_DAYS).contains(&form.expires_in_days) {
+        return HttpResponse::BadRequest().body(format!("Invites must last between 1 and {} days", MAX_INVITE_DAYS));
+    }
+
+    let now = now();
+    let new_invite = invite::ActiveModel {
+        token: Set(random_token()),
+        access_level: Set(role.as_str().to_string()),
+        created_by: Set(user.user.id),
+        created_at: Set(now),
+        expires_at: Set(now + form.expires_in_days * 24 * 3600),
+        used_by: Set(None),
+        used_at: Set(None),
+        ..Default::default()
+    };
+    if let Err(err) = new_invite.insert(db.get_ref()).await {
+        eprintln!("Error creating invite: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to create invite");
+    }
+    back_to_users()
+}
+
+#[post("/admin/invites/{id}/delete")]
+pub async fn delete_invite(
+    id: web::Path<i32>,
+    db: web::Data<DatabaseConnection>,
+    user: CurrentUser,
+) -> HttpResponse {
+    if let Err(resp) = user.require(Permission::ManageUsers) {
+        return resp;
+    }
+    if let Err(err) = invite::Entity::delete_many()
+        .filter(invite::Column::Id.eq(*id))
+        .exec(db.get_ref())
+        .await
+    {
+        eprintln!("Error deleting invite: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to delete invite");
+    }
+    back_to_users()
+}
+
+pub fn users_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index);
+    cfg.service(set_access);
+    cfg.service(delete);
+    cfg.service(signup_mode);
+    cfg.service(create_invite);
+    cfg.service(delete_invite);
+}
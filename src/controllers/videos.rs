@@ -1,19 +1,51 @@
 use actix_web::{get, web, HttpResponse, HttpRequest, Responder};
-use std::path::{PathBuf, Path};
+use std::path::Path;
 use actix_web::http::header;
 use std::fs;
+use crate::models::user::Permission;
+use crate::utils::auth::CurrentUser;
+use crate::utils::html::escape;
+use crate::utils::libraries::Libraries;
 
 const SHOW_HTML: &str = include_str!("../views/videos/show.html");
+const PANEL_HTML: &str = include_str!("../views/videos/panel.html");
 
 
 #[get("/videos/{video_path:.*}")]
-pub async fn show(video_path: web::Path<PathBuf>) -> HttpResponse {
-    let filename = format!("/{}", video_path.display());
-    let html = SHOW_HTML.replace("{{filename}}", &filename);
+pub async fn show(video_path: web::Path<String>, libraries: web::Data<Libraries>, user: CurrentUser) -> HttpResponse {
+    let video = match libraries.resolve(&video_path) {
+        Ok(video) => video,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::View, &video.relative) {
+        return resp;
+    }
+    let share = if user.can_at(Permission::Share, &video.relative) {
+        format!(
+            "<a class=\"btn btn-outline-secondary mt-3 w-100\" href=\"/shares?path={}\">Share</a>",
+            urlencoding::encode(&video.relative)
+        )
+    } else {
+        String::new()
+    };
+    let panel = PANEL_HTML.trim_end().replace("{{share}}", &share);
+    let html = player_html(&video.url(), &video.url(), "/", Some(&panel));
 
     HttpResponse::Ok().content_type("text/html").body(html)
 }
 
+/// The video player page. Without a `panel` (clips, points, tags and the clip maker)
+/// it is a plain player, as used for share links.
+pub fn player_html(title: &str, src: &str, home: &str, panel: Option<&str>) -> String {
+    SHOW_HTML
+        .replace("{{panel}}", panel.unwrap_or(""))
+        .replace("{{editable}}", if panel.is_some() { "true" } else { "false" })
+        .replace("{{title}}", &escape(title))
+        .replace("{{src}}", &escape(src))
+        .replace("{{home}}", &escape(home))
+        .replace("{{filename}}", &escape(title))
+}
+
 // Utility: get sorted list of video files in the same directory, and find next/prev
 fn get_sorted_videos_and_index(current_path: &Path) -> Option<(Vec<String>, usize)> {
     let video_extensions = ["mp4", "avi", "mov", "mkv", "webm"];
@@ -28,44 +60,42 @@ fn get_sorted_videos_and_index(current_path: &Path) -> Option<(Vec<String>, usiz
         })
         .map(|e| e.file_name().to_string_lossy().to_string())
         .collect();
-    entries.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()));
+    entries.sort_by_key(|a| a.to_lowercase());
     let file_name = current_path.file_name()?.to_string_lossy();
     let idx = entries.iter().position(|n| n == &file_name)?;
     Some((entries, idx))
 }
 
-#[get("/videos/{video_path:.*}/next")]
-pub async fn next(_req: HttpRequest, video_path: web::Path<PathBuf>) -> impl Responder {
-    let current = video_path.into_inner();
-    let abs_path = Path::new("").join(&current);
-    if let Some((files, idx)) = get_sorted_videos_and_index(&abs_path) {
-        let next_idx = if idx + 1 < files.len() { idx + 1 } else { idx };
-        let next_file = &files[next_idx];
-        let new_path = abs_path.parent().unwrap_or(Path::new("")).join(next_file);
-        let rel_path = new_path.strip_prefix("").unwrap_or(&new_path);
-        let url = format!("/videos/{}", rel_path.display());
-        return HttpResponse::Found().append_header((header::LOCATION, url)).finish();
+// Redirect to the video `offset` places away in the same folder, clamped to the ends.
+fn step(libraries: &Libraries, user: &CurrentUser, video_path: &str, offset: isize) -> HttpResponse {
+    let current = match libraries.resolve(video_path) {
+        Ok(current) => current,
+        Err(e) => return e.response(),
+    };
+    if let Err(resp) = user.require_at(Permission::View, &current.relative) {
+        return resp;
+    }
+    let mut url = format!("/videos/{}", current.relative);
+    if let Some((files, idx)) = get_sorted_videos_and_index(&current.absolute) {
+        let new_idx = idx.saturating_add_signed(offset).min(files.len() - 1);
+        let parent = current.parent_relative();
+        url = if parent.is_empty() {
+            format!("/videos/{}", files[new_idx])
+        } else {
+            format!("/videos/{}/{}", parent, files[new_idx])
+        };
     }
-    // fallback: redirect to current
-    let url = format!("/videos/{}", abs_path.display());
     HttpResponse::Found().append_header((header::LOCATION, url)).finish()
 }
 
+#[get("/videos/{video_path:.*}/next")]
+pub async fn next(_req: HttpRequest, video_path: web::Path<String>, libraries: web::Data<Libraries>, user: CurrentUser) -> impl Responder {
+    step(&libraries, &user, &video_path, 1)
+}
+
 #[get("/videos/{video_path:.*}/prev")]
-pub async fn prev(_req: HttpRequest, video_path: web::Path<PathBuf>) -> impl Responder {
-    let current = video_path.into_inner();
-    let abs_path = Path::new("").join(&current);
-    if let Some((files, idx)) = get_sorted_videos_and_index(&abs_path) {
-        let prev_idx = if idx > 0 { idx - 1 } else { idx };
-        let prev_file = &files[prev_idx];
-        let new_path = abs_path.parent().unwrap_or(Path::new("")).join(prev_file);
-        let rel_path = new_path.strip_prefix("").unwrap_or(&new_path);
-        let url = format!("/videos/{}", rel_path.display());
-        return HttpResponse::Found().append_header((header::LOCATION, url)).finish();
-    }
-    // fallback: redirect to current
-    let url = format!("/videos/{}", abs_path.display());
-    HttpResponse::Found().append_header((header::LOCATION, url)).finish()
+pub async fn prev(_req: HttpRequest, video_path: web::Path<String>, libraries: web::Data<Libraries>, user: CurrentUser) -> impl Responder {
+    step(&libraries, &user, &video_path, -1)
 }
 
 pub fn video_routes(cfg: &mut web::ServiceConfig) {
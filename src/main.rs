@@ -3,17 +3,19 @@ mod models;
 mod tools;
 mod utils;
 use actix_web::{web, App, HttpServer};
-use tools::conversions::process_conversion_queue;
+use tools::conversions::{process_conversion_queue, RunningConversions};
+use tools::trash::purge_expired_trash;
+use tools::uploads::collect_abandoned_uploads;
+use tools::watcher::watch_media;
 use tokio::sync::watch;
-use actix_web::cookie::Key;
-use actix_web::middleware::Logger;
+use actix_web::middleware::{from_fn, Logger};
 use actix_session::SessionMiddleware;
 use dotenvy::from_path;
 use std::env;
 use std::process::Command;
-use controllers::login::is_logged_in;
 use utils::args::handle_args;
-use utils::database::get_ffmpeg_path;
+use utils::database::{get_ffmpeg_path, get_libraries};
+use utils::libraries::Libraries;
 use utils::ssl::get_certificates;
 use utils::redirect::redirect_to_https;
 
@@ -53,7 +55,7 @@ async fn main() {
     });
 
     let args: Vec<String> = env::args().collect();
-    handle_args(&args, &db).await;
+    let cli = handle_args(&args, &db).await;
 
     // Check for FFMPEG.
     let ffmpeg_path = get_ffmpeg_path(&db).await.or_else(|| std::env::var("FFMPEG_PATH").ok());
@@ -67,8 +69,19 @@ async fn main() {
         std::process::exit(1);
     }
 
-    let folder = env::current_dir().unwrap();
+    let folder = match cli.folder {
+        Some(folder) => folder,
+        None => env::current_dir().unwrap(),
+    };
+    if !folder.is_dir() {
+        eprintln!("Error: {:?} is not a folder.", folder);
+        std::process::exit(1);
+    }
     println!("Serving folder: {:?}", folder);
+    let libraries = Libraries::new(folder, get_libraries(&db).await);
+    for library in &libraries.named {
+        println!("Serving library /{}: {:?}", library.name, library.path);
+    }
 
     let (cert_path, key_path) = get_certificates().unwrap_or_else(|e| {
         eprintln!("Failed to prepare certificates: {}", e);
@@ -77,9 +90,19 @@ async fn main() {
 
     let tls_config = load_rustls_config(&cert_path, &key_path);
 
+    let session_key = utils::session_key::load_or_create().unwrap_or_else(|e| {
+        eprintln!("Failed to load session key: {}", e);
+        std::process::exit(1);
+    });
+
     let db_for_worker = db.clone();
+    let db_for_uploads = db.clone();
+    let db_for_trash = db.clone();
+    let db_for_watcher = db.clone();
     let db_data = web::Data::new(db);
-    let folder_data = web::Data::new(folder);
+    let libraries_data = web::Data::new(libraries.clone());
+    let running_conversions = web::Data::new(RunningConversions::default());
+    let running_data = running_conversions.clone();
 
     // Set up a shutdown signal using a watch channel
     let (shutdown_tx, shutdown_rx) = watch::channel(false);
@@ -89,22 +112,38 @@ async fn main() {
         App::new()
             .wrap(Logger::default())
             .app_data(db_data.clone())
-            .app_data(folder_data.clone())
+            .app_data(libraries_data.clone())
+            .app_data(running_data.clone())
+            // Registered before the session middleware so it runs inside it.
+            .wrap(from_fn(utils::auth::require_login))
             .wrap(
                 SessionMiddleware::builder(
                     actix_session::storage::CookieSessionStore::default(),
-                    Key::from(&[0; 64]), // left exactly as you had it
+                    session_key.clone(),
                 )
                 .cookie_secure(true)
                 .build(),
             )
+            .configure(controllers::shares::shares_routes) // Before the `{path}/clips` style routes.
             .configure(controllers::ai::ai_routes)
+            .configure(controllers::archives::archives_routes)
+            .configure(controllers::checksums::checksums_routes)
             .configure(controllers::clips::clips_routes)
+            .configure(controllers::conversions::conversions_routes)
+            .configure(controllers::dropboxes::dropboxes_routes)
             .configure(controllers::points::points_routes)
+            .configure(controllers::renditions::renditions_routes)
             .configure(controllers::tags::tags_routes)
             .configure(controllers::login::login_routes)
+            .configure(controllers::manage::manage_routes)
+            .configure(controllers::media::media_routes)
             .configure(controllers::search::search_routes)
+            .configure(controllers::sharing::sharing_routes)
             .configure(controllers::signup::signup_routes)
+            .configure(controllers::tokens::tokens_routes)
+            .configure(controllers::trash::trash_routes)
+            .configure(controllers::uploads::uploads_routes)
+            .configure(controllers::users::users_routes)
             .configure(controllers::videos::video_routes)
             .configure(controllers::files::files_routes) // Must be last.
     });
@@ -124,8 +163,29 @@ async fn main() {
     .run();
 
     // Start the conversion queue processor as a background task, pass shutdown_rx
+    let libraries_for_uploads = libraries.clone();
+    let uploads_rx = shutdown_rx.clone();
+    let libraries_for_trash = libraries.clone();
+    let trash_rx = shutdown_rx.clone();
+    let libraries_for_watcher = libraries.clone();
+    let watcher_rx = shutdown_rx.clone();
     let conversion_worker = tokio::spawn(async move {
-        process_conversion_queue(&db_for_worker, shutdown_rx).await;
+        process_conversion_queue(&db_for_worker, &libraries, &running_conversions, shutdown_rx).await;
+    });
+
+    // Clean up resumable uploads that were never finished
+    let uploads_collector = tokio::spawn(async move {
+        collect_abandoned_uploads(&db_for_uploads, &libraries_for_uploads, uploads_rx).await;
+    });
+
+    // Empty the trash of anything older than the retention period
+    let trash_purger = tokio::spawn(async move {
+        purge_expired_trash(&db_for_trash, &libraries_for_trash, trash_rx).await;
+    });
+
+    // Index media as it's added, moved or deleted, with an hourly rescan to catch up
+    let media_watcher = tokio::spawn(async move {
+        watch_media(&db_for_watcher, &libraries_for_watcher, watcher_rx).await;
     });
 
     // Listen for shutdown signals (Ctrl+C or SIGTERM)
@@ -178,4 +238,13 @@ async fn main() {
         eprintln!("Conversion worker task failed: {}", e);
         std::process::exit(1);
     }
+    if let Err(e) = uploads_collector.await {
+        eprintln!("Upload cleanup task failed: {}", e);
+    }
+    if let Err(e) = trash_purger.await {
+        eprintln!("Trash purge task failed: {}", e);
+    }
+    if let Err(e) = media_watcher.await {
+        eprintln!("Media watcher task failed: {}", e);
+    }
 }
@@ -0,0 +1,96 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use sha2::{Digest, Sha256};
+use crate::models::user::Permission;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "api_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub token_hash: String, // SHA-256 of the token; the token itself is only shown once
+    pub scope: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Prefix on every token so they are easy to spot in scripts and logs.
+pub const TOKEN_PREFIX: &str = "rc_";
+
+/// Only write last_used_at when it is older than this, to avoid a write per request.
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+/// What a token may do, on top of (never beyond) its owner's role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    /// Browse, stream, download and search.
+    Read,
+    /// Read, plus upload, clips, points, tags and folders.
+    Write,
+    /// Everything the owner can do, including user management and settings.
+    Admin,
+}
+
+impl TokenScope {
+    pub const ALL: [TokenScope; 3] = [TokenScope::Read, TokenScope::Write, TokenScope::Admin];
+
+    pub fn from_str_case_insensitive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "read" => Some(TokenScope::Read),
+            "write" => Some(TokenScope::Write),
+            "admin" => Some(TokenScope::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+            TokenScope::Admin => "admin",
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        match self {
+            TokenScope::Read => matches!(permission, Permission::View),
+            TokenScope::Write => !matches!(permission, Permission::ManageUsers | Permission::ManageSettings),
+            TokenScope::Admin => true,
+        }
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+impl Model {
+    pub fn scope(&self) -> Option<TokenScope> {
+        TokenScope::from_str_case_insensitive(&self.scope)
+    }
+
+    /// Look up an unrevoked token by its plaintext value, noting that it was used.
+    pub async fn find_active(db: &DatabaseConnection, token: &str, now: i64) -> Result<Option<Model>, DbErr> {
+        let found = Entity::find()
+            .filter(Column::TokenHash.eq(hash_token(token)))
+            .filter(Column::RevokedAt.is_null())
+            .one(db)
+            .await?;
+        if let Some(found) = &found
+            && found.last_used_at.is_none_or(|t| now - t >= LAST_USED_RESOLUTION_SECS)
+        {
+            let mut am: ActiveModel = found.clone().into();
+            am.last_used_at = Set(Some(now));
+            am.update(db).await?;
+        }
+        Ok(found)
+    }
+}
@@ -16,6 +16,10 @@ pub struct Model {
     pub end: i64,
     pub name: Option<String>,
     pub description: Option<String>,
+    #[serde(skip)]
+    pub deleted_at: Option<i64>, // set while the video is in the trash
+    #[serde(skip)]
+    pub media_id: Option<i32>, // the file it belongs to, which `source_filename` follows
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -30,7 +34,7 @@ impl Model {
         fs::create_dir_all(&path)?; // ensure .clips directory exists
         path.push(format!("{}.json", self.id));
         let json = serde_json::to_string_pretty(self)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(std::io::Error::other)?;
         fs::write(path, json)?;
         Ok(())
     }
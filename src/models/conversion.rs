@@ -1,11 +1,23 @@
-use crate::models::{conversion, tag};
-use sea_orm::{ActiveModelTrait, Set};
+use crate::models::{file_checksum, media, tag};
+use sea_orm::{ActiveModelTrait, DbBackend, Set, Statement, UpdateMany};
 use sea_orm::EntityTrait;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
+use crate::models::probe::Probe;
+use crate::models::rendition::{self, PROXIES_DIR};
+use crate::utils::checksums::hash_file_reporting;
+use crate::utils::ffmpeg;
+use crate::utils::libraries::Libraries;
+use crate::utils::time::now;
 
-// Operations: Thumbnail, Scaledown, Makeclip, Categorize
-// Status: Pending, Running, Completed, Failed
+// Operations: Thumbnail, Scaledown, Makeclip, Categorize, Verify, Probe
+// Status: Pending, Running, Completed, Failed, Cancelled
+
+/// Attempts a conversion gets before it's marked failed.
+pub const MAX_TRIES: i32 = 5;
+
+/// The error recorded for a try whose worker stopped without saying how it went.
+const WORKER_STOPPED: &str = "The server stopped or the worker hung before it finished";
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, DeriveEntityModel)]
 #[sea_orm(table_name = "conversions")]
@@ -18,7 +30,25 @@ pub struct Model {
     pub time_requested: i64,
     pub time_completed: Option<i64>,
     pub status: String,
-    pub times_tried: i32,
+    pub times_tried: i32, // the attempt that's running or due next, from 1
+    #[serde(skip)]
+    pub media_id: Option<i32>,
+    pub time_started: Option<i64>, // of the latest attempt
+    // A running conversion's worker pushes this forward while it's alive; once it has
+    // passed, the worker is taken to have died and the conversion goes back in the queue.
+    #[serde(skip)]
+    pub lease_expires_at: Option<i64>,
+    pub retry_at: Option<i64>, // a pending conversion that failed waits until then
+    pub progress: Option<f64>, // percent done, while running, for operations that can tell
+    pub eta: Option<i64>,      // when a running conversion should be done
+    // Why the latest try failed, kept until one succeeds.
+    pub error_kind: Option<String>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub command: Option<String>, // the ffmpeg command line, when it was ffmpeg that failed
+    #[serde(skip)]
+    pub error_output: Option<String>, // the end of ffmpeg's error output
+    pub priority: i32, // higher goes first, 0 by default
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -26,230 +56,494 @@ pub enum Relation {}
 
 impl ActiveModelBehavior for ActiveModel {}
 
+/// `update` also recording why a try failed.
+fn record(update: UpdateMany<Entity>, failure: &Failure) -> UpdateMany<Entity> {
+    update
+        .col_expr(Column::ErrorKind, Expr::value(failure.kind.to_value()))
+        .col_expr(Column::Error, Expr::value(failure.message.clone()))
+        .col_expr(Column::Command, Expr::value(failure.command.clone()))
+        .col_expr(Column::ErrorOutput, Expr::value(failure.output.clone()))
+}
+
+/// Seconds to wait after try number `times_tried` failed, longer after each one:
+/// 30 seconds, then a minute, two, and so on up to an hour.
+fn retry_delay(times_tried: i32) -> i64 {
+    (30i64 << (times_tried - 1).clamp(0, 7)).min(3600)
+}
+
+/// Why a conversion didn't finish.
+#[derive(Debug)]
+pub struct Failure {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Whether it might work later, e.g. ffmpeg crashed or the AI service was down, and is
+    /// tried again after a while. Otherwise it won't work however often it's tried.
+    pub retry: bool,
+    pub command: Option<String>,
+    pub output: Option<String>,
+}
+
+impl Failure {
+    pub fn retry(message: impl Into<String>) -> Self {
+        Failure { kind: ErrorKind::Other, message: message.into(), retry: true, command: None, output: None }
+    }
+
+    pub fn permanent(message: impl Into<String>) -> Self {
+        Failure { retry: false, ..Failure::retry(message) }
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<DbErr> for Failure {
+    fn from(e: DbErr) -> Self {
+        Failure::retry(format!("Database error: {}", e))
+    }
+}
+
+impl From<ffmpeg::Error> for Failure {
+    fn from(e: ffmpeg::Error) -> Self {
+        Failure {
+            // A file ffmpeg can't find or can't decode won't get any better.
+            retry: !matches!(e.kind, ErrorKind::MissingInput | ErrorKind::UnsupportedCodec),
+            kind: e.kind,
+            message: e.message,
+            command: Some(e.command),
+            output: Some(e.output),
+        }
+    }
+}
+
 impl Model {
-    /// Request a conversion operation. Returns true if a new conversion was created, false if one already exists.
-    /// If a conversion exists but was requested over 1 hour ago, creates a new one with incremented times_tried.
+    /// Request a conversion operation. Returns true if a new conversion was created, false if one
+    /// is already queued or running; the runner takes care of conversions whose worker died.
     pub async fn request_conversion(
         db: &DatabaseConnection,
         source_filename: String,
         operation: String,
     ) -> Result<bool, sea_orm::DbErr> {
         use sea_orm::{ColumnTrait, QueryFilter};
-        
+
+        // Files get a media id once the watcher has seen them; until then a rescan links them.
+        let media_id = media::Model::find_by_path(db, &source_filename).await?.map(|media| media.id);
+
         // Check for existing conversion with same source_filename and operation
         let existing = Entity::find()
             .filter(Column::SourceFilename.eq(&source_filename))
             .filter(Column::Operation.eq(&operation))
-            .filter(
-                Column::Status.eq("pending")
-                    .or(Column::Status.eq("running"))
-            )
+            .filter(Column::Status.is_in([Status::Pending.to_value(), Status::Running.to_value()]))
             .one(db)
             .await?;
-        
-        if let Some(existing_conversion) = existing {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_secs() as i64)
-                .unwrap_or(0);
-            
-            let time_diff = now - existing_conversion.time_requested;
-            
-            // If less than 1 hour (3600 seconds), don't create a new one
-            if time_diff < 3600 {
-                println!("Conversion already pending/running for {} ({}), skipping", source_filename, operation);
-                return Ok(false);
-            }
-            
-            // More than 1 hour old, create a new one with incremented times_tried
-            println!("Existing conversion for {} ({}) is over 1 hour old, creating new attempt", source_filename, operation);
-            let new_conversion = ActiveModel {
-                source_filename: Set(source_filename),
-                operation: Set(operation),
-                time_requested: Set(now),
-                time_completed: Set(None),
-                status: Set("pending".to_string()),
-                times_tried: Set(existing_conversion.times_tried + 1),
-                ..Default::default()
-            };
-            
-            new_conversion.insert(db).await?;
-            return Ok(true);
+        if existing.is_some() {
+            println!("Conversion already pending/running for {} ({}), skipping", source_filename, operation);
+            return Ok(false);
         }
-        
-        // No existing conversion, create a new one
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs() as i64)
-            .unwrap_or(0);
-        
+
         let new_conversion = ActiveModel {
             source_filename: Set(source_filename),
             operation: Set(operation),
-            time_requested: Set(now),
+            time_requested: Set(now()),
             time_completed: Set(None),
-            status: Set("pending".to_string()),
+            status: Set(Status::Pending.to_value()),
             times_tried: Set(1),
+            media_id: Set(media_id),
             ..Default::default()
         };
-        
+
         new_conversion.insert(db).await?;
         Ok(true)
     }
 
-    pub async fn process(&self, db: &DatabaseConnection) -> Result<(), sea_orm::DbErr> {
+    /// Take the pending `operation` that's due with the highest priority, oldest first, marking it running with a lease of
+    /// `lease` seconds. Done in one statement, so two workers never get the same one.
+    pub async fn claim(db: &DatabaseConnection, operation: &str, lease: i64) -> Result<Option<Model>, DbErr> {
+        let now = now();
+        Entity::find()
+            .from_raw_sql(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                "UPDATE conversions SET status = 'running', time_started = ?1, lease_expires_at = ?2, progress = NULL, eta = NULL \
+                WHERE id = (SELECT id FROM conversions WHERE status = 'pending' AND operation = ?3 \
+                AND (retry_at IS NULL OR retry_at <= ?1) ORDER BY priority DESC, time_requested, id LIMIT 1) RETURNING *",
+                [now.into(), (now + lease).into(), operation.into()],
+            ))
+            .one(db)
+            .await
+    }
+
+    /// An update of this conversion that only applies while the claim it was handed out
+    /// with stands: it's still running, not cancelled or finished, and hasn't been put
+    /// back in the queue and claimed again since, which would have moved `time_started`.
+    fn update_claimed(&self) -> UpdateMany<Entity> {
+        Entity::update_many()
+            .filter(Column::Id.eq(self.id))
+            .filter(Column::Status.eq(Status::Running.to_value()))
+            .filter(Column::TimeStarted.eq(self.time_started))
+    }
+
+    /// Run an `update_claimed`, leaving the conversion be when it's no longer ours.
+    async fn finish_claimed(&self, db: &DatabaseConnection, update: UpdateMany<Entity>) {
+        match update.exec(db).await {
+            Ok(result) if result.rows_affected == 0 => {
+                println!("Conversion id {} was cancelled or handed to another worker, leaving it as it is", self.id);
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to update conversion status: {}", e),
+        }
+    }
+
+    /// Keep the lease of a running conversion for another `lease` seconds.
+    pub async fn heartbeat(&self, db: &DatabaseConnection, lease: i64) -> Result<(), DbErr> {
+        self.update_claimed()
+            .col_expr(Column::LeaseExpiresAt, Expr::value(now() + lease))
+            .exec(db)
+            .await
+            .map(|_| ())
+    }
+
+    /// Record how far along a running conversion is.
+    pub async fn report_progress(&self, db: &DatabaseConnection, progress: ffmpeg::Progress) {
+        let result = self.update_claimed()
+            .col_expr(Column::Progress, Expr::value(progress.percent))
+            .col_expr(Column::Eta, Expr::value(progress.eta))
+            .exec(db)
+            .await;
+        if let Err(e) = result {
+            eprintln!("Failed to update conversion progress: {}", e);
+        }
+    }
+
+    /// Put running conversions whose lease has run out, because the server stopped or the
+    /// worker hung, back in the queue as another try. Returns how many there were.
+    pub async fn requeue_expired(db: &DatabaseConnection) -> Result<u64, DbErr> {
+        let now = now();
+        let failed = db
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                "UPDATE conversions SET status = 'failed', time_completed = ?1, lease_expires_at = NULL, \
+                error_kind = 'other', error = ?3, command = NULL, error_output = NULL \
+                WHERE status = 'running' AND lease_expires_at < ?1 AND times_tried >= ?2",
+                [now.into(), MAX_TRIES.into(), WORKER_STOPPED.into()],
+            ))
+            .await?;
+        let requeued = db
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                "UPDATE conversions SET status = 'pending', times_tried = times_tried + 1, lease_expires_at = NULL, \
+                error_kind = 'other', error = ?2, command = NULL, error_output = NULL \
+                WHERE status = 'running' AND (lease_expires_at IS NULL OR lease_expires_at < ?1)",
+                [now.into(), WORKER_STOPPED.into()],
+            ))
+            .await?;
+        Ok(failed.rows_affected() + requeued.rows_affected())
+    }
+
+    /// Give a running conversion back to the queue as it was, e.g. when the server shuts down.
+    pub async fn release(&self, db: &DatabaseConnection) {
+        let update = self.update_claimed()
+            .col_expr(Column::Status, Expr::value(Status::Pending.to_value()))
+            .col_expr(Column::LeaseExpiresAt, Expr::value(Option::<i64>::None));
+        self.finish_claimed(db, update).await;
+    }
+
+    /// Back in the queue after a failed try, waiting `retry_delay` seconds.
+    pub async fn retry_later(&self, db: &DatabaseConnection, failure: &Failure) {
+        let delay = retry_delay(self.times_tried);
+        let update = record(self.update_claimed(), failure)
+            .col_expr(Column::Status, Expr::value(Status::Pending.to_value()))
+            .col_expr(Column::TimesTried, Expr::value(self.times_tried + 1))
+            .col_expr(Column::RetryAt, Expr::value(now() + delay))
+            .col_expr(Column::LeaseExpiresAt, Expr::value(Option::<i64>::None));
+        self.finish_claimed(db, update).await;
+    }
+
+    /// Stop conversion `id` if it's pending or running; its worker, if any, is told
+    /// separately. Returns whether there was one to stop.
+    pub async fn cancel(db: &DatabaseConnection, id: i32) -> Result<bool, DbErr> {
+        let result = Entity::update_many()
+            .col_expr(Column::Status, Expr::value(Status::Cancelled.to_value()))
+            .col_expr(Column::TimeCompleted, Expr::value(now()))
+            .col_expr(Column::LeaseExpiresAt, Expr::value(Option::<i64>::None))
+            .col_expr(Column::Eta, Expr::value(Option::<i64>::None))
+            .filter(Column::Id.eq(id))
+            .filter(Column::Status.is_in([Status::Pending.to_value(), Status::Running.to_value()]))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Queue failed or cancelled conversion `id` again, starting over at the first try.
+    /// Returns whether there was one to retry.
+    pub async fn retry(db: &DatabaseConnection, id: i32) -> Result<bool, DbErr> {
+        let result = Entity::update_many()
+            .col_expr(Column::Status, Expr::value(Status::Pending.to_value()))
+            .col_expr(Column::TimesTried, Expr::value(1))
+            .col_expr(Column::RetryAt, Expr::value(Option::<i64>::None))
+            .col_expr(Column::TimeCompleted, Expr::value(Option::<i64>::None))
+            .col_expr(Column::Progress, Expr::value(Option::<f64>::None))
+            .filter(Column::Id.eq(id))
+            .filter(Column::Status.is_in([Status::Failed.to_value(), Status::Cancelled.to_value()]))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Returns whether conversion `id` exists.
+    pub async fn set_priority(db: &DatabaseConnection, id: i32, priority: i32) -> Result<bool, DbErr> {
+        let result = Entity::update_many()
+            .col_expr(Column::Priority, Expr::value(priority))
+            .filter(Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Delete completed conversions, only of `operation` if given. Returns how many.
+    pub async fn delete_completed(db: &DatabaseConnection, operation: Option<&str>) -> Result<u64, DbErr> {
+        let mut query = Entity::delete_many().filter(Column::Status.eq(Status::Completed.to_value()));
+        if let Some(operation) = operation {
+            query = query.filter(Column::Operation.eq(operation));
+        }
+        Ok(query.exec(db).await?.rows_affected)
+    }
+
+    /// Do the work of a claimed conversion. Recording how it went is up to the caller.
+    pub async fn process(&self, db: &DatabaseConnection, libraries: &Libraries) -> Result<(), Failure> {
+        // source_filename is a URL path; find the file in the library it belongs to.
+        let source = libraries
+            .resolve(&self.source_filename)
+            .map_err(|e| Failure {
+                kind: ErrorKind::MissingInput,
+                ..Failure::permanent(format!("Can't resolve {}: {}", self.source_filename, e))
+            })?;
+        let source_str = source.absolute.to_string_lossy().to_string();
         match Operation::from_str_case_insensitive(&self.operation) {
             Some(Operation::Thumbnail) => {
-                use std::path::Path;
-                
                 // Get ffmpeg path from environment or database
-                let ffmpeg_path = crate::utils::database::get_ffmpeg_path(db).await
-                    .or_else(|| std::env::var("FFMPEG_PATH").ok())
-                    .ok_or_else(|| sea_orm::DbErr::Custom("FFMPEG_PATH not defined".into()))?;
-                
+                let ffmpeg_path = ffmpeg_path(db).await?;
+
                 // Determine output path: source_filename -> source_filename/thumbs/filename.webp
-                let source_path = Path::new(&self.source_filename);
-                let parent = source_path.parent().ok_or_else(|| sea_orm::DbErr::Custom("Invalid source path".into()))?;
-                let file_stem = source_path.file_stem().ok_or_else(|| sea_orm::DbErr::Custom("Invalid filename".into()))?;
-                
+                let source_path = source.absolute.as_path();
+                let parent = source_path.parent().ok_or_else(|| Failure::permanent("Invalid source path"))?;
+                let file_name = source_path.file_name().ok_or_else(|| Failure::permanent("Invalid filename"))?;
+
                 let thumbs_dir = parent.join("thumbs");
                 if !thumbs_dir.exists() {
-                    std::fs::create_dir_all(&thumbs_dir).map_err(|e| sea_orm::DbErr::Custom(format!("Failed to create thumbs directory: {}", e)))?;
+                    std::fs::create_dir_all(&thumbs_dir).map_err(|e| Failure::retry(format!("Failed to create thumbs directory: {}", e)))?;
                 }
-                
-                let output_path = thumbs_dir.join(format!("{}.webp", file_stem.to_string_lossy()));
+
+                // Named after the full filename, which is what the listing links to.
+                let output_path = thumbs_dir.join(format!("{}.webp", file_name.to_string_lossy()));
                 let output_path_str = output_path.to_string_lossy().to_string();
-                
+
                 println!("Generating thumbnail: {} -> {}", self.source_filename, output_path_str);
-                
+
                 // Use the Thumb::generate function
-                match crate::models::thumb::Thumb::generate(&self.source_filename, &output_path_str, &ffmpeg_path) {
-                    Ok(_) => {
-                        // Update conversion status to completed
-                        let mut am: conversion::ActiveModel = self.clone().into();
-                        let now = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .map(|d| d.as_secs() as i64)
-                            .unwrap_or(0);
-                        am.status = Set("completed".to_string());
-                        am.time_completed = Set(Some(now));
-                        if let Err(e) = am.update(db).await {
-                            eprintln!("Failed to update conversion status: {}", e);
-                        }
-                        println!("Thumbnail generated successfully: {}", output_path_str);
+                crate::models::thumb::Thumb::generate(&source_str, &output_path_str, &ffmpeg_path)
+                    .await?;
+                println!("Thumbnail generated successfully: {}", output_path_str);
+            }
+            Some(Operation::Scaledown) => {
+                // Make each enabled proxy preset into `proxies/` beside the source.
+                let ffmpeg_path = ffmpeg_path(db).await?;
+                let presets = crate::utils::database::get_proxy_presets(db).await;
+                if presets.is_empty() {
+                    return Err(Failure::permanent("No proxy presets are enabled; see --set-proxy-presets"));
+                }
+                let proxies_dir = source.absolute.with_file_name(PROXIES_DIR);
+                std::fs::create_dir_all(&proxies_dir).map_err(|e| Failure::retry(format!("Failed to create proxies directory: {}", e)))?;
+                let existing = rendition::Model::for_source(db, &source.relative).await?;
+                let duration = source_duration(db, &source.relative).await;
+
+                let started = std::time::Instant::now();
+                let count = presets.len() as f64;
+                for (done, preset) in presets.into_iter().enumerate() {
+                    let output_path = proxies_dir.join(preset.file_name(source.file_name()));
+                    if existing.iter().any(|made| made.preset == preset.name) && output_path.is_file() {
+                        continue;
                     }
-                    Err(e) => {
-                        eprintln!("Thumbnail generation failed: {}", e);
-                        // Update conversion status to failed
-                        let mut am: conversion::ActiveModel = self.clone().into();
-                        let now = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .map(|d| d.as_secs() as i64)
-                            .unwrap_or(0);
-                        am.status = Set("failed".to_string());
-                        am.time_completed = Set(Some(now));
-                        if let Err(e) = am.update(db).await {
-                            eprintln!("Failed to update conversion status: {}", e);
-                        }
-                        return Err(sea_orm::DbErr::Custom(e));
+                    // Written under another name until it's whole, keeping the extension
+                    // ffmpeg picks the format by.
+                    let partial_path = proxies_dir.join(format!("partial-{}", preset.file_name(source.file_name())));
+                    let partial_str = partial_path.to_string_lossy().to_string();
+                    println!("Making {} proxy: {} -> {}", preset.label, self.source_filename, output_path.display());
+
+                    // Progress is over all the presets, each taken to be as much work.
+                    let report = |progress: ffmpeg::Progress| {
+                        let fraction = (done as f64 + progress.percent.unwrap_or(0.0) / 100.0) / count;
+                        self.report_progress(db, ffmpeg::Progress::at(fraction, started.elapsed().as_secs_f64()))
+                    };
+                    if let Err(e) = ffmpeg::run(&ffmpeg_path, &preset.args(&source_str, &partial_str), duration, report).await {
+                        let _ = std::fs::remove_file(&partial_path);
+                        return Err(e.into());
                     }
+                    std::fs::rename(&partial_path, &output_path)
+                        .map_err(|e| Failure::retry(format!("Failed to move {} into place: {}", output_path.display(), e)))?;
+
+                    let size = std::fs::metadata(&output_path).map(|meta| meta.len() as i64).unwrap_or(0);
+                    let output_str = output_path.to_string_lossy().to_string();
+                    let dimensions = match Probe::run(&output_str, &ffmpeg_path).await {
+                        Ok(Probe { width: Some(width), height: Some(height), .. }) => Some((width, height)),
+                        _ => None,
+                    };
+                    rendition::Model::record(db, &source.relative, preset, size, dimensions, now()).await?;
                 }
             }
-            Some(Operation::Scaledown) => {
-                // TODO: Implement scaledown logic
-            }
             Some(Operation::Makeclip) => {
                 // TODO: Implement makeclip logic
+                return Err(Failure::permanent("Makeclip isn't implemented yet"));
             }
             Some(Operation::Categorize) => {
-                use std::process::{Command, Stdio};
                 use std::path::Path;
                 // Extract a frame at 1s using ffmpeg
-                let ffmpeg_path = std::env::var("FFMPEG_PATH").map_err(|_| sea_orm::DbErr::Custom("FFMPEG_PATH not defined in environment".into()))?;
+                let ffmpeg_path = std::env::var("FFMPEG_PATH").map_err(|_| Failure::permanent("FFMPEG_PATH not defined in environment"))?;
                 let output_dir = Path::new("segments/ai/conversions");
                 if !output_dir.exists() {
-                    std::fs::create_dir_all(output_dir).map_err(|e| sea_orm::DbErr::Custom(format!("Failed to create output directory: {}", e)))?;
+                    std::fs::create_dir_all(output_dir).map_err(|e| Failure::retry(format!("Failed to create output directory: {}", e)))?;
                 }
                 let output_path = output_dir.join(format!("{}.jpg", self.id));
                 let output_path_str = output_path.to_string_lossy().to_string();
-                let args = vec![
+                let args: Vec<String> = [
+                    "-y",
                     "-ss", "1",
-                    "-i", &self.source_filename,
+                    "-i", &source_str,
                     "-frames:v", "1",
                     "-q:v", "2",
                     &output_path_str,
-                ];
-                let status = Command::new(&ffmpeg_path)
-                    .args(&args)
-                    .stdin(Stdio::null())
-                    .stdout(Stdio::null())
-                    .stderr(Stdio::null())
-                    .status()
-                    .map_err(|e| sea_orm::DbErr::Custom(format!("Failed to start ffmpeg: {}", e)))?;
-                if !status.success() {
-                    return Err(sea_orm::DbErr::Custom(format!("ffmpeg failed with exit code: {}", status.code().unwrap_or(-1))));
-                }
+                ]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect();
+                ffmpeg::run(&ffmpeg_path, &args, None, ffmpeg::ignore).await?;
                 // Call the AI tagging function on output_path
                 // The AI tagging function expects a URL, so you may need to construct a URL to the image
                 // For now, assume the server is running on localhost and port 443 (HTTPS)
                 let image_url = format!("https://media.aiowa.com/categorize/{}.jpg", self.id);
-                match crate::tools::ai::tag_image(&image_url).await {
-                    Ok(tags) => {
-                        for tag_str in &tags.tags {
-                            let tag_model = tag::ActiveModel::new(self.source_filename.clone(), tag_str.clone());
-                            let tag_model_check = tag::Model {
-                                id: 0, // id is not used in is_duplicate
-                                source_filename: self.source_filename.clone(),
-                                tag: tag_str.clone(),
-                                slug: tag::Model::normalize_tag(tag_str),
-                            };
-                            if tag_model_check.is_duplicate(db).await.unwrap_or(false) {
-                                continue; // Skip duplicates
-                            }
-                            if let Err(e) = tag_model.insert(db).await {
-                                eprintln!("Failed to insert tag '{}': {}", tag_str, e);
-                            }
-                        }
-                        // Optionally, save tags.description somewhere as well
-                        println!("AI tags: {:?}", tags);
-                        // Update conversion status to completed
-                        let mut am: conversion::ActiveModel = self.clone().into();
-                        let now = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .map(|d| d.as_secs() as i64)
-                            .unwrap_or(0);
-                        am.status = Set("completed".to_string());
-                        am.time_completed = Set(Some(now));
-                        if let Err(e) = am.update(db).await {
-                            eprintln!("Failed to update conversion status: {}", e);
-                        }
+                let tags = crate::tools::ai::tag_image(&image_url)
+                    .await
+                    .map_err(|e| Failure::retry(format!("AI tagging failed: {}", e)))?;
+                for tag_str in &tags.tags {
+                    let mut tag_model = tag::ActiveModel::new(self.source_filename.clone(), tag_str.clone());
+                    tag_model.media_id = Set(self.media_id);
+                    let tag_model_check = tag::Model {
+                        id: 0, // id is not used in is_duplicate
+                        source_filename: self.source_filename.clone(),
+                        tag: tag_str.clone(),
+                        slug: tag::Model::normalize_tag(tag_str),
+                        deleted_at: None,
+                        media_id: self.media_id,
+                    };
+                    if tag_model_check.is_duplicate(db).await.unwrap_or(false) {
+                        continue; // Skip duplicates
                     }
-                    Err(e) => {
-                        eprintln!("AI tagging failed: {}", e);
-                        // Update conversion status to failed
-                        let mut am: conversion::ActiveModel = self.clone().into();
-                        let now = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .map(|d| d.as_secs() as i64)
-                            .unwrap_or(0);
-                        am.status = Set("failed".to_string());
-                        am.time_completed = Set(Some(now));
-                        if let Err(e) = am.update(db).await {
-                            eprintln!("Failed to update conversion status: {}", e);
+                    if let Err(e) = tag_model.insert(db).await {
+                        eprintln!("Failed to insert tag '{}': {}", tag_str, e);
+                    }
+                }
+                // Optionally, save tags.description somewhere as well
+                println!("AI tags: {:?}", tags);
+            }
+            Some(Operation::Verify) => {
+                // Re-hash the file and compare with what it hashed to when it was uploaded.
+                let stored = file_checksum::Model::find_by_path(db, &source.relative)
+                    .await?
+                    .ok_or_else(|| Failure::permanent(format!("No checksum stored for {}", source.relative)))?;
+                let path = source.absolute.clone();
+                let (tx, rx) = tokio::sync::watch::channel(0.0);
+                let mut hashing = tokio::task::spawn_blocking(move || {
+                    hash_file_reporting(&path, |fraction| {
+                        tx.send_replace(fraction);
+                    })
+                    .map(|hasher| hasher.finish())
+                });
+                // Camera files can take minutes to read, so say how far along it is.
+                let started = std::time::Instant::now();
+                let hashed = loop {
+                    tokio::select! {
+                        hashed = &mut hashing => break hashed.map_err(|e| Failure::retry(format!("Hashing task failed: {}", e)))?,
+                        _ = tokio::time::sleep(std::time::Duration::from_secs(1)) => {
+                            let fraction = *rx.borrow();
+                            self.report_progress(db, ffmpeg::Progress::at(fraction, started.elapsed().as_secs_f64())).await;
                         }
                     }
+                };
+                let status = match hashed {
+                    Ok(digest) if stored.matches(&digest) => file_checksum::Status::Ok,
+                    Ok(_) => file_checksum::Status::Corrupt,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => file_checksum::Status::Missing,
+                    Err(e) => return Err(Failure::retry(format!("Failed to read {}: {}", source.relative, e))),
+                };
+                if status != file_checksum::Status::Ok {
+                    eprintln!("Checksum verification: {} is {}", source.relative, status.as_str());
+                }
+                let mut checked: file_checksum::ActiveModel = stored.into();
+                checked.status = Set(status.as_str().to_string());
+                checked.verified_at = Set(Some(now()));
+                checked.update(db).await?;
+            }
+            Some(Operation::Probe) => {
+                // Record the video's duration and size on its media.
+                let ffmpeg_path = ffmpeg_path(db).await?;
+                let probe = Probe::run(&source_str, &ffmpeg_path).await?;
+                let found = media::Model::find_by_path(db, &source.relative)
+                    .await?
+                    .ok_or_else(|| Failure::permanent(format!("No media recorded for {}", source.relative)))?;
+                let mut probed: media::ActiveModel = found.into();
+                probed.duration = Set(probe.duration);
+                probed.width = Set(probe.width);
+                probed.height = Set(probe.height);
+                probed.update(db).await?;
+                // Camera originals too heavy to scrub through get lighter proxies.
+                let large = probe.width.is_some_and(|width| width > 1920) || probe.height.is_some_and(|height| height > 1080);
+                if large && !crate::utils::database::get_proxy_presets(db).await.is_empty() {
+                    Model::request_conversion(db, source.relative.clone(), Operation::Scaledown.to_value()).await?;
                 }
             }
             None => {
-                // Unknown operation
+                return Err(Failure::permanent(format!("Unknown operation {}", self.operation)));
             }
         }
         Ok(())
     }
-}
 
+    pub async fn mark_completed(&self, db: &DatabaseConnection) {
+        let update = self.update_claimed()
+            .col_expr(Column::Status, Expr::value(Status::Completed.to_value()))
+            .col_expr(Column::TimeCompleted, Expr::value(now()))
+            .col_expr(Column::LeaseExpiresAt, Expr::value(Option::<i64>::None))
+            .col_expr(Column::Progress, Expr::value(100.0))
+            .col_expr(Column::Eta, Expr::value(Option::<i64>::None))
+            .col_expr(Column::ErrorKind, Expr::value(Option::<String>::None))
+            .col_expr(Column::Error, Expr::value(Option::<String>::None))
+            .col_expr(Column::Command, Expr::value(Option::<String>::None))
+            .col_expr(Column::ErrorOutput, Expr::value(Option::<String>::None));
+        self.finish_claimed(db, update).await;
+    }
 
+    pub async fn mark_failed(&self, db: &DatabaseConnection, failure: &Failure) {
+        let update = record(self.update_claimed(), failure)
+            .col_expr(Column::Status, Expr::value(Status::Failed.to_value()))
+            .col_expr(Column::TimeCompleted, Expr::value(now()))
+            .col_expr(Column::LeaseExpiresAt, Expr::value(Option::<i64>::None))
+            .col_expr(Column::Eta, Expr::value(Option::<i64>::None));
+        self.finish_claimed(db, update).await;
+    }
+}
 
+/// How long the video at `path` is, if it's been probed.
+async fn source_duration(db: &DatabaseConnection, path: &str) -> Option<f64> {
+    media::Model::find_by_path(db, path).await.ok().flatten().and_then(|media| media.duration)
+}
+
+async fn ffmpeg_path(db: &DatabaseConnection) -> Result<String, Failure> {
+    crate::utils::database::get_ffmpeg_path(db)
+        .await
+        .or_else(|| std::env::var("FFMPEG_PATH").ok())
+        .ok_or_else(|| Failure::permanent("FFMPEG_PATH not defined"))
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
 #[sea_orm(rs_type = "String", db_type = "Text")]
@@ -262,6 +556,10 @@ pub enum Operation {
     Makeclip,
     #[sea_orm(string_value = "categorize")]
     Categorize,
+    #[sea_orm(string_value = "verify")]
+    Verify,
+    #[sea_orm(string_value = "probe")]
+    Probe,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
@@ -275,6 +573,24 @@ pub enum Status {
     Completed,
     #[sea_orm(string_value = "failed")]
     Failed,
+    #[sea_orm(string_value = "cancelled")]
+    Cancelled,
+}
+
+/// What went wrong with a failed conversion, as far as can be told.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Text")]
+pub enum ErrorKind {
+    #[sea_orm(string_value = "missing_input")]
+    MissingInput,
+    #[sea_orm(string_value = "unsupported_codec")]
+    UnsupportedCodec,
+    #[sea_orm(string_value = "disk_full")]
+    DiskFull,
+    #[sea_orm(string_value = "timeout")]
+    Timeout,
+    #[sea_orm(string_value = "other")]
+    Other,
 }
 
 impl Operation {
@@ -284,7 +600,37 @@ impl Operation {
             "scaledown" => Some(Operation::Scaledown),
             "makeclip" => Some(Operation::Makeclip),
             "categorize" => Some(Operation::Categorize),
+            "verify" => Some(Operation::Verify),
+            "probe" => Some(Operation::Probe),
             _ => None,
         }
     }
-}
\ No newline at end of file
+
+    /// How many of these run at once unless set otherwise with `--set-workers`.
+    pub fn default_workers(&self) -> usize {
+        match self {
+            Operation::Thumbnail | Operation::Probe => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_from_thirty_seconds() {
+        let delays: Vec<i64> = (1..=7).map(retry_delay).collect();
+        assert_eq!(delays, vec![30, 60, 120, 240, 480, 960, 1920]);
+    }
+
+    #[test]
+    fn retry_delay_is_capped_at_an_hour() {
+        assert_eq!(retry_delay(8), 3600);
+        assert_eq!(retry_delay(9), 3600);
+        assert_eq!(retry_delay(i32::MAX), 3600);
+        // A row that was never counted as tried waits like the first try.
+        assert_eq!(retry_delay(0), 30);
+    }
+}
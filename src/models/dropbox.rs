@@ -0,0 +1,102 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::Expr;
+
+/// A public `/d/{token}` upload form that drops files into one folder.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "dropboxes")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub token: String,
+    pub name: String,
+    pub path: String, // URL path of the target folder
+    pub max_bytes: Option<i64>,
+    pub allowed_extensions: String, // comma separated, lowercase, empty for any
+    pub expires_at: Option<i64>,
+    pub created_by: i32,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+    pub reserved_bytes: i64, // uploaded so far plus uploads in progress, counted against max_bytes
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Normalize "MP4, .mov braw" into "mp4,mov,braw".
+pub fn normalize_extensions(list: &str) -> String {
+    list.split([',', ' '])
+        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Model {
+    pub fn is_active(&self, now: i64) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+
+    pub fn extensions(&self) -> Vec<&str> {
+        self.allowed_extensions.split(',').filter(|ext| !ext.is_empty()).collect()
+    }
+
+    pub fn allows_file(&self, filename: &str) -> bool {
+        let extensions = self.extensions();
+        if extensions.is_empty() {
+            return true;
+        }
+        match filename.rsplit_once('.') {
+            Some((_, ext)) => extensions.contains(&ext.to_lowercase().as_str()),
+            None => false,
+        }
+    }
+
+    pub async fn find_active(db: &DatabaseConnection, token: &str, now: i64) -> Result<Option<Model>, DbErr> {
+        Ok(Entity::find()
+            .filter(Column::Token.eq(token))
+            .one(db)
+            .await?
+            .filter(|dropbox| dropbox.is_active(now)))
+    }
+
+    /// Set aside up to `wanted` bytes of the quota for an upload before writing it, and
+    /// return how many it got: all of them without a quota, none once it's used up.
+    /// Uploads running at the same time each get their own part of what's left.
+    pub async fn reserve(&self, db: &DatabaseConnection, wanted: u64) -> Result<u64, DbErr> {
+        let Some(max) = self.max_bytes else {
+            return Ok(wanted);
+        };
+        loop {
+            let reserved = Entity::find_by_id(self.id).one(db).await?.map(|dropbox| dropbox.reserved_bytes).unwrap_or(max);
+            let granted = (max - reserved).max(0).min(wanted.min(i64::MAX as u64) as i64);
+            if granted == 0 {
+                return Ok(0);
+            }
+            // Only if nobody reserved in between; otherwise look again.
+            let claimed = Entity::update_many()
+                .col_expr(Column::ReservedBytes, Expr::value(reserved + granted))
+                .filter(Column::Id.eq(self.id))
+                .filter(Column::ReservedBytes.eq(reserved))
+                .exec(db)
+                .await?;
+            if claimed.rows_affected == 1 {
+                return Ok(granted as u64);
+            }
+        }
+    }
+
+    /// Give back the part of a reservation an upload didn't use.
+    pub async fn release(&self, db: &DatabaseConnection, unused: u64) -> Result<(), DbErr> {
+        if self.max_bytes.is_none() || unused == 0 {
+            return Ok(());
+        }
+        Entity::update_many()
+            .col_expr(Column::ReservedBytes, Expr::col(Column::ReservedBytes).sub(unused.min(i64::MAX as u64) as i64))
+            .filter(Column::Id.eq(self.id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
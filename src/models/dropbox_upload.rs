@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+
+/// A file received through a drop-box, and who sent it.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "dropbox_uploads")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub dropbox_id: i32,
+    pub filename: String, // URL path of the stored file
+    pub size: i64,
+    pub uploader_name: String,
+    pub uploader_email: String,
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
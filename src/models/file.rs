@@ -1,37 +1,56 @@
+use crate::utils::html::{escape, url_path};
+
 pub struct File;
 
 impl File {
     pub fn video_preview(subpath: &str, video: &str) -> String {
-        let thumbnail_path = if !subpath.is_empty() {
-            format!("/{}/thumbs/{}.webp", subpath, video)
+        let (link, thumbnail_path) = if !subpath.is_empty() {
+            (format!("{}/{}", subpath, video), format!("/{}/thumbs/{}.webp", subpath, video))
         } else {
-            format!("/thumbs/{}.webp", video)
+            (video.to_string(), format!("/thumbs/{}.webp", video))
         };
         format!(
             "<a href='/videos/{link}' style='max-width:250px;display:inline-block;' class='video_preview'>\
             <img src='{thumbnail_path}' class='img-fluid rounded border' alt='{video}' style='width:100%;'>\
-            <div class='text-center text-white position-absolute mx-auto px-2 filename'>{filename}</div></a>",
-            link = if subpath.is_empty() { format!("/{}", video) } else { format!("/{}/{}", subpath, video) },
-            thumbnail_path = thumbnail_path,
-            video = video,
-            filename = video
+            <div class='text-center text-white position-absolute mx-auto px-2 filename'>{video}</div></a>",
+            link = url_path(&link),
+            thumbnail_path = url_path(&thumbnail_path),
+            video = escape(video),
         )
     }
 
-    pub fn file_preview(link: &str, file_name: &str, is_video: bool) -> String {
+    /// `manage` adds a link to rename, move, copy or delete the entry.
+    /// A line in a listing. `select` adds a checkbox for downloading several entries at once.
+    pub fn file_preview(link: &str, file_name: &str, is_video: bool, manage: bool, select: bool) -> String {
         let main_link = if is_video {
             format!("/videos{}", link)
         } else {
             link.to_string()
         };
         let extra_link = if is_video {
-            format!(" <a href='{}'>🎬</a>", link)
+            format!(" <a href='{}'>🎬</a>", escape(link))
+        } else {
+            "".to_string()
+        };
+        let manage_link = if manage {
+            format!(
+                " <a href='/manage?path={}' class='small text-muted ms-2'>manage</a>",
+                urlencoding::encode(link.trim_start_matches('/'))
+            )
+        } else {
+            "".to_string()
+        };
+        let checkbox = if select {
+            format!(
+                "<input type='checkbox' name='path' value='{}' form='archiveForm' class='form-check-input me-2'>",
+                escape(link.trim_start_matches('/'))
+            )
         } else {
             "".to_string()
         };
         format!(
-            "<li class='list-group-item'><a href='{}'>{}</a>{}</li>",
-            main_link, file_name, extra_link
+            "<li class='list-group-item'>{}<a href='{}'>{}</a>{}{}</li>",
+            checkbox, escape(&main_link), escape(file_name), extra_link, manage_link
         )
     }
 
@@ -45,8 +64,8 @@ impl File {
             {clip_filename} ({start}-{end})
             </a>
             </li>",
-            source_filename = clip.source_filename.clone(),
-            clip_filename = clip.clip_filename,
+            source_filename = escape(&clip.source_filename),
+            clip_filename = escape(&clip.clip_filename),
             start = clip.start,
             end = clip.end,
         )
@@ -83,10 +102,12 @@ impl File {
             <a href='{source_filename}'>
             {source_filename}
             </a> &gt; Tag {id}: {tag}
+            <a href='/archive?tag={slug}' class='small text-muted ms-2'>download all tagged</a>
             </li>",
-            source_filename = tag.source_filename.clone(),
+            source_filename = escape(&tag.source_filename),
             id = tag.id,
-            tag = tag.tag.clone()
+            tag = escape(&tag.tag),
+            slug = urlencoding::encode(&tag.slug),
         )
     }
 
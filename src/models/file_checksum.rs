@@ -0,0 +1,82 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use crate::utils::checksums::Digest;
+
+/// The hashes a file had when it was uploaded, and the outcome of the last check against them.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "file_checksums")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub path: String, // URL path of the file
+    pub size: i64,
+    pub xxh64: String,
+    pub sha256: String,
+    pub hashed_at: i64,
+    pub verified_at: Option<i64>,
+    pub status: String, // "ok", "corrupt" or "missing"
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// The file no longer hashes to what was uploaded.
+    Corrupt,
+    Missing,
+}
+
+impl Status {
+    pub fn from_str_case_insensitive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "ok" => Some(Status::Ok),
+            "corrupt" => Some(Status::Corrupt),
+            "missing" => Some(Status::Missing),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Corrupt => "corrupt",
+            Status::Missing => "missing",
+        }
+    }
+}
+
+impl Model {
+    pub fn status(&self) -> Status {
+        Status::from_str_case_insensitive(&self.status).unwrap_or(Status::Ok)
+    }
+
+    pub fn matches(&self, digest: &Digest) -> bool {
+        self.size == digest.size as i64 && self.xxh64 == digest.xxh64 && self.sha256 == digest.sha256
+    }
+
+    /// Store the hashes of a freshly written file, replacing any for an earlier file at `path`.
+    pub async fn record(db: &DatabaseConnection, path: &str, digest: &Digest, now: i64) -> Result<(), DbErr> {
+        Entity::delete_many().filter(Column::Path.eq(path)).exec(db).await?;
+        ActiveModel {
+            path: Set(path.to_string()),
+            size: Set(digest.size as i64),
+            xxh64: Set(digest.xxh64.clone()),
+            sha256: Set(digest.sha256.clone()),
+            hashed_at: Set(now),
+            verified_at: Set(None),
+            status: Set(Status::Ok.as_str().to_string()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn find_by_path(db: &DatabaseConnection, path: &str) -> Result<Option<Model>, DbErr> {
+        Entity::find().filter(Column::Path.eq(path)).one(db).await
+    }
+}
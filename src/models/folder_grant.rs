@@ -0,0 +1,148 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Condition;
+use crate::models::group_member;
+use crate::models::user::Permission;
+
+/// Gives a user, or every member of a group, rights on a folder and everything under it.
+///
+/// Users who were never given a grant keep the access their role gives them everywhere.
+/// Once a user gets a grant (directly or through a group) they're marked `restricted` and
+/// only see the folders granted to them, which is nothing after the last grant goes.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "folder_grants")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub path: String, // URL path of the folder, without the leading `/`; empty for everything
+    pub user_id: Option<i32>,
+    pub group_id: Option<i32>,
+    pub rights: String,
+    pub created_by: i32,
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// What a grant allows inside its folder. Each level includes the ones before it.
+/// A grant never goes beyond the user's role: a viewer granted `edit` can still only view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FolderRight {
+    Read,
+    Upload,
+    Edit,
+}
+
+impl FolderRight {
+    pub const ALL: [FolderRight; 3] = [FolderRight::Read, FolderRight::Upload, FolderRight::Edit];
+
+    pub fn from_str_case_insensitive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "read" => Some(FolderRight::Read),
+            "upload" => Some(FolderRight::Upload),
+            "edit" => Some(FolderRight::Edit),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FolderRight::Read => "read",
+            FolderRight::Upload => "upload",
+            FolderRight::Edit => "edit",
+        }
+    }
+
+    /// The right needed for a path-based permission; None for server-wide ones like ManageUsers.
+    pub fn needed_for(permission: Permission) -> Option<Self> {
+        match permission {
+            Permission::View => Some(FolderRight::Read),
+            Permission::Upload => Some(FolderRight::Upload),
+            Permission::Edit | Permission::CreateFolder | Permission::Share | Permission::ManageFiles => Some(FolderRight::Edit),
+            Permission::ManageUsers | Permission::ManageSettings => None,
+        }
+    }
+}
+
+/// True if `path` is `folder` or somewhere inside it.
+pub fn covers(folder: &str, path: &str) -> bool {
+    folder.is_empty()
+        || path == folder
+        || path.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl Model {
+    pub fn rights(&self) -> Option<FolderRight> {
+        FolderRight::from_str_case_insensitive(&self.rights)
+    }
+
+    /// The users a new grant for `user_id` or `group_id` confines.
+    pub async fn subjects(db: &DatabaseConnection, user_id: Option<i32>, group_id: Option<i32>) -> Result<Vec<i32>, DbErr> {
+        let mut ids: Vec<i32> = user_id.into_iter().collect();
+        if let Some(group_id) = group_id {
+            let members = group_member::Entity::find()
+                .filter(group_member::Column::GroupId.eq(group_id))
+                .all(db)
+                .await?;
+            ids.extend(members.into_iter().map(|member| member.user_id));
+        }
+        Ok(ids)
+    }
+
+    /// Every grant that applies to `user_id`, directly or through a group.
+    pub async fn for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<Model>, DbErr> {
+        let group_ids: Vec<i32> = group_member::Entity::find()
+            .filter(group_member::Column::UserId.eq(user_id))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|member| member.group_id)
+            .collect();
+        Entity::find()
+            .filter(
+                Condition::any()
+                    .add(Column::UserId.eq(user_id))
+                    .add(Column::GroupId.is_in(group_ids)),
+            )
+            .all(db)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_the_folder_and_what_is_inside() {
+        assert!(covers("foo", "foo"));
+        assert!(covers("foo", "foo/a.mp4"));
+        assert!(covers("foo/bar", "foo/bar/baz/a.mp4"));
+    }
+
+    #[test]
+    fn covers_not_siblings_sharing_a_prefix() {
+        assert!(!covers("foo", "foobar"));
+        assert!(!covers("foo", "foobar/a.mp4"));
+        assert!(!covers("foo/bar", "foo/bar.mp4"));
+        assert!(!covers("foo", "fo"));
+        assert!(!covers("foo/bar", "foo"));
+    }
+
+    #[test]
+    fn empty_folder_covers_everything() {
+        assert!(covers("", ""));
+        assert!(covers("", "foo"));
+        assert!(covers("", "foo/bar/a.mp4"));
+        assert!(!covers("foo", ""));
+    }
+
+    #[test]
+    fn rights_are_ordered() {
+        assert!(FolderRight::Edit > FolderRight::Upload && FolderRight::Upload > FolderRight::Read);
+        assert_eq!(FolderRight::needed_for(Permission::ManageFiles), Some(FolderRight::Edit));
+        assert_eq!(FolderRight::needed_for(Permission::ManageUsers), None);
+    }
+}
@@ -0,0 +1,34 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "invites")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub token: String,
+    pub access_level: String, // Role given to the account created with this invite
+    pub created_by: i32,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub used_by: Option<i32>,
+    pub used_at: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// An invite can be redeemed once, before it expires.
+    pub fn is_usable(&self, now: i64) -> bool {
+        self.used_at.is_none() && self.expires_at > now
+    }
+
+    pub async fn find_by_token(db: &DatabaseConnection, token: &str) -> Result<Option<Model>, DbErr> {
+        Entity::find()
+            .filter(Column::Token.eq(token))
+            .one(db)
+            .await
+    }
+}
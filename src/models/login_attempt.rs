@@ -0,0 +1,181 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::{QueryOrder, QuerySelect, Set};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "login_attempts")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub kind: AttemptKind,
+    pub username: String, // the share link's token for share passwords
+    pub ip: String,
+    pub success: bool,
+    pub time: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// What was being unlocked. Each kind backs off on its own, and only logins belong in the
+/// account audit trail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Text")]
+pub enum AttemptKind {
+    #[sea_orm(string_value = "login")]
+    Login,
+    #[sea_orm(string_value = "share")]
+    Share,
+}
+
+/// Failures older than this no longer count, so a lockout lifts on its own.
+pub const WINDOW_SECS: i64 = 15 * 60;
+
+/// How many failures in a row lock a username or an IP out for WINDOW_SECS.
+/// IPs get more room since several people can share one.
+const USERNAME_LOCKOUT_AFTER: u64 = 5;
+const IP_LOCKOUT_AFTER: u64 = 20;
+
+/// Longest wait between attempts before the lockout kicks in.
+const MAX_BACKOFF_SECS: i64 = 60;
+
+impl Model {
+    pub async fn record(
+        db: &DatabaseConnection,
+        kind: AttemptKind,
+        username: &str,
+        ip: &str,
+        success: bool,
+        now: i64,
+    ) -> Result<(), DbErr> {
+        ActiveModel {
+            kind: Set(kind),
+            username: Set(username.to_string()),
+            ip: Set(ip.to_string()),
+            success: Set(success),
+            time: Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .map(|_| ())
+    }
+
+    /// Seconds until another attempt for this username from this IP will be checked, or 0.
+    /// Each failure doubles the wait (1s, 2s, 4s...) until the lockout threshold.
+    pub async fn seconds_until_allowed(
+        db: &DatabaseConnection,
+        kind: AttemptKind,
+        username: &str,
+        ip: &str,
+        now: i64,
+    ) -> Result<i64, DbErr> {
+        let by_username = wait_for(db, kind, Column::Username.eq(username), USERNAME_LOCKOUT_AFTER, now).await?;
+        let by_ip = wait_for(db, kind, Column::Ip.eq(ip), IP_LOCKOUT_AFTER, now).await?;
+        Ok(by_username.max(by_ip))
+    }
+}
+
+// Counts the failures since the last success within the window and turns them into a wait.
+async fn wait_for(
+    db: &DatabaseConnection,
+    kind: AttemptKind,
+    filter: sea_orm::sea_query::SimpleExpr,
+    lockout_after: u64,
+    now: i64,
+) -> Result<i64, DbErr> {
+    let recent = Entity::find()
+        .filter(Column::Kind.eq(kind))
+        .filter(filter)
+        .filter(Column::Time.gt(now - WINDOW_SECS))
+        .order_by_desc(Column::Time)
+        .order_by_desc(Column::Id)
+        .limit(lockout_after)
+        .all(db)
+        .await?;
+    let failures: Vec<_> = recent.iter().take_while(|attempt| !attempt.success).collect();
+    let last_failure = match failures.first() {
+        Some(attempt) => attempt.time,
+        None => return Ok(0),
+    };
+    Ok((last_failure + backoff(failures.len() as u64, lockout_after) - now).max(0))
+}
+
+// The wait after `failures` failures in a row, counted from the last one.
+fn backoff(failures: u64, lockout_after: u64) -> i64 {
+    if failures == 0 {
+        0
+    } else if failures >= lockout_after {
+        WINDOW_SECS
+    } else {
+        (1i64 << (failures - 1).min(62)).min(MAX_BACKOFF_SECS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_until_the_lockout() {
+        let waits: Vec<i64> = (0..=5).map(|failures| backoff(failures, USERNAME_LOCKOUT_AFTER)).collect();
+        assert_eq!(waits, vec![0, 1, 2, 4, 8, WINDOW_SECS]);
+    }
+
+    #[test]
+    fn backoff_is_capped_below_the_lockout() {
+        assert_eq!(backoff(7, IP_LOCKOUT_AFTER), MAX_BACKOFF_SECS);
+        assert_eq!(backoff(19, IP_LOCKOUT_AFTER), MAX_BACKOFF_SECS);
+        assert_eq!(backoff(20, IP_LOCKOUT_AFTER), WINDOW_SECS);
+        assert_eq!(backoff(100, IP_LOCKOUT_AFTER), WINDOW_SECS);
+    }
+
+    async fn db() -> DatabaseConnection {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        let schema = sea_orm::Schema::new(db.get_database_backend());
+        db.execute(db.get_database_backend().build(&schema.create_table_from_entity(Entity))).await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn waits_after_failures_and_clears_on_success() {
+        let db = db().await;
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "ann", "1.2.3.4", 1000).await.unwrap(), 0);
+        for time in [1000, 1001, 1002] {
+            Model::record(&db, AttemptKind::Login, "ann", "1.2.3.4", false, time).await.unwrap();
+        }
+        // Three failures: 4s from the last one.
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "ann", "1.2.3.4", 1002).await.unwrap(), 4);
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "ann", "1.2.3.4", 1005).await.unwrap(), 1);
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "ann", "1.2.3.4", 1006).await.unwrap(), 0);
+        // The IP is held back for every username, but only that IP.
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "bob", "1.2.3.4", 1002).await.unwrap(), 4);
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "bob", "5.6.7.8", 1002).await.unwrap(), 0);
+        Model::record(&db, AttemptKind::Login, "ann", "1.2.3.4", true, 1010).await.unwrap();
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "ann", "1.2.3.4", 1010).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn locks_out_for_the_window_and_lifts_after() {
+        let db = db().await;
+        for time in 0..USERNAME_LOCKOUT_AFTER as i64 {
+            Model::record(&db, AttemptKind::Login, "ann", &format!("10.0.0.{}", time), false, 1000 + time).await.unwrap();
+        }
+        let last = 1000 + USERNAME_LOCKOUT_AFTER as i64 - 1;
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "ann", "10.0.0.99", last).await.unwrap(), WINDOW_SECS);
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "ann", "10.0.0.99", last + WINDOW_SECS).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn share_attempts_stay_apart_from_logins() {
+        let db = db().await;
+        for time in 0..IP_LOCKOUT_AFTER as i64 {
+            Model::record(&db, AttemptKind::Share, "tok", "1.2.3.4", false, 1000 + time).await.unwrap();
+        }
+        let last = 1000 + IP_LOCKOUT_AFTER as i64 - 1;
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Share, "tok", "1.2.3.4", last).await.unwrap(), WINDOW_SECS);
+        assert_eq!(Model::seconds_until_allowed(&db, AttemptKind::Login, "tok", "1.2.3.4", last).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,31 @@
+use sea_orm::entity::prelude::*;
+
+/// A file in one of the libraries, recorded by the watcher as it appears. Clips, points,
+/// tags and conversions refer to it by id, so it can be found again by fingerprint after
+/// it's been moved outside the app.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "media")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub path: String, // URL path where it was last seen
+    pub size: i64,
+    pub fingerprint: String, // see `utils::checksums::fingerprint`
+    pub first_seen_at: i64,
+    pub missing_since: Option<i64>, // set once it's gone and not found anywhere else
+    // Filled in by a probe conversion, for videos.
+    pub duration: Option<f64>, // seconds
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    pub async fn find_by_path(db: &DatabaseConnection, path: &str) -> Result<Option<Model>, DbErr> {
+        Entity::find().filter(Column::Path.eq(path)).one(db).await
+    }
+}
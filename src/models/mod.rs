@@ -1,8 +1,23 @@
+pub mod api_token;
 pub mod clip;
 pub mod conversion;
+pub mod dropbox;
+pub mod dropbox_upload;
 pub mod file;
+pub mod file_checksum;
+pub mod folder_grant;
+pub mod group;
+pub mod group_member;
+pub mod invite;
+pub mod login_attempt;
+pub mod media;
 pub mod point;
+pub mod probe;
+pub mod rendition;
 pub mod settings;
+pub mod share_link;
 pub mod tag;
 pub mod thumb;
+pub mod trash_item;
+pub mod upload_session;
 pub mod user;
\ No newline at end of file
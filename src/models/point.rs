@@ -10,6 +10,10 @@ pub struct Model {
     pub source_filename: String,
     pub time: i64,
     pub name: Option<String>,
+    #[serde(skip)]
+    pub deleted_at: Option<i64>, // set while the video is in the trash
+    #[serde(skip)]
+    pub media_id: Option<i32>, // the file it belongs to, which `source_filename` follows
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
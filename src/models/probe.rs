@@ -0,0 +1,69 @@
+use regex::Regex;
+use std::process::Stdio;
+use tokio::process::Command;
+use crate::models::conversion::ErrorKind;
+use crate::utils::ffmpeg;
+
+/// What ffmpeg says about a video when asked to read it without writing anything.
+#[derive(Debug, Default, PartialEq)]
+pub struct Probe {
+    pub duration: Option<f64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+impl Probe {
+    pub async fn run(input: &str, ffmpeg_path: &str) -> Result<Probe, ffmpeg::Error> {
+        // Only FFMPEG_PATH is configured, so this reads `ffmpeg -i`'s report rather than
+        // asking ffprobe. ffmpeg exits with an error since no output is given.
+        let args = ["-hide_banner".to_string(), "-i".to_string(), input.to_string()];
+        let command = ffmpeg::command_line(ffmpeg_path, &args);
+        let output = Command::new(ffmpeg_path)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output();
+        let output = match tokio::time::timeout(ffmpeg::STALL, output).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => return Err(ffmpeg::Error::new(format!("Failed to execute ffmpeg: {}", e), command, String::new())),
+            Err(_) => {
+                return Err(ffmpeg::Error {
+                    kind: ErrorKind::Timeout,
+                    message: format!("ffmpeg took over {} seconds to read the file and was stopped", ffmpeg::STALL.as_secs()),
+                    command,
+                    output: String::new(),
+                });
+            }
+        };
+        let report = String::from_utf8_lossy(&output.stderr);
+        let probe = Probe::parse(&report);
+        if probe == Probe::default() {
+            let tail = ffmpeg::tail(report.as_bytes()).await;
+            return Err(ffmpeg::Error::new("ffmpeg found no duration or video stream".to_string(), command, tail));
+        }
+        Ok(probe)
+    }
+
+    fn parse(report: &str) -> Probe {
+        let duration = Regex::new(r"Duration: (\d+):(\d{2}):(\d{2}(?:\.\d+)?)")
+            .unwrap()
+            .captures(report)
+            .and_then(|c| {
+                let hours: f64 = c[1].parse().ok()?;
+                let minutes: f64 = c[2].parse().ok()?;
+                let seconds: f64 = c[3].parse().ok()?;
+                Some(hours * 3600.0 + minutes * 60.0 + seconds)
+            });
+        let size = Regex::new(r"Stream #.*: Video: .*?\b(\d{2,5})x(\d{2,5})\b")
+            .unwrap()
+            .captures(report)
+            .and_then(|c| Some((c[1].parse().ok()?, c[2].parse().ok()?)));
+        Probe {
+            duration,
+            width: size.map(|(width, _)| width),
+            height: size.map(|(_, height)| height),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::{QueryOrder, Set};
+use serde::Serialize;
+
+/// Folder beside a video where its proxies are kept, hidden from listings like `thumbs`.
+pub const PROXIES_DIR: &str = "proxies";
+
+/// A lighter copy of a video made by the scaledown conversion, kept in `proxies/`.
+#[derive(Clone, Debug, PartialEq, Serialize, DeriveEntityModel)]
+#[sea_orm(table_name = "renditions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub source_filename: String, // URL path of the original
+    pub preset: String,
+    pub size: i64,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// How a proxy is made: the ffmpeg output options and the container they go in.
+pub struct Preset {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub extension: &'static str,
+    /// Whether browsers can play it, so the player can offer it.
+    pub playable: bool,
+    args: &'static [&'static str],
+}
+
+/// Made unless set otherwise with `--set-proxy-presets`.
+pub const DEFAULT_PROXY_PRESETS: &str = "720p";
+
+/// The presets there are; which of them get made is set with `--set-proxy-presets`.
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "720p",
+        label: "720p H.264",
+        extension: "mp4",
+        playable: true,
+        args: &[
+            "-vf", "scale=-2:'min(720,ih)'",
+            "-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-pix_fmt", "yuv420p",
+            "-c:a", "aac", "-b:a", "128k",
+            "-movflags", "+faststart",
+        ],
+    },
+    // For offline editing in an NLE, relinked to the originals for the final cut.
+    Preset {
+        name: "prores_proxy",
+        label: "ProRes Proxy",
+        extension: "mov",
+        playable: false,
+        args: &[
+            "-vf", "scale=-2:'min(1080,ih)'",
+            "-c:v", "prores_ks", "-profile:v", "0", "-vendor", "apl0", "-pix_fmt", "yuv422p10le",
+            "-c:a", "pcm_s16le",
+        ],
+    },
+    Preset {
+        name: "dnxhr_lb",
+        label: "DNxHR LB",
+        extension: "mov",
+        playable: false,
+        args: &[
+            "-vf", "scale=-2:'min(1080,ih)'",
+            "-c:v", "dnxhd", "-profile:v", "dnxhr_lb", "-pix_fmt", "yuv422p",
+            "-c:a", "pcm_s16le",
+        ],
+    },
+];
+
+impl Preset {
+    pub fn find(name: &str) -> Option<&'static Preset> {
+        PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The proxy's name in `proxies/`: the original's full name, so it sorts and moves
+    /// along with it, then the preset.
+    pub fn file_name(&self, source_name: &str) -> String {
+        format!("{}.{}.{}", source_name, self.name, self.extension)
+    }
+
+    /// ffmpeg arguments turning `input` into this preset at `output`.
+    pub fn args(&self, input: &str, output: &str) -> Vec<String> {
+        let mut args: Vec<String> = ["-y", "-i", input].iter().map(|arg| arg.to_string()).collect();
+        args.extend(self.args.iter().map(|arg| arg.to_string()));
+        args.push(output.to_string());
+        args
+    }
+}
+
+impl Model {
+    pub fn preset(&self) -> Option<&'static Preset> {
+        Preset::find(&self.preset)
+    }
+
+    /// URL path of the proxy file.
+    pub fn path(&self) -> Option<String> {
+        let preset = self.preset()?;
+        let (dir, name) = match self.source_filename.rsplit_once('/') {
+            Some((dir, name)) => (format!("{}/", dir), name),
+            None => (String::new(), self.source_filename.as_str()),
+        };
+        Some(format!("{}{}/{}", dir, PROXIES_DIR, preset.file_name(name)))
+    }
+
+    pub async fn for_source(db: &DatabaseConnection, source_filename: &str) -> Result<Vec<Model>, DbErr> {
+        Entity::find()
+            .filter(Column::SourceFilename.eq(source_filename))
+            .order_by_asc(Column::Preset)
+            .all(db)
+            .await
+    }
+
+    /// Record a freshly made proxy, replacing any earlier one of the same preset.
+    pub async fn record(
+        db: &DatabaseConnection,
+        source_filename: &str,
+        preset: &Preset,
+        size: i64,
+        dimensions: Option<(i32, i32)>,
+        now: i64,
+    ) -> Result<(), DbErr> {
+        Entity::delete_many()
+            .filter(Column::SourceFilename.eq(source_filename))
+            .filter(Column::Preset.eq(preset.name))
+            .exec(db)
+            .await?;
+        ActiveModel {
+            source_filename: Set(source_filename.to_string()),
+            preset: Set(preset.name.to_string()),
+            size: Set(size),
+            width: Set(dimensions.map(|(width, _)| width)),
+            height: Set(dimensions.map(|(_, height)| height)),
+            created_at: Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(())
+    }
+}
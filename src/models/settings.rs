@@ -1,4 +1,6 @@
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "settings")]
@@ -6,9 +8,83 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub ffmpeg_path: String,
+    pub signup_mode: String,
+    pub libraries: String, // JSON list of `Library`
+    pub trash_retention_days: i64,
+    pub conversion_workers: String, // JSON map of operation to how many run at once
+    pub proxy_presets: String,      // comma-separated names of the presets scaledown makes
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
-impl ActiveModelBehavior for ActiveModel {}
\ No newline at end of file
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Who may create an account through /signup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignupMode {
+    /// Anyone can sign up; the account waits for an admin to approve it.
+    Open,
+    /// Only holders of an unused, unexpired invite can sign up.
+    InviteOnly,
+    /// Nobody can sign up.
+    Closed,
+}
+
+impl SignupMode {
+    pub const ALL: [SignupMode; 3] = [SignupMode::Open, SignupMode::InviteOnly, SignupMode::Closed];
+
+    pub fn from_str_case_insensitive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "open" => Some(SignupMode::Open),
+            "invite" | "invite-only" | "invite_only" => Some(SignupMode::InviteOnly),
+            "closed" => Some(SignupMode::Closed),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignupMode::Open => "open",
+            SignupMode::InviteOnly => "invite-only",
+            SignupMode::Closed => "closed",
+        }
+    }
+}
+
+/// A named folder served under its own URL prefix, e.g. `/raw-footage/...`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Library {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// First URL segments already taken by routes, which a library can't be named after.
+const RESERVED_LIBRARY_NAMES: &[&str] = &[
+    "admin", "archive", "categorize", "checksums", "conversions", "create_folder", "d", "login", "logout", "manage", "points", "proxies",
+    "renditions", "s", "search", "segments", "shares", "signup", "tags", "thumbs", "tokens", "trash", "upload", "uploads", "videos",
+];
+
+impl Library {
+    /// Library names become URL prefixes, so keep them to lowercase letters, digits, `-` and `_`.
+    pub fn validate_name(name: &str) -> Result<(), String> {
+        if name.is_empty()
+            || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(format!("Library names may only use a-z, 0-9, '-' and '_', got '{}'", name));
+        }
+        if RESERVED_LIBRARY_NAMES.contains(&name) {
+            return Err(format!("'{}' is reserved and can't be used as a library name", name));
+        }
+        Ok(())
+    }
+}
+
+impl Model {
+    pub fn libraries(&self) -> Vec<Library> {
+        serde_json::from_str(&self.libraries).unwrap_or_else(|e| {
+            eprintln!("Ignoring unreadable libraries setting: {}", e);
+            Vec::new()
+        })
+    }
+}
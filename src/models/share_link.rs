@@ -0,0 +1,122 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::Expr;
+use sea_orm::Condition;
+
+/// A public `/s/{token}` link to a file, a folder or a clip, for people without an account.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "share_links")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub token: String,
+    pub owner_id: i32,
+    pub kind: String, // "file", "folder" or "clip"
+    pub path: String, // URL path of the file or folder; the source video for clips
+    pub clip_id: Option<i32>,
+    pub mode: String, // "view" or "download"
+    pub password_hash: Option<String>,
+    pub expires_at: Option<i64>,
+    pub max_downloads: Option<i32>,
+    pub download_count: i32,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareKind {
+    File,
+    Folder,
+    Clip,
+}
+
+impl ShareKind {
+    pub fn from_str_case_insensitive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "file" => Some(ShareKind::File),
+            "folder" => Some(ShareKind::Folder),
+            "clip" => Some(ShareKind::Clip),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareKind::File => "file",
+            ShareKind::Folder => "folder",
+            ShareKind::Clip => "clip",
+        }
+    }
+}
+
+/// Whether the recipient gets a download button, or can only watch in the browser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareMode {
+    View,
+    Download,
+}
+
+impl ShareMode {
+    pub fn from_str_case_insensitive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "view" => Some(ShareMode::View),
+            "download" => Some(ShareMode::Download),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareMode::View => "view",
+            ShareMode::Download => "download",
+        }
+    }
+}
+
+impl Model {
+    pub fn kind(&self) -> Option<ShareKind> {
+        ShareKind::from_str_case_insensitive(&self.kind)
+    }
+
+    pub fn mode(&self) -> ShareMode {
+        ShareMode::from_str_case_insensitive(&self.mode).unwrap_or(ShareMode::View)
+    }
+
+    pub fn downloads_left(&self) -> Option<i32> {
+        self.max_downloads.map(|max| (max - self.download_count).max(0))
+    }
+
+    /// Not revoked, not expired and not out of downloads.
+    pub fn is_active(&self, now: i64) -> bool {
+        self.revoked_at.is_none()
+            && self.expires_at.is_none_or(|expires_at| expires_at > now)
+            && self.downloads_left().is_none_or(|left| left > 0)
+    }
+
+    pub async fn find_active(db: &DatabaseConnection, token: &str, now: i64) -> Result<Option<Model>, DbErr> {
+        Ok(Entity::find()
+            .filter(Column::Token.eq(token))
+            .one(db)
+            .await?
+            .filter(|link| link.is_active(now)))
+    }
+
+    /// Count a download, unless the limit has been reached in the meantime. Returns false if it has.
+    pub async fn claim_download(&self, db: &DatabaseConnection) -> Result<bool, DbErr> {
+        let claimed = Entity::update_many()
+            .col_expr(Column::DownloadCount, Expr::col(Column::DownloadCount).add(1))
+            .filter(Column::Id.eq(self.id))
+            .filter(
+                Condition::any()
+                    .add(Column::MaxDownloads.is_null())
+                    .add(Expr::col(Column::DownloadCount).lt(Expr::col(Column::MaxDownloads))),
+            )
+            .exec(db)
+            .await?;
+        Ok(claimed.rows_affected > 0)
+    }
+}
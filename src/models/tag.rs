@@ -13,6 +13,10 @@ pub struct Model {
 	pub source_filename: String,
 	pub tag: String, // The tag word or phrase
 	pub slug: String, // Normalized version for searching (downcased, dashes)
+	#[serde(skip)]
+	pub deleted_at: Option<i64>, // set while the video is in the trash
+	#[serde(skip)]
+	pub media_id: Option<i32>, // the file it belongs to, which `source_filename` follows
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -51,6 +55,8 @@ impl ActiveModel {
 			source_filename: sea_orm::ActiveValue::Set(source_filename),
 			tag: sea_orm::ActiveValue::Set(tag),
 			slug: sea_orm::ActiveValue::Set(slug),
+			deleted_at: sea_orm::ActiveValue::NotSet,
+			media_id: sea_orm::ActiveValue::NotSet,
 		}
 	}
 }
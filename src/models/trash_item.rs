@@ -0,0 +1,35 @@
+use sea_orm::entity::prelude::*;
+
+/// Something deleted through the web UI, kept in `.trash/` until it's restored or purged.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "trash_items")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub original_path: String, // URL path it was deleted from
+    pub trash_path: String,    // URL path it's kept at, `.trash/{token}/{name}` in its library
+    pub is_dir: bool,
+    pub deleted_by: i32,
+    pub deleted_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Where deleted files go, at the top of the root folder and of each library so they
+/// never have to cross disks. Nothing in it can be reached through a normal URL.
+pub const TRASH_DIR: &str = ".trash";
+
+/// How long deleted items are kept when no retention has been set.
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// Longest retention an admin can set.
+pub const MAX_RETENTION_DAYS: i64 = 3650;
+
+/// A retention in seconds, held to 1..=MAX_RETENTION_DAYS days so a bad setting can't
+/// wrap the purge cutoff around into the future.
+pub fn retention_secs(days: i64) -> i64 {
+    days.clamp(1, MAX_RETENTION_DAYS).saturating_mul(24 * 3600)
+}
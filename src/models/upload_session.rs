@@ -0,0 +1,38 @@
+use sea_orm::entity::prelude::*;
+use crate::utils::uploads::ConflictPolicy;
+
+/// A resumable upload in progress. The bytes so far live in the staging file, so its
+/// size is the upload offset and survives restarts.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "upload_sessions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub token: String,
+    pub user_id: i32,
+    pub path: String,    // URL path the finished file will get
+    pub staging: String, // URL path of the partial file, in `.uploads/` next to `path`
+    pub length: i64,
+    pub conflict: String, // "skip", "rename" or "overwrite"
+    pub checksum: Option<String>, // what the client says the finished file hashes to, e.g. "sha256:<hex>"
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    pub fn conflict(&self) -> ConflictPolicy {
+        ConflictPolicy::from_str_case_insensitive(&self.conflict).unwrap_or(ConflictPolicy::Skip)
+    }
+}
+
+/// Where partial uploads are kept, inside the target folder so the final rename never
+/// crosses filesystems. Hidden from listings like `thumbs` and `segments`.
+pub const STAGING_DIR: &str = ".uploads";
+
+/// Uploads untouched for this long are considered abandoned.
+pub const ABANDONED_AFTER_SECS: i64 = 24 * 3600;
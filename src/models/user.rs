@@ -8,6 +8,8 @@ pub struct Model {
     pub username: String,
     pub password_hash: String,
     pub access_level: String,
+    /// Confined to their folder grants, even once there are none left.
+    pub restricted: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -15,3 +17,105 @@ pub enum Relation {}
 
 
 impl ActiveModelBehavior for ActiveModel {}
+
+/// access_level of an account created through open signup that no admin has approved yet.
+pub const PENDING: &str = "pending";
+
+impl Model {
+    /// The role stored in `access_level`, or None if the account has no access yet.
+    pub fn role(&self) -> Option<Role> {
+        Role::from_access_level(&self.access_level)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.access_level == PENDING
+    }
+
+    /// Confine `ids` to their folder grants from now on.
+    pub async fn restrict(db: &DatabaseConnection, ids: Vec<i32>) -> Result<(), DbErr> {
+        Entity::update_many()
+            .col_expr(Column::Restricted, Expr::value(true))
+            .filter(Column::Id.is_in(ids))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Delete an account along with its group memberships, folder grants, API tokens and share links.
+    pub async fn delete_account(db: &DatabaseConnection, id: i32) -> Result<(), DbErr> {
+        use crate::models::{api_token, folder_grant, group_member, share_link};
+        group_member::Entity::delete_many().filter(group_member::Column::UserId.eq(id)).exec(db).await?;
+        folder_grant::Entity::delete_many().filter(folder_grant::Column::UserId.eq(id)).exec(db).await?;
+        api_token::Entity::delete_many().filter(api_token::Column::UserId.eq(id)).exec(db).await?;
+        share_link::Entity::delete_many().filter(share_link::Column::OwnerId.eq(id)).exec(db).await?;
+        Entity::delete_by_id(id).exec(db).await?;
+        Ok(())
+    }
+}
+
+// Roles: Admin, Editor, Uploader, Viewer
+// Anything else in access_level ("pending", or the legacy "None") grants nothing.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    Editor,
+    Uploader,
+    Viewer,
+}
+
+/// Actions a controller can ask permission for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Browse folders, stream and download media, read clips/points/tags, search.
+    View,
+    /// Upload files.
+    Upload,
+    /// Cut clips, add points, tag and categorize videos.
+    Edit,
+    /// Create folders.
+    CreateFolder,
+    /// Create public share links to files, folders and clips.
+    Share,
+    /// Rename, move, copy and delete files and folders.
+    ManageFiles,
+    /// Approve, promote and delete accounts, and issue invites.
+    ManageUsers,
+    /// Change server settings such as the signup mode.
+    ManageSettings,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Admin, Role::Editor, Role::Uploader, Role::Viewer];
+
+    pub fn from_access_level(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "admin" => Some(Role::Admin),
+            "editor" => Some(Role::Editor),
+            "uploader" => Some(Role::Uploader),
+            "viewer" => Some(Role::Viewer),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Editor => "editor",
+            Role::Uploader => "uploader",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Editor => matches!(
+                permission,
+                Permission::View | Permission::Upload | Permission::Edit | Permission::Share | Permission::ManageFiles
+            ),
+            Role::Uploader => matches!(permission, Permission::View | Permission::Upload),
+            Role::Viewer => matches!(permission, Permission::View),
+        }
+    }
+}
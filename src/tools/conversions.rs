@@ -1,63 +1,176 @@
-use sea_orm::{EntityTrait, ColumnTrait, QueryFilter, DatabaseConnection};
+use futures::future::join_all;
+use sea_orm::{ActiveEnum, DatabaseConnection, Iterable};
+use std::collections::HashMap;
+use std::sync::Mutex;
 use tokio::time::{sleep, Duration};
-use crate::models::conversion;
-use sea_orm::QueryOrder;
-use tokio::sync::watch;
+use crate::models::conversion::{self, Operation, MAX_TRIES};
+use crate::utils::database::get_conversion_workers;
+use crate::utils::libraries::Libraries;
+use tokio::sync::{oneshot, watch};
 
-/// Continuously processes the next pending/running conversion in the queue.
-/// Will exit when the shutdown signal is received.
+/// Seconds a claimed conversion stays claimed without a heartbeat.
+const LEASE: i64 = 60;
+
+/// How often a running conversion renews its lease.
+const HEARTBEAT: Duration = Duration::from_secs(20);
+
+/// The conversions this server's workers are running, so they can be cancelled.
+#[derive(Default)]
+pub struct RunningConversions {
+  cancels: Mutex<HashMap<i32, oneshot::Sender<()>>>,
+}
+
+impl RunningConversions {
+  /// Stop conversion `id` if it's running here, killing its ffmpeg. Returns whether it was.
+  pub fn cancel(&self, id: i32) -> bool {
+    let cancel = self.cancels.lock().unwrap().remove(&id);
+    cancel.is_some_and(|tx| tx.send(()).is_ok())
+  }
+
+  fn start(&self, id: i32) -> oneshot::Receiver<()> {
+    let (tx, rx) = oneshot::channel();
+    self.cancels.lock().unwrap().insert(id, tx);
+    rx
+  }
+
+  fn finish(&self, id: i32) {
+    self.cancels.lock().unwrap().remove(&id);
+  }
+}
+
+/// Why a conversion stopped before it was done.
+enum Interrupted {
+  Cancelled,
+  Shutdown,
+}
+
+/// Runs the conversion queue: for each operation, as many workers as configured with
+/// `--set-workers` claim pending conversions and run them, listed in `running` meanwhile,
+/// and conversions whose worker died, e.g. in a crash, are put back in the queue. Will
+/// exit when the shutdown signal is received, handing back whatever is still running.
 pub async fn process_conversion_queue(
   db: &DatabaseConnection,
+  libraries: &Libraries,
+  running: &RunningConversions,
+  shutdown_rx: watch::Receiver<bool>,
+) {
+  let configured = get_conversion_workers(db).await;
+  let mut workers = Vec::new();
+  for operation in Operation::iter() {
+    let name = operation.to_value();
+    let count = configured.get(&name).copied().unwrap_or_else(|| operation.default_workers());
+    println!("Starting {} {} conversion workers", count, name);
+    for _ in 0..count {
+      workers.push(work(db, libraries, running, name.clone(), shutdown_rx.clone()));
+    }
+  }
+  // Everything here awaits ffmpeg or a blocking task, so the workers can share this one.
+  tokio::join!(requeue_expired(db, shutdown_rx), join_all(workers));
+}
+
+/// At startup, and every lease period after that, puts conversions whose lease has
+/// run out back in the queue.
+async fn requeue_expired(db: &DatabaseConnection, mut shutdown_rx: watch::Receiver<bool>) {
+  loop {
+    if *shutdown_rx.borrow() {
+      break;
+    }
+    match conversion::Model::requeue_expired(db).await {
+      Ok(0) => {}
+      Ok(count) => println!("Requeued {} conversions whose worker stopped", count),
+      Err(e) => eprintln!("Error requeueing conversions: {}", e),
+    }
+    tokio::select! {
+      _ = sleep(Duration::from_secs(LEASE as u64)) => {},
+      _ = shutdown_rx.changed() => {}
+    }
+  }
+}
+
+/// One worker: claims the next conversion of `operation` and runs it, until shutdown.
+async fn work(
+  db: &DatabaseConnection,
+  libraries: &Libraries,
+  running: &RunningConversions,
+  operation: String,
   mut shutdown_rx: watch::Receiver<bool>,
 ) {
   loop {
     // Check for shutdown signal
     if *shutdown_rx.borrow() {
-      println!("Conversion worker received shutdown signal. Exiting loop.");
       break;
     }
 
-    // Find the next conversion that is not completed or failed
-    let next_conversion = conversion::Entity::find()
-      .filter(
-        conversion::Column::Status
-          .is_in(vec!["pending", "running"])
-      )
-      .order_by(conversion::Column::TimeRequested, sea_orm::Order::Asc)
-      .one(db)
-      .await;
-
-    match next_conversion {
+    match conversion::Model::claim(db, &operation, LEASE).await {
       Ok(Some(conversion)) => {
-        conversion.process(db).await.unwrap_or_else(|e| {
-          eprintln!("Error processing conversion id {}: {}", conversion.id, e);
-        });
         println!("Processing conversion id: {}", conversion.id);
+        run(db, libraries, running, &conversion, &mut shutdown_rx).await;
       }
       Ok(None) => {
-        // No pending/running conversions, sleep before checking again
+        // Nothing due, sleep before checking again
         tokio::select! {
           _ = sleep(Duration::from_secs(3)) => {},
-          _ = shutdown_rx.changed() => {
-            if *shutdown_rx.borrow() {
-              println!("Conversion worker received shutdown signal during sleep. Exiting loop.");
-              break;
-            }
-          }
+          _ = shutdown_rx.changed() => {}
         }
       }
       Err(e) => {
-        eprintln!("Error querying conversions: {}", e);
+        eprintln!("Error claiming a {} conversion: {}", operation, e);
         tokio::select! {
           _ = sleep(Duration::from_secs(5)) => {},
-          _ = shutdown_rx.changed() => {
-            if *shutdown_rx.borrow() {
-              println!("Conversion worker received shutdown signal during error sleep. Exiting loop.");
-              break;
-            }
-          }
+          _ = shutdown_rx.changed() => {}
         }
       }
     }
   }
-}
\ No newline at end of file
+}
+
+async fn run(
+  db: &DatabaseConnection,
+  libraries: &Libraries,
+  running: &RunningConversions,
+  conversion: &conversion::Model,
+  shutdown_rx: &mut watch::Receiver<bool>,
+) {
+  let heartbeat = async {
+    loop {
+      sleep(HEARTBEAT).await;
+      if let Err(e) = conversion.heartbeat(db, LEASE).await {
+        eprintln!("Error renewing the lease of conversion id {}: {}", conversion.id, e);
+      }
+    }
+  };
+  let cancelled = running.start(conversion.id);
+  let result = tokio::select! {
+    result = conversion.process(db, libraries) => Ok(result),
+    _ = heartbeat => unreachable!(),
+    _ = cancelled => Err(Interrupted::Cancelled),
+    _ = shutdown_rx.wait_for(|shutdown| *shutdown) => Err(Interrupted::Shutdown),
+  };
+  running.finish(conversion.id);
+  // Dropping the work above killed its ffmpeg.
+  let result = match result {
+    Ok(result) => result,
+    Err(Interrupted::Cancelled) => {
+      // Already marked cancelled by whoever cancelled it.
+      println!("Conversion id {} was cancelled", conversion.id);
+      return;
+    }
+    Err(Interrupted::Shutdown) => {
+      // It starts over next time.
+      println!("Handing conversion id {} back to the queue for shutdown", conversion.id);
+      conversion.release(db).await;
+      return;
+    }
+  };
+  match result {
+    Ok(()) => conversion.mark_completed(db).await,
+    Err(failure) if failure.retry && conversion.times_tried < MAX_TRIES => {
+      eprintln!("Error processing conversion id {} (try {} of {}), retrying later: {}", conversion.id, conversion.times_tried, MAX_TRIES, failure);
+      conversion.retry_later(db, &failure).await;
+    }
+    Err(failure) => {
+      eprintln!("Error processing conversion id {}: {}", conversion.id, failure);
+      conversion.mark_failed(db, &failure).await;
+    }
+  }
+}
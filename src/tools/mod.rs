@@ -1,2 +1,5 @@
 pub mod ai;
 pub mod conversions;
+pub mod trash;
+pub mod uploads;
+pub mod watcher;
@@ -0,0 +1,44 @@
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use tokio::time::{sleep, Duration};
+use tokio::sync::watch;
+use crate::models::trash_item::{self, retention_secs};
+use crate::utils::database::get_trash_retention_days;
+use crate::utils::file_ops::purge_path;
+use crate::utils::libraries::Libraries;
+use crate::utils::time::now;
+
+/// Every hour, deletes for good whatever has been in the trash longer than the
+/// retention period set by an admin. Exits when the shutdown signal is received.
+pub async fn purge_expired_trash(
+  db: &DatabaseConnection,
+  libraries: &Libraries,
+  mut shutdown_rx: watch::Receiver<bool>,
+) {
+  loop {
+    if *shutdown_rx.borrow() {
+      break;
+    }
+
+    let cutoff = now() - retention_secs(get_trash_retention_days(db).await);
+    let expired = trash_item::Entity::find()
+      .filter(trash_item::Column::DeletedAt.lt(cutoff))
+      .all(db)
+      .await;
+    match expired {
+      Ok(items) => {
+        for item in items {
+          println!("Purging {} from the trash", item.original_path);
+          if let Err(e) = purge_path(db, libraries, &item).await {
+            eprintln!("Error purging {} from the trash: {}", item.original_path, e);
+          }
+        }
+      }
+      Err(e) => eprintln!("Error querying the trash: {}", e),
+    }
+
+    tokio::select! {
+      _ = sleep(Duration::from_secs(3600)) => {},
+      _ = shutdown_rx.changed() => {}
+    }
+  }
+}
@@ -0,0 +1,42 @@
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use tokio::time::{sleep, Duration};
+use tokio::sync::watch;
+use crate::controllers::uploads::discard;
+use crate::models::upload_session::{self, ABANDONED_AFTER_SECS};
+use crate::utils::libraries::Libraries;
+use crate::utils::time::now;
+
+/// Every hour, removes resumable uploads nobody has sent a chunk to for a day,
+/// along with their staging files. Exits when the shutdown signal is received.
+pub async fn collect_abandoned_uploads(
+  db: &DatabaseConnection,
+  libraries: &Libraries,
+  mut shutdown_rx: watch::Receiver<bool>,
+) {
+  loop {
+    if *shutdown_rx.borrow() {
+      break;
+    }
+
+    let abandoned = upload_session::Entity::find()
+      .filter(upload_session::Column::UpdatedAt.lt(now() - ABANDONED_AFTER_SECS))
+      .all(db)
+      .await;
+    match abandoned {
+      Ok(uploads) => {
+        for upload in uploads {
+          println!("Removing abandoned upload of {}", upload.path);
+          if let Err(e) = discard(db, libraries, &upload).await {
+            eprintln!("Error removing abandoned upload {}: {}", upload.token, e);
+          }
+        }
+      }
+      Err(e) => eprintln!("Error querying abandoned uploads: {}", e),
+    }
+
+    tokio::select! {
+      _ = sleep(Duration::from_secs(3600)) => {},
+      _ = shutdown_rx.changed() => {}
+    }
+  }
+}
@@ -0,0 +1,110 @@
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use sea_orm::DatabaseConnection;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep_until, Duration, Instant};
+use crate::utils::libraries::Libraries;
+use crate::utils::media_index::{index_path, rescan, RescanReport};
+
+/// How long a path has to stay quiet before it's looked at, so files still being
+/// copied in aren't fingerprinted halfway.
+const SETTLE: Duration = Duration::from_secs(3);
+
+/// How often everything is rescanned anyway, for changes the watcher can't see.
+const RESCAN_EVERY: Duration = Duration::from_secs(3600);
+
+/// Keeps the media index up to date: watches every library for files being added,
+/// moved or deleted, and rescans all of them at startup and every hour after that.
+/// Exits when the shutdown signal is received.
+pub async fn watch_media(
+  db: &DatabaseConnection,
+  libraries: &Libraries,
+  mut shutdown_rx: watch::Receiver<bool>,
+) {
+  let (tx, mut events) = mpsc::unbounded_channel();
+  // Dropping the watcher stops it, so it's kept until the loop ends.
+  let _watcher = match start_watching(libraries, tx) {
+    Ok(watcher) => Some(watcher),
+    Err(e) => {
+      eprintln!("Error watching the libraries, relying on hourly rescans: {}", e);
+      None
+    }
+  };
+
+  let mut changed: HashMap<PathBuf, Instant> = HashMap::new();
+  let mut next_rescan = Instant::now();
+  loop {
+    if *shutdown_rx.borrow() {
+      break;
+    }
+
+    if Instant::now() >= next_rescan {
+      match rescan(db, libraries).await {
+        Ok(report) => log_report("Rescan", &report),
+        Err(e) => eprintln!("Error rescanning media: {}", e),
+      }
+      next_rescan = Instant::now() + RESCAN_EVERY;
+    }
+
+    let settled: Vec<PathBuf> = changed
+      .iter()
+      .filter(|(_, at)| at.elapsed() >= SETTLE)
+      .map(|(path, _)| path.clone())
+      .collect();
+    for path in settled {
+      changed.remove(&path);
+      let Some(relative) = libraries.locate(&path) else { continue };
+      // Trashed and invalid paths don't resolve, and aren't media.
+      let Ok(path) = libraries.resolve(&relative) else { continue };
+      match index_path(db, libraries, &path).await {
+        Ok(report) => log_report(&format!("/{}", path.relative), &report),
+        Err(e) => eprintln!("Error indexing {}: {}", path.relative, e),
+      }
+    }
+
+    let next_settle = changed.values().min().map(|at| *at + SETTLE);
+    tokio::select! {
+      Some(event) = events.recv() => {
+        match event {
+          // Reads, the server's own included, change nothing.
+          Ok(Event { kind, .. }) if kind.is_access() && kind != EventKind::Access(AccessKind::Close(AccessMode::Write)) => {}
+          Ok(Event { paths, .. }) => {
+            for path in paths {
+              changed.insert(path, Instant::now());
+            }
+          }
+          Err(e) => eprintln!("Error watching the libraries: {}", e),
+        }
+      }
+      _ = sleep_until(next_settle.map_or(next_rescan, |at| at.min(next_rescan))) => {},
+      _ = shutdown_rx.changed() => {}
+    }
+  }
+}
+
+fn start_watching(
+  libraries: &Libraries,
+  tx: mpsc::UnboundedSender<notify::Result<Event>>,
+) -> notify::Result<RecommendedWatcher> {
+  let mut watcher = notify::recommended_watcher(move |event| {
+    let _ = tx.send(event);
+  })?;
+  for (_, root) in libraries.mounts() {
+    watcher.watch(root, RecursiveMode::Recursive)?;
+  }
+  Ok(watcher)
+}
+
+fn log_report(what: &str, report: &RescanReport) {
+  for (from, to) in &report.relinked {
+    println!("Media moved: /{} -> /{}", from, to);
+  }
+  for path in &report.missing {
+    println!("Media missing: /{}", path);
+  }
+  if report.added > 0 || report.linked > 0 {
+    println!("{}: {} new files, {} files linked to their clips, points and tags", what, report.added, report.linked);
+  }
+}
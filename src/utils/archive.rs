@@ -0,0 +1,218 @@
+use actix_web::web::Bytes;
+use futures::Stream;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+use tokio::sync::mpsc;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+use crate::utils::time::{civil, now};
+
+/// Name of the JSON file with the clips, points and tags of everything in an archive.
+pub const SIDECAR_NAME: &str = "ryancloud.json";
+
+/// Files from this size on need ZIP64 sizes.
+const ZIP64_FROM: u64 = u32::MAX as u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+}
+
+impl Format {
+    pub fn from_str_case_insensitive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "zip" => Some(Format::Zip),
+            "tar" => Some(Format::Tar),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::Tar => "tar",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Zip => "application/zip",
+            Format::Tar => "application/x-tar",
+        }
+    }
+}
+
+/// A file to put in an archive, and the `a/b/c` name it gets there.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub absolute: PathBuf,
+    pub name: String,
+}
+
+// Hands what the archive writer produces to the response, a chunk at a time. Sending
+// fails once the client has gone, which stops the writer.
+struct ChannelWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "download cancelled"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Build the archive while it's being sent, so nothing is written to disk and the first
+/// bytes go out right away. Video is already compressed, so files are stored as they are.
+pub fn stream(format: Format, entries: Vec<Entry>, sidecar: Vec<u8>) -> impl Stream<Item = io::Result<Bytes>> {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::task::spawn_blocking(move || {
+        let writer = BufWriter::with_capacity(256 * 1024, ChannelWriter(tx.clone()));
+        let result = match format {
+            Format::Zip => write_zip(writer, &entries, &sidecar, ZIP64_FROM),
+            Format::Tar => write_tar(writer, &entries, &sidecar),
+        };
+        if let Err(e) = result
+            && e.kind() != io::ErrorKind::BrokenPipe
+        {
+            eprintln!("Error writing archive: {}", e);
+            // Ends the response early so the client sees the download failed.
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    futures::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) })
+}
+
+fn write_zip<W: Write>(writer: W, entries: &[Entry], sidecar: &[u8], zip64_from: u64) -> io::Result<()> {
+    let mut zip = ZipWriter::new_stream(writer);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file(SIDECAR_NAME, options.last_modified_time(zip_time(now())))?;
+    zip.write_all(sidecar)?;
+    for entry in entries {
+        let mut file = File::open(&entry.absolute)?;
+        let metadata = file.metadata()?;
+        // ZIP64 sizes have to be chosen before the file is written.
+        let options = options
+            .large_file(metadata.len() >= zip64_from)
+            .last_modified_time(zip_time(modified(&metadata)));
+        zip.start_file(entry.name.as_str(), options)?;
+        io::copy(&mut file, &mut zip)?;
+    }
+    zip.finish()?.into_inner().flush()
+}
+
+fn write_tar<W: Write>(writer: W, entries: &[Entry], sidecar: &[u8]) -> io::Result<()> {
+    let mut tar = tar::Builder::new(writer);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(sidecar.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(now() as u64);
+    tar.append_data(&mut header, SIDECAR_NAME, sidecar)?;
+    for entry in entries {
+        let mut file = File::open(&entry.absolute)?;
+        tar.append_file(&entry.name, &mut file)?;
+    }
+    tar.into_inner()?.flush()
+}
+
+fn modified(metadata: &std::fs::Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_else(now)
+}
+
+// Zip dates start in 1980; anything earlier gets the earliest one.
+fn zip_time(timestamp: i64) -> DateTime {
+    let (year, month, day, hour, minute, second) = civil(timestamp);
+    DateTime::from_date_and_time(year as u16, month as u8, day as u8, hour as u8, minute as u8, second as u8)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    fn entries(dir: &std::path::Path) -> Vec<Entry> {
+        std::fs::create_dir_all(dir.join("videos")).unwrap();
+        std::fs::write(dir.join("videos/a.mp4"), b"first file").unwrap();
+        std::fs::write(dir.join("b.txt"), vec![7u8; 100_000]).unwrap();
+        vec![
+            Entry { absolute: dir.join("videos/a.mp4"), name: "videos/a.mp4".to_string() },
+            Entry { absolute: dir.join("b.txt"), name: "b.txt".to_string() },
+        ]
+    }
+
+    // The entries and their contents, and the ones written with ZIP64 sizes.
+    fn read_zip(bytes: Vec<u8>) -> (Vec<(String, Vec<u8>)>, Vec<String>) {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes.clone())).unwrap();
+        let mut read = Vec::new();
+        let mut zip64 = Vec::new();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).unwrap();
+            // "Version needed to extract" in the local header is 4.5 for ZIP64.
+            let start = file.header_start() as usize;
+            if u16::from_le_bytes([bytes[start + 4], bytes[start + 5]]) >= 45 {
+                zip64.push(file.name().to_string());
+            }
+            read.push((file.name().to_string(), contents));
+        }
+        (read, zip64)
+    }
+
+    fn expected() -> Vec<(String, Vec<u8>)> {
+        vec![
+            (SIDECAR_NAME.to_string(), b"{}".to_vec()),
+            ("videos/a.mp4".to_string(), b"first file".to_vec()),
+            ("b.txt".to_string(), vec![7u8; 100_000]),
+        ]
+    }
+
+    #[test]
+    fn zip_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bytes = Vec::new();
+        write_zip(&mut bytes, &entries(dir.path()), b"{}", ZIP64_FROM).unwrap();
+        assert_eq!(read_zip(bytes), (expected(), Vec::new()));
+    }
+
+    #[test]
+    fn zip64_entries_read_back() {
+        // Switch over at 1 KB so the ZIP64 path runs without writing 4 GB.
+        let dir = tempfile::tempdir().unwrap();
+        let mut bytes = Vec::new();
+        write_zip(&mut bytes, &entries(dir.path()), b"{}", 1024).unwrap();
+        assert_eq!(read_zip(bytes), (expected(), vec!["b.txt".to_string()]));
+    }
+
+    #[test]
+    fn tar_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bytes = Vec::new();
+        write_tar(&mut bytes, &entries(dir.path()), b"{}").unwrap();
+        let mut archive = tar::Archive::new(Cursor::new(bytes));
+        let read: Vec<(String, Vec<u8>)> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents).unwrap();
+                (entry.path().unwrap().to_string_lossy().to_string(), contents)
+            })
+            .collect();
+        assert_eq!(read, expected());
+    }
+}
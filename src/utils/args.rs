@@ -1,36 +1,319 @@
-use sea_orm::DatabaseConnection;
-use crate::utils::database::set_ffmpeg_path;
-
-pub async fn handle_args(args: &[String], db: &DatabaseConnection) {
-    if args.len() > 1 {
-        for arg in &args[1..] {
-            match arg.as_str() {
-                "--where" => {
-                    let db_path = crate::utils::database::db_path();
-                    println!("Database path: {:?}", db_path);
-                }
-                "--help" => {
-                    println!("Usage: {} [OPTIONS]\n\nOptions:\n  --where       Print the path to the database file.\n  --help        Show this help message.\n  --folder=PATH Specify the folder to serve.\n  --set-ffmpeg=PATH Set the FFMPEG_PATH in the database.", args[0]);
-                }
-                _ if arg.starts_with("--folder=") => {
-                    if let Some(path) = arg.strip_prefix("--folder=") {
-                        println!("Folder argument provided: {}", path);
-                    }
-                }
-                _ if arg.starts_with("--set-ffmpeg=") => {
-                    if let Some(path) = arg.strip_prefix("--set-ffmpeg=") {
-                        if let Err(e) = set_ffmpeg_path(db, path).await {
-                            eprintln!("Failed to set FFMPEG_PATH: {}", e);
-                        } else {
-                            println!("FFMPEG_PATH set to: {}", path);
-                        }
-                    }
-                }
-                _ => {
-                    println!("Unknown argument: {}", arg);
-                }
-            }
-        }
-        std::process::exit(0);
-    }
-}
\ No newline at end of file
+use clap::{Parser, Subcommand};
+use sea_orm::{ActiveEnum, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set};
+use std::path::PathBuf;
+use crate::models::settings::Library;
+use crate::models::user::{self, Role};
+use crate::models::conversion::Operation;
+use crate::models::rendition::{Preset, PRESETS};
+use crate::utils::database::{get_conversion_workers, get_libraries, set_conversion_workers, set_ffmpeg_path, set_libraries, set_proxy_presets};
+
+/// Simple cloud media server for sharing files with video editors.
+///
+/// Run with no subcommand to serve the current folder, or the one given by --folder.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Print the path to the database file.
+    #[arg(long = "where")]
+    pub where_db: bool,
+
+    /// Serve this folder at / instead of the current directory.
+    #[arg(long, value_name = "PATH")]
+    pub folder: Option<PathBuf>,
+
+    /// Set the FFMPEG_PATH in the database.
+    #[arg(long, value_name = "PATH")]
+    pub set_ffmpeg: Option<String>,
+
+    /// Set how many conversions of an operation run at once, e.g. `thumbnail=4`. Can be
+    /// given more than once; takes effect when the server next starts.
+    #[arg(long, value_name = "OPERATION=COUNT")]
+    pub set_workers: Vec<String>,
+
+    /// Set which proxies the scaledown conversion makes, comma-separated, from 720p,
+    /// prores_proxy and dnxhr_lb; `none` to make none.
+    #[arg(long, value_name = "PRESETS")]
+    pub set_proxy_presets: Option<String>,
+
+    /// Replace the session signing key, logging everyone out.
+    #[arg(long)]
+    pub rotate_session_key: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manage user accounts.
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Manage named libraries, each served under its own URL prefix.
+    #[command(subcommand)]
+    Library(LibraryCommand),
+}
+
+#[derive(Subcommand)]
+pub enum LibraryCommand {
+    /// Serve PATH under /NAME/.
+    Add {
+        name: String,
+        path: PathBuf,
+    },
+    /// Stop serving a library. Its files are left alone.
+    Remove {
+        name: String,
+    },
+    /// List the named libraries.
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Create a user. Prompts for the password unless --password is given.
+    Create {
+        username: String,
+        /// admin, editor, uploader or viewer.
+        #[arg(long, default_value = "viewer")]
+        role: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Set a new password for a user. Prompts for it unless --password is given.
+    ResetPassword {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Change a user's access level (admin, editor, uploader, viewer or pending).
+    SetAccess {
+        username: String,
+        access_level: String,
+    },
+    /// List all users and their access levels.
+    List,
+    /// Delete a user.
+    Delete {
+        username: String,
+    },
+}
+
+impl Cli {
+    // True when the arguments ask for a one-off task rather than starting the server.
+    fn is_task(&self) -> bool {
+        self.where_db
+            || self.set_ffmpeg.is_some()
+            || !self.set_workers.is_empty()
+            || self.set_proxy_presets.is_some()
+            || self.rotate_session_key
+            || self.command.is_some()
+    }
+}
+
+/// Runs any one-off task and exits, or returns the parsed arguments when it's time to serve.
+pub async fn handle_args(args: &[String], db: &DatabaseConnection) -> Cli {
+    let cli = Cli::parse_from(args);
+    if !cli.is_task() {
+        return cli;
+    }
+
+    if cli.where_db {
+        let db_path = crate::utils::database::db_path();
+        println!("Database path: {:?}", db_path);
+    }
+    if let Some(path) = &cli.set_ffmpeg {
+        if let Err(e) = set_ffmpeg_path(db, path).await {
+            eprintln!("Failed to set FFMPEG_PATH: {}", e);
+        } else {
+            println!("FFMPEG_PATH set to: {}", path);
+        }
+    }
+    if !cli.set_workers.is_empty()
+        && let Err(e) = set_workers(&cli.set_workers, db).await
+    {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    if let Some(names) = &cli.set_proxy_presets
+        && let Err(e) = set_presets(names, db).await
+    {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    if cli.rotate_session_key {
+        match crate::utils::session_key::rotate() {
+            Ok(path) => println!("New session key written to {:?}. Restart the server to log everyone out.", path),
+            Err(e) => eprintln!("Failed to rotate session key: {}", e),
+        }
+    }
+    let result = match cli.command {
+        Some(Command::User(command)) => handle_user_command(command, db).await,
+        Some(Command::Library(command)) => handle_library_command(command, db).await,
+        None => Ok(()),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+async fn set_workers(settings: &[String], db: &DatabaseConnection) -> Result<(), String> {
+    let mut workers = get_conversion_workers(db).await;
+    for setting in settings {
+        let (operation, count) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("Expected OPERATION=COUNT, got '{}'", setting))?;
+        let operation = Operation::from_str_case_insensitive(operation)
+            .ok_or_else(|| format!("Unknown operation '{}'", operation))?;
+        let count: usize = count.parse().map_err(|_| format!("'{}' is not a number of workers", count))?;
+        println!("{} conversions: {} at once", operation.to_value(), count);
+        workers.insert(operation.to_value(), count);
+    }
+    set_conversion_workers(db, &workers).await.map_err(|e| format!("Failed to save workers: {}", e))
+}
+
+async fn set_presets(names: &str, db: &DatabaseConnection) -> Result<(), String> {
+    let mut presets = Vec::new();
+    if !names.trim().eq_ignore_ascii_case("none") {
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let preset = Preset::find(name).ok_or_else(|| {
+                let known: Vec<&str> = PRESETS.iter().map(|preset| preset.name).collect();
+                format!("Unknown proxy preset '{}', expected one of {}", name, known.join(", "))
+            })?;
+            presets.push(preset);
+        }
+    }
+    set_proxy_presets(db, &presets).await.map_err(|e| format!("Failed to save proxy presets: {}", e))?;
+    if presets.is_empty() {
+        println!("No proxies will be made");
+    } else {
+        let labels: Vec<&str> = presets.iter().map(|preset| preset.label).collect();
+        println!("Proxies made: {}", labels.join(", "));
+    }
+    Ok(())
+}
+
+async fn handle_library_command(command: LibraryCommand, db: &DatabaseConnection) -> Result<(), String> {
+    let mut libraries = get_libraries(db).await;
+    match command {
+        LibraryCommand::Add { name, path } => {
+            Library::validate_name(&name)?;
+            if libraries.iter().any(|library| library.name == name) {
+                return Err(format!("Library '{}' already exists", name));
+            }
+            let path = path.canonicalize().map_err(|e| format!("{}: {}", path.display(), e))?;
+            if !path.is_dir() {
+                return Err(format!("{} is not a folder", path.display()));
+            }
+            println!("Added library /{} -> {}", name, path.display());
+            libraries.push(Library { name, path });
+        }
+        LibraryCommand::Remove { name } => {
+            let before = libraries.len();
+            libraries.retain(|library| library.name != name);
+            if libraries.len() == before {
+                return Err(format!("No library named '{}'", name));
+            }
+            println!("Removed library '{}'", name);
+        }
+        LibraryCommand::List => {
+            if libraries.is_empty() {
+                println!("No libraries.");
+            }
+            for library in &libraries {
+                println!("/{:<20}  {}", library.name, library.path.display());
+            }
+            return Ok(());
+        }
+    }
+    set_libraries(db, &libraries).await.map_err(|e| e.to_string())?;
+    println!("Restart the server to pick up the change.");
+    Ok(())
+}
+
+async fn handle_user_command(command: UserCommand, db: &DatabaseConnection) -> Result<(), String> {
+    match command {
+        UserCommand::Create { username, role, password } => {
+            let role = Role::from_access_level(&role)
+                .ok_or_else(|| format!("Unknown role '{}'", role))?;
+            if find_user(db, &username).await.is_ok() {
+                return Err(format!("User '{}' already exists", username));
+            }
+            let password = password_or_prompt(password)?;
+            let new_user = user::ActiveModel {
+                username: Set(username.clone()),
+                password_hash: Set(hash_password(&password)?),
+                access_level: Set(role.as_str().to_string()),
+                ..Default::default()
+            };
+            new_user.insert(db).await.map_err(|e| e.to_string())?;
+            println!("Created {} '{}'", role.as_str(), username);
+        }
+        UserCommand::ResetPassword { username, password } => {
+            let existing = find_user(db, &username).await?;
+            let password = password_or_prompt(password)?;
+            let mut am: user::ActiveModel = existing.into();
+            am.password_hash = Set(hash_password(&password)?);
+            am.update(db).await.map_err(|e| e.to_string())?;
+            println!("Password reset for '{}'", username);
+        }
+        UserCommand::SetAccess { username, access_level } => {
+            let access_level = match Role::from_access_level(&access_level) {
+                Some(role) => role.as_str().to_string(),
+                None if access_level == user::PENDING => access_level,
+                None => return Err(format!("Unknown access level '{}'", access_level)),
+            };
+            let existing = find_user(db, &username).await?;
+            let mut am: user::ActiveModel = existing.into();
+            am.access_level = Set(access_level.clone());
+            am.update(db).await.map_err(|e| e.to_string())?;
+            println!("'{}' is now {}", username, access_level);
+        }
+        UserCommand::List => {
+            let users = user::Entity::find()
+                .order_by_asc(user::Column::Username)
+                .all(db)
+                .await
+                .map_err(|e| e.to_string())?;
+            if users.is_empty() {
+                println!("No users.");
+            }
+            for u in users {
+                println!("{:>5}  {:<10}  {}", u.id, u.access_level, u.username);
+            }
+        }
+        UserCommand::Delete { username } => {
+            let existing = find_user(db, &username).await?;
+            user::Model::delete_account(db, existing.id)
+                .await
+                .map_err(|e| e.to_string())?;
+            println!("Deleted '{}'", username);
+        }
+    }
+    Ok(())
+}
+
+async fn find_user(db: &DatabaseConnection, username: &str) -> Result<user::Model, String> {
+    user::Entity::find()
+        .filter(user::Column::Username.eq(username))
+        .one(db)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No user named '{}'", username))
+}
+
+fn password_or_prompt(password: Option<String>) -> Result<String, String> {
+    let password = match password {
+        Some(password) => password,
+        None => rpassword::prompt_password("Password: ").map_err(|e| e.to_string())?,
+    };
+    if password.is_empty() {
+        return Err("Password cannot be empty".to_string());
+    }
+    Ok(password)
+}
+
+fn hash_password(password: &str) -> Result<String, String> {
+    bcrypt::hash(password, bcrypt::DEFAULT_COST).map_err(|e| e.to_string())
+}
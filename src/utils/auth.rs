@@ -0,0 +1,299 @@
+use actix_session::{Session, SessionExt};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use futures_util::future::LocalBoxFuture;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use crate::models::api_token::{self, TokenScope};
+use crate::models::folder_grant::{self, covers, FolderRight};
+use crate::models::user::{self, Permission, Role};
+use crate::utils::time::now;
+
+/// The logged-in user for this request, with their role resolved from `access_level`.
+///
+/// Use it as a handler argument (or `Option<CurrentUser>` for pages that also render
+/// for anonymous visitors) and call `require` before doing anything.
+#[derive(Clone, Debug)]
+pub struct CurrentUser {
+    pub user: user::Model,
+    pub role: Option<Role>,
+    /// Set when the request authenticated with an API token instead of the session cookie.
+    pub scope: Option<TokenScope>,
+    /// The folders this user is confined to, possibly none at all, or None when their
+    /// role applies everywhere.
+    pub grants: Option<Vec<folder_grant::Model>>,
+}
+
+/// The grants `user` is confined to, or None when their role applies everywhere.
+/// Confined users with no grants left get nothing rather than everything.
+fn confined_to(user: &user::Model, grants: Vec<folder_grant::Model>) -> Option<Vec<folder_grant::Model>> {
+    Some(grants).filter(|grants| user.restricted || !grants.is_empty())
+}
+
+impl CurrentUser {
+    /// Load the user behind an `Authorization: Bearer` token if one was sent,
+    /// otherwise the one referenced by the session's `user_id`.
+    pub async fn load(session: &Session, bearer: Option<&str>, db: &DatabaseConnection) -> Result<Option<Self>, actix_web::Error> {
+        let (user_id, scope) = match bearer {
+            Some(token) => {
+                let token = api_token::Model::find_active(db, token, now())
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+                match token.and_then(|t| t.scope().map(|scope| (t.user_id, scope))) {
+                    Some((user_id, scope)) => (user_id, Some(scope)),
+                    None => return Ok(None),
+                }
+            }
+            None => match session.get::<i32>("user_id").unwrap_or(None) {
+                Some(id) => (id, None),
+                None => return Ok(None),
+            },
+        };
+        let user = match user::Entity::find_by_id(user_id)
+            .one(db)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+        {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        let role = user.role();
+        // Admins see everything regardless of grants.
+        let grants = if role == Some(Role::Admin) {
+            None
+        } else {
+            let grants = folder_grant::Model::for_user(db, user.id)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            confined_to(&user, grants)
+        };
+        Ok(Some(CurrentUser { user, role, scope, grants }))
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        self.role.is_some_and(|role| role.can(permission))
+            && self.scope.is_none_or(|scope| scope.allows(permission))
+    }
+
+    /// Whether the user may do `permission` on the file or folder at URL path `path`.
+    pub fn can_at(&self, permission: Permission, path: &str) -> bool {
+        if !self.can(permission) {
+            return false;
+        }
+        match (&self.grants, FolderRight::needed_for(permission)) {
+            (Some(grants), Some(needed)) => grants.iter().any(|grant| {
+                covers(&grant.path, path) && grant.rights().is_some_and(|rights| rights >= needed)
+            }),
+            _ => true,
+        }
+    }
+
+    /// Whether `path` belongs in a listing: it's readable, or it leads to a folder that is.
+    pub fn can_see(&self, path: &str) -> bool {
+        self.can_at(Permission::View, path)
+            || (self.can(Permission::View)
+                && self.grants.as_ref().is_some_and(|grants| grants.iter().any(|grant| covers(path, &grant.path))))
+    }
+
+    /// Like `require`, but also checks the user's folder grants for `path`.
+    pub fn require_at(&self, permission: Permission, path: &str) -> Result<(), HttpResponse> {
+        self.require(permission)?;
+        if self.can_at(permission, path) {
+            return Ok(());
+        }
+        Err(HttpResponse::Forbidden().body("You do not have access to this folder"))
+    }
+
+    /// True when logged in with the session cookie rather than an API token.
+    pub fn is_session(&self) -> bool {
+        self.scope.is_none()
+    }
+
+    /// Returns a 403 response to send back if the user lacks `permission`.
+    pub fn require(&self, permission: Permission) -> Result<(), HttpResponse> {
+        if self.can(permission) {
+            return Ok(());
+        }
+        if self.role.is_none() {
+            return Err(HttpResponse::Forbidden().body("Your account has not been given access yet"));
+        }
+        Err(HttpResponse::Forbidden().body("You do not have permission to do that"))
+    }
+}
+
+impl FromRequest for CurrentUser {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        // Already loaded by `require_login` for every non-public route.
+        if let Some(user) = req.extensions().get::<CurrentUser>().cloned() {
+            return Box::pin(async move { Ok(user) });
+        }
+        let session = req.get_session();
+        let bearer = bearer_token(req);
+        let db = req.app_data::<web::Data<DatabaseConnection>>().cloned();
+        Box::pin(async move {
+            let db = db.ok_or_else(|| actix_web::error::ErrorInternalServerError("Database unavailable"))?;
+            CurrentUser::load(&session, bearer.as_deref(), db.get_ref())
+                .await?
+                .ok_or_else(|| actix_web::error::ErrorUnauthorized("Login required"))
+        })
+    }
+}
+
+/// The token from an `Authorization: Bearer ...` header, if present.
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+}
+
+/// A random 64-character hex string for invite links and other bearer secrets.
+pub fn random_token() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+/// Exact paths that can be reached without logging in.
+const PUBLIC_PATHS: &[&str] = &["/login", "/logout", "/signup", "/favicon.ico", "/robots.txt"];
+
+/// Path prefixes that can be reached without logging in.
+/// `/categorize/` serves the extracted frames that OpenAI fetches by URL; `/s/` and `/d/` are share links and drop-boxes.
+const PUBLIC_PREFIXES: &[&str] = &["/categorize/", "/d/", "/s/"];
+
+pub fn is_public_path(path: &str) -> bool {
+    PUBLIC_PATHS.contains(&path) || PUBLIC_PREFIXES.iter().any(|prefix| path.starts_with(prefix))
+}
+
+/// Middleware wrapped around the whole `App`: every route outside the allow-list needs a
+/// logged-in user, who is then stashed in the request extensions for `CurrentUser`.
+pub async fn require_login(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if is_public_path(req.path()) {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    let db = req.app_data::<web::Data<DatabaseConnection>>().cloned()
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("Database unavailable"))?;
+    let session = req.get_session();
+    let bearer = bearer_token(req.request());
+    match CurrentUser::load(&session, bearer.as_deref(), db.get_ref()).await? {
+        Some(user) => {
+            req.extensions_mut().insert(user);
+            next.call(req).await.map(ServiceResponse::map_into_left_body)
+        }
+        None => {
+            let response = unauthenticated_response(req.request());
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+// Browsers navigating to a page get sent to the login form; htmx, fetch and JSON
+// callers get a plain 401 they can handle themselves.
+fn unauthenticated_response(req: &HttpRequest) -> HttpResponse {
+    let is_htmx = req.headers().contains_key("HX-Request");
+    let wants_html = req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if req.method() == Method::GET && wants_html && !is_htmx {
+        HttpResponse::Found().append_header((header::LOCATION, "/login")).finish()
+    } else {
+        HttpResponse::Unauthorized().body("Login required")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(role: Role, restricted: bool) -> user::Model {
+        user::Model {
+            id: 7,
+            username: "ann".to_string(),
+            password_hash: String::new(),
+            access_level: role.as_str().to_string(),
+            restricted,
+        }
+    }
+
+    fn grant(path: &str, rights: FolderRight) -> folder_grant::Model {
+        folder_grant::Model {
+            id: 1,
+            path: path.to_string(),
+            user_id: Some(7),
+            group_id: None,
+            rights: rights.as_str().to_string(),
+            created_by: 1,
+            created_at: 0,
+        }
+    }
+
+    fn current(role: Role, restricted: bool, grants: Vec<folder_grant::Model>) -> CurrentUser {
+        let user = account(role, restricted);
+        let grants = confined_to(&user, grants);
+        CurrentUser { user, role: Some(role), scope: None, grants }
+    }
+
+    #[test]
+    fn unrestricted_users_without_grants_keep_their_role() {
+        let viewer = current(Role::Viewer, false, Vec::new());
+        assert!(viewer.grants.is_none());
+        assert!(viewer.can_at(Permission::View, "videos/a.mp4"));
+        assert!(!viewer.can_at(Permission::Upload, "videos"));
+    }
+
+    #[test]
+    fn restricted_user_with_no_grants_left_sees_nothing() {
+        let user = current(Role::Editor, true, Vec::new());
+        assert_eq!(user.grants, Some(Vec::new()));
+        assert!(!user.can_at(Permission::View, ""));
+        assert!(!user.can_at(Permission::View, "videos/a.mp4"));
+        assert!(!user.can_see("videos"));
+    }
+
+    #[test]
+    fn grants_stop_at_siblings_sharing_a_prefix() {
+        let user = current(Role::Editor, true, vec![grant("foo", FolderRight::Read)]);
+        assert!(user.can_at(Permission::View, "foo"));
+        assert!(user.can_at(Permission::View, "foo/bar.mp4"));
+        assert!(!user.can_at(Permission::View, "foobar"));
+        assert!(!user.can_at(Permission::View, "foobar/bar.mp4"));
+        assert!(!user.can_see("foobar"));
+        // The root leads to the granted folder, so it's listed without being readable.
+        assert!(user.can_see(""));
+        assert!(!user.can_at(Permission::View, ""));
+    }
+
+    #[test]
+    fn root_grant_reaches_everything() {
+        let user = current(Role::Editor, true, vec![grant("", FolderRight::Upload)]);
+        assert!(user.can_at(Permission::View, "anything/at/all.mp4"));
+        assert!(user.can_at(Permission::Upload, "videos"));
+        assert!(!user.can_at(Permission::Edit, "videos/a.mp4"));
+    }
+
+    #[test]
+    fn grants_never_go_beyond_the_role() {
+        let viewer = current(Role::Viewer, true, vec![grant("foo", FolderRight::Edit)]);
+        assert!(viewer.can_at(Permission::View, "foo/a.mp4"));
+        assert!(!viewer.can_at(Permission::Upload, "foo"));
+        assert!(!viewer.can_at(Permission::Edit, "foo/a.mp4"));
+        assert!(!viewer.can_at(Permission::ManageFiles, "foo/a.mp4"));
+    }
+
+    #[test]
+    fn rights_below_the_needed_level_are_refused() {
+        let editor = current(Role::Editor, true, vec![grant("foo", FolderRight::Read)]);
+        assert!(editor.can_at(Permission::View, "foo/a.mp4"));
+        assert!(!editor.can_at(Permission::Upload, "foo"));
+        assert!(!editor.can_at(Permission::Edit, "foo/a.mp4"));
+    }
+}
@@ -0,0 +1,159 @@
+use sha2::{Digest as _, Sha256};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use xxhash_rust::xxh64::Xxh64;
+
+/// The hashes kept for every uploaded file. xxHash64 is what camera-card copy tools and
+/// MHL manifests use; SHA-256 is there for anyone who needs a cryptographic one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Digest {
+    pub size: u64,
+    pub xxh64: String,  // 16 hex digits, big-endian like `xxhsum` prints it
+    pub sha256: String, // 64 hex digits
+}
+
+/// Both hashes at once, fed as the bytes stream in.
+#[derive(Clone)]
+pub struct Hasher {
+    xxh64: Xxh64,
+    sha256: Sha256,
+    size: u64,
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher { xxh64: Xxh64::new(0), sha256: Sha256::new(), size: 0 }
+    }
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        self.xxh64.update(data);
+        self.sha256.update(data);
+        self.size += data.len() as u64;
+    }
+
+    /// Bytes hashed so far.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn finish(self) -> Digest {
+        Digest {
+            size: self.size,
+            xxh64: format!("{:016x}", self.xxh64.digest()),
+            sha256: hex::encode(self.sha256.finalize()),
+        }
+    }
+}
+
+/// Hash the file at `path` from the start, reading it in 1 MB blocks.
+pub fn hash_file(path: &Path) -> std::io::Result<Hasher> {
+    hash_file_reporting(path, |_| {})
+}
+
+/// `hash_file`, telling `on_progress` what fraction of the file has been read after each block.
+pub fn hash_file_reporting(path: &Path, mut on_progress: impl FnMut(f64)) -> std::io::Result<Hasher> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len().max(1) as f64;
+    let mut hasher = Hasher::default();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    let mut total = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher);
+        }
+        hasher.update(&buffer[..read]);
+        total += read;
+        on_progress((total as f64 / size).min(1.0));
+    }
+}
+
+/// How much of each end of a file goes into its fingerprint.
+const FINGERPRINT_BLOCK: u64 = 1024 * 1024;
+
+/// A quick identity for a file that survives renames: its size, and xxHash64 of its first
+/// and last megabyte. Telling copies of the same video apart needs the full hashes above.
+pub fn fingerprint(path: &Path) -> std::io::Result<(u64, String)> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut hasher = Xxh64::new(0);
+    let mut buffer = Vec::with_capacity(FINGERPRINT_BLOCK as usize);
+    (&mut file).take(FINGERPRINT_BLOCK).read_to_end(&mut buffer)?;
+    hasher.update(&buffer);
+    if size > FINGERPRINT_BLOCK {
+        // The tail, without reading the middle or the head twice.
+        file.seek(SeekFrom::Start(FINGERPRINT_BLOCK.max(size - FINGERPRINT_BLOCK)))?;
+        buffer.clear();
+        file.take(FINGERPRINT_BLOCK).read_to_end(&mut buffer)?;
+        hasher.update(&buffer);
+    }
+    Ok((size, format!("{:016x}", hasher.digest())))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Xxh64,
+    Sha256,
+}
+
+impl Algorithm {
+    pub fn from_str_case_insensitive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "xxh64" | "xxhash64" => Some(Algorithm::Xxh64),
+            "sha256" | "sha-256" => Some(Algorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Xxh64 => "xxh64",
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+/// A checksum the client sent along with a file, as `sha256:<hex>` or `xxh64:<hex>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedChecksum {
+    pub algorithm: Algorithm,
+    pub hex: String,
+}
+
+impl ExpectedChecksum {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Checksum '{}' should be sha256: or xxh64: followed by the hex digest", s);
+        let (algorithm, hex) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let algorithm = Algorithm::from_str_case_insensitive(algorithm).ok_or_else(invalid)?;
+        let hex = hex.trim().to_lowercase();
+        let digits = match algorithm {
+            Algorithm::Xxh64 => 16,
+            Algorithm::Sha256 => 64,
+        };
+        if hex.len() != digits || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        Ok(ExpectedChecksum { algorithm, hex })
+    }
+
+    /// `Err` describes the mismatch, for showing to whoever uploaded the file.
+    pub fn check(&self, digest: &Digest) -> Result<(), String> {
+        let actual = match self.algorithm {
+            Algorithm::Xxh64 => &digest.xxh64,
+            Algorithm::Sha256 => &digest.sha256,
+        };
+        if *actual == self.hex {
+            Ok(())
+        } else {
+            Err(format!(
+                "Checksum mismatch: expected {} {}, received {}",
+                self.algorithm.as_str(),
+                self.hex,
+                actual
+            ))
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use directories::ProjectDirs;
 use sea_orm::{ActiveModelTrait, ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, IntoActiveModel, Statement};
 use sea_orm::EntityTrait;
+use std::collections::HashMap;
 use std::path::{PathBuf};
-use crate::models::settings::Entity as SettingsEntity;
+use crate::models::settings::{Entity as SettingsEntity, Library, SignupMode};
+use crate::models::rendition::{Preset, DEFAULT_PROXY_PRESETS};
+use crate::models::trash_item::DEFAULT_RETENTION_DAYS;
 
 const DB_FILE: &str = "database.sqlite";
 
@@ -15,10 +18,15 @@ fn fallback_dir() -> PathBuf {
     std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
 }
 
-pub fn db_path() -> PathBuf {
+/// The directory holding `database.sqlite` and the other files the server keeps for itself.
+pub fn data_dir() -> PathBuf {
     let dir = project_data_dir().unwrap_or_else(fallback_dir);
     let _ = std::fs::create_dir_all(&dir);
-    dir.join(DB_FILE)
+    dir
+}
+
+pub fn db_path() -> PathBuf {
+    data_dir().join(DB_FILE)
 }
 
 pub async fn get_database() -> Result<DatabaseConnection, DbErr> {
@@ -60,16 +68,100 @@ pub async fn get_database() -> Result<DatabaseConnection, DbErr> {
             CREATE_CONVERSIONS_TABLE.to_string(),
         )).await?;
 
+        run_migrations(&db).await?;
         return Ok(db);
     }
 
     // Connect to the existing database
     let db_url = format!("sqlite://{}", db_path.to_string_lossy());
     let db = Database::connect(&db_url).await?;
+    run_migrations(&db).await?;
 
     Ok(db)
 }
 
+/// Schema added after the first release. Every statement is safe to run on each start:
+/// tables use IF NOT EXISTS and columns that already exist are skipped.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE settings ADD COLUMN signup_mode TEXT NOT NULL DEFAULT 'open'",
+    "ALTER TABLE settings ADD COLUMN libraries TEXT NOT NULL DEFAULT '[]'",
+    CREATE_INVITES_TABLE,
+    CREATE_LOGIN_ATTEMPTS_TABLE,
+    CREATE_API_TOKENS_TABLE,
+    CREATE_GROUPS_TABLE,
+    CREATE_GROUP_MEMBERS_TABLE,
+    CREATE_FOLDER_GRANTS_TABLE,
+    CREATE_SHARE_LINKS_TABLE,
+    CREATE_DROPBOXES_TABLE,
+    CREATE_DROPBOX_UPLOADS_TABLE,
+    CREATE_UPLOAD_SESSIONS_TABLE,
+    "ALTER TABLE upload_sessions ADD COLUMN conflict TEXT NOT NULL DEFAULT 'skip'",
+    "ALTER TABLE upload_sessions ADD COLUMN checksum TEXT",
+    CREATE_FILE_CHECKSUMS_TABLE,
+    CREATE_TRASH_ITEMS_TABLE,
+    "ALTER TABLE clips ADD COLUMN deleted_at BIGINT",
+    "ALTER TABLE points ADD COLUMN deleted_at BIGINT",
+    "ALTER TABLE tags ADD COLUMN deleted_at BIGINT",
+    "ALTER TABLE settings ADD COLUMN trash_retention_days INTEGER NOT NULL DEFAULT 30",
+    CREATE_MEDIA_TABLE,
+    "ALTER TABLE clips ADD COLUMN media_id INTEGER",
+    "ALTER TABLE points ADD COLUMN media_id INTEGER",
+    "ALTER TABLE tags ADD COLUMN media_id INTEGER",
+    "ALTER TABLE conversions ADD COLUMN media_id INTEGER",
+    "ALTER TABLE media ADD COLUMN duration REAL",
+    "ALTER TABLE media ADD COLUMN width INTEGER",
+    "ALTER TABLE media ADD COLUMN height INTEGER",
+    "ALTER TABLE conversions ADD COLUMN time_started BIGINT",
+    "ALTER TABLE conversions ADD COLUMN lease_expires_at BIGINT",
+    "ALTER TABLE conversions ADD COLUMN retry_at BIGINT",
+    "CREATE INDEX IF NOT EXISTS conversions_queue ON conversions (status, operation, time_requested)",
+    "ALTER TABLE settings ADD COLUMN conversion_workers TEXT NOT NULL DEFAULT '{}'",
+    "ALTER TABLE conversions ADD COLUMN progress REAL",
+    "ALTER TABLE conversions ADD COLUMN eta BIGINT",
+    "ALTER TABLE conversions ADD COLUMN error_kind TEXT",
+    "ALTER TABLE conversions ADD COLUMN error TEXT",
+    "ALTER TABLE conversions ADD COLUMN command TEXT",
+    "ALTER TABLE conversions ADD COLUMN error_output TEXT",
+    "ALTER TABLE conversions ADD COLUMN priority INTEGER NOT NULL DEFAULT 0",
+    "CREATE INDEX IF NOT EXISTS conversions_priority ON conversions (status, operation, priority, time_requested)",
+    CREATE_RENDITIONS_TABLE,
+    "ALTER TABLE settings ADD COLUMN proxy_presets TEXT NOT NULL DEFAULT '720p'",
+    "ALTER TABLE users ADD COLUMN restricted BOOLEAN NOT NULL DEFAULT 0",
+    "ALTER TABLE dropboxes ADD COLUMN reserved_bytes BIGINT NOT NULL DEFAULT 0",
+    // Reservations don't outlive the server; start again from what was actually uploaded.
+    "UPDATE dropboxes SET reserved_bytes = (SELECT COALESCE(SUM(size), 0) FROM dropbox_uploads WHERE dropbox_uploads.dropbox_id = dropboxes.id)",
+    // Users who had grants before the flag existed stay confined once they lose them.
+    "UPDATE users SET restricted = 1 WHERE id IN (SELECT user_id FROM folder_grants WHERE user_id IS NOT NULL) \
+        OR id IN (SELECT group_members.user_id FROM group_members JOIN folder_grants ON folder_grants.group_id = group_members.group_id)",
+    // Accounts created before this index could share a name; see run_migrations.
+    "CREATE UNIQUE INDEX IF NOT EXISTS users_username ON users (username)",
+    // Accounts from before roles were signed up as "None", which no longer grants anything.
+    // The oldest becomes the admin unless there already is one, and the rest become viewers.
+    "UPDATE users SET access_level = 'admin' WHERE lower(access_level) = 'none' \
+        AND id = (SELECT MIN(id) FROM users WHERE lower(access_level) = 'none') \
+        AND NOT EXISTS (SELECT 1 FROM users WHERE access_level = 'admin')",
+    "UPDATE users SET access_level = 'viewer' WHERE lower(access_level) = 'none'",
+    "ALTER TABLE login_attempts ADD COLUMN kind TEXT NOT NULL DEFAULT 'login'",
+    // Share passwords were briefly recorded as logins by "share:<token>"; they only mattered
+    // for throttling, so drop them rather than keep them in the login audit.
+    "DELETE FROM login_attempts WHERE kind = 'login' AND username LIKE 'share:%' AND username NOT IN (SELECT username FROM users)",
+];
+
+async fn run_migrations(db: &DatabaseConnection) -> Result<(), DbErr> {
+    for migration in MIGRATIONS {
+        match db.execute(Statement::from_string(DbBackend::Sqlite, migration.to_string())).await {
+            Ok(_) => {}
+            Err(e) if e.to_string().contains("duplicate column name") => {}
+            // Leave the index out rather than refuse to start; the signup check still applies.
+            Err(e) if e.to_string().contains("UNIQUE constraint failed") => {
+                eprintln!("Warning: some usernames are used by more than one account, rename them to enforce unique names: {}", e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
 const CREATE_USERS_TABLE: &str = r#"
 CREATE TABLE users (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -96,6 +188,165 @@ CREATE TABLE settings (
     ffmpeg_path TEXT NOT NULL
 );
 "#;
+pub const CREATE_LOGIN_ATTEMPTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS login_attempts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    success BOOLEAN NOT NULL,
+    time BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS login_attempts_username_time ON login_attempts (username, time);
+CREATE INDEX IF NOT EXISTS login_attempts_ip_time ON login_attempts (ip, time);
+"#;
+pub const CREATE_GROUPS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+"#;
+pub const CREATE_GROUP_MEMBERS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS group_members (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    group_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    UNIQUE (group_id, user_id)
+);
+"#;
+pub const CREATE_FOLDER_GRANTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS folder_grants (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL,
+    user_id INTEGER,
+    group_id INTEGER,
+    rights TEXT NOT NULL,
+    created_by INTEGER NOT NULL,
+    created_at BIGINT NOT NULL
+);
+"#;
+pub const CREATE_SHARE_LINKS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS share_links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token TEXT NOT NULL UNIQUE,
+    owner_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    path TEXT NOT NULL,
+    clip_id INTEGER,
+    mode TEXT NOT NULL,
+    password_hash TEXT,
+    expires_at BIGINT,
+    max_downloads INTEGER,
+    download_count INTEGER NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL,
+    revoked_at BIGINT
+);
+"#;
+pub const CREATE_DROPBOXES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS dropboxes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    path TEXT NOT NULL,
+    max_bytes BIGINT,
+    allowed_extensions TEXT NOT NULL DEFAULT '',
+    expires_at BIGINT,
+    created_by INTEGER NOT NULL,
+    created_at BIGINT NOT NULL,
+    revoked_at BIGINT
+);
+"#;
+pub const CREATE_DROPBOX_UPLOADS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS dropbox_uploads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    dropbox_id INTEGER NOT NULL,
+    filename TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    uploader_name TEXT NOT NULL,
+    uploader_email TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+"#;
+pub const CREATE_UPLOAD_SESSIONS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS upload_sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token TEXT NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    staging TEXT NOT NULL,
+    length BIGINT NOT NULL,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+"#;
+pub const CREATE_FILE_CHECKSUMS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS file_checksums (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE,
+    size BIGINT NOT NULL,
+    xxh64 TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    hashed_at BIGINT NOT NULL,
+    verified_at BIGINT,
+    status TEXT NOT NULL
+);
+"#;
+pub const CREATE_TRASH_ITEMS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS trash_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    original_path TEXT NOT NULL,
+    trash_path TEXT NOT NULL,
+    is_dir BOOLEAN NOT NULL,
+    deleted_by INTEGER NOT NULL,
+    deleted_at BIGINT NOT NULL
+);
+"#;
+pub const CREATE_MEDIA_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS media (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE,
+    size BIGINT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    first_seen_at BIGINT NOT NULL,
+    missing_since BIGINT
+);
+CREATE INDEX IF NOT EXISTS media_size ON media (size);
+"#;
+pub const CREATE_RENDITIONS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS renditions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source_filename TEXT NOT NULL,
+    preset TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    width INTEGER,
+    height INTEGER,
+    created_at BIGINT NOT NULL,
+    UNIQUE (source_filename, preset)
+);
+"#;
+pub const CREATE_API_TOKENS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scope TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    last_used_at BIGINT,
+    revoked_at BIGINT
+);
+"#;
+pub const CREATE_INVITES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS invites (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token TEXT NOT NULL UNIQUE,
+    access_level TEXT NOT NULL,
+    created_by INTEGER NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    used_by INTEGER,
+    used_at BIGINT
+);
+"#;
 const CREATE_POINTS_TABLE: &str = r#"
 CREATE TABLE points (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -131,18 +382,114 @@ pub async fn get_ffmpeg_path(db: &DatabaseConnection) -> Option<String> {
         .ok()
         .flatten()
         .map(|settings| settings.ffmpeg_path)
+        .filter(|path| !path.is_empty())
 }
 
 pub async fn set_ffmpeg_path(db: &DatabaseConnection, path: &str) -> Result<(), DbErr> {
-    let mut settings: crate::models::settings::ActiveModel = SettingsEntity::find()
+    let mut settings = settings_active_model(db).await?;
+    settings.ffmpeg_path = sea_orm::ActiveValue::Set(path.to_string());
+    settings.save(db).await.map(|_| ())
+}
+
+pub async fn get_signup_mode(db: &DatabaseConnection) -> SignupMode {
+    SettingsEntity::find()
+        .one(db)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|settings| SignupMode::from_str_case_insensitive(&settings.signup_mode))
+        .unwrap_or(SignupMode::Open)
+}
+
+pub async fn set_signup_mode(db: &DatabaseConnection, mode: SignupMode) -> Result<(), DbErr> {
+    let mut settings = settings_active_model(db).await?;
+    settings.signup_mode = sea_orm::ActiveValue::Set(mode.as_str().to_string());
+    settings.save(db).await.map(|_| ())
+}
+
+pub async fn get_libraries(db: &DatabaseConnection) -> Vec<Library> {
+    SettingsEntity::find()
+        .one(db)
+        .await
+        .ok()
+        .flatten()
+        .map(|settings| settings.libraries())
+        .unwrap_or_default()
+}
+
+pub async fn set_libraries(db: &DatabaseConnection, libraries: &[Library]) -> Result<(), DbErr> {
+    let json = serde_json::to_string(libraries).map_err(|e| DbErr::Custom(e.to_string()))?;
+    let mut settings = settings_active_model(db).await?;
+    settings.libraries = sea_orm::ActiveValue::Set(json);
+    settings.save(db).await.map(|_| ())
+}
+
+pub async fn get_trash_retention_days(db: &DatabaseConnection) -> i64 {
+    SettingsEntity::find()
+        .one(db)
+        .await
+        .ok()
+        .flatten()
+        .map(|settings| settings.trash_retention_days)
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+pub async fn set_trash_retention_days(db: &DatabaseConnection, days: i64) -> Result<(), DbErr> {
+    let mut settings = settings_active_model(db).await?;
+    settings.trash_retention_days = sea_orm::ActiveValue::Set(days);
+    settings.save(db).await.map(|_| ())
+}
+
+/// How many conversions of each operation run at once, by operation name.
+pub async fn get_conversion_workers(db: &DatabaseConnection) -> HashMap<String, usize> {
+    SettingsEntity::find()
+        .one(db)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|settings| serde_json::from_str(&settings.conversion_workers).ok())
+        .unwrap_or_default()
+}
+
+pub async fn set_conversion_workers(db: &DatabaseConnection, workers: &HashMap<String, usize>) -> Result<(), DbErr> {
+    let json = serde_json::to_string(workers).map_err(|e| DbErr::Custom(e.to_string()))?;
+    let mut settings = settings_active_model(db).await?;
+    settings.conversion_workers = sea_orm::ActiveValue::Set(json);
+    settings.save(db).await.map(|_| ())
+}
+
+/// The proxy presets scaledown makes, in the order they're made.
+pub async fn get_proxy_presets(db: &DatabaseConnection) -> Vec<&'static Preset> {
+    let names = SettingsEntity::find()
+        .one(db)
+        .await
+        .ok()
+        .flatten()
+        .map(|settings| settings.proxy_presets)
+        .unwrap_or_else(|| DEFAULT_PROXY_PRESETS.to_string());
+    names.split(',').filter_map(|name| Preset::find(name.trim())).collect()
+}
+
+pub async fn set_proxy_presets(db: &DatabaseConnection, presets: &[&Preset]) -> Result<(), DbErr> {
+    let names: Vec<&str> = presets.iter().map(|preset| preset.name).collect();
+    let mut settings = settings_active_model(db).await?;
+    settings.proxy_presets = sea_orm::ActiveValue::Set(names.join(","));
+    settings.save(db).await.map(|_| ())
+}
+
+// The single settings row, or a fresh one to insert if nothing has been saved yet.
+async fn settings_active_model(db: &DatabaseConnection) -> Result<crate::models::settings::ActiveModel, DbErr> {
+    Ok(SettingsEntity::find()
         .one(db)
         .await?
         .map(|m| m.into_active_model())
         .unwrap_or_else(|| crate::models::settings::ActiveModel {
             id: Default::default(),
-            ffmpeg_path: Default::default(),
-        });
-
-    settings.ffmpeg_path = sea_orm::ActiveValue::Set(path.to_string());
-    settings.update(db).await.map(|_| ())
+            ffmpeg_path: sea_orm::ActiveValue::Set(String::new()),
+            signup_mode: sea_orm::ActiveValue::Set(SignupMode::Open.as_str().to_string()),
+            libraries: sea_orm::ActiveValue::Set("[]".to_string()),
+            trash_retention_days: sea_orm::ActiveValue::Set(DEFAULT_RETENTION_DAYS),
+            conversion_workers: sea_orm::ActiveValue::Set("{}".to_string()),
+            proxy_presets: sea_orm::ActiveValue::Set(DEFAULT_PROXY_PRESETS.to_string()),
+        }))
 }
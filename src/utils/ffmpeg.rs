@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use crate::models::conversion::ErrorKind;
+use crate::utils::time::now;
+
+/// How long ffmpeg may go without reporting anything before it's taken to be stuck,
+/// e.g. reading from a network share that went away.
+pub const STALL: Duration = Duration::from_secs(600);
+
+/// Lines kept from the end of ffmpeg's error output.
+const TAIL_LINES: usize = 40;
+
+/// How far along an ffmpeg run is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub percent: Option<f64>, // unknown without an expected duration
+    pub eta: Option<i64>,     // when it should be done, as a timestamp
+}
+
+/// A failed ffmpeg run, with what's needed to tell why.
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub command: String, // as it was run, quoted for pasting into a shell
+    pub output: String,  // the end of what ffmpeg wrote to stderr
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error {
+    /// An error for `output`, classified by what ffmpeg said in it.
+    pub fn new(message: String, command: String, output: String) -> Error {
+        Error { kind: classify(&output), message, command, output }
+    }
+}
+
+/// Run ffmpeg with `args`, reading its `-progress` report as it goes. With the
+/// `expected` duration of the output in seconds, e.g. the probed length of the source,
+/// `on_progress` gets the percent done and an estimate of when it'll finish, at most
+/// once a second. Pass `ignore` when there's nothing to report to. ffmpeg is stopped
+/// if it reports nothing for `STALL`.
+pub async fn run<F: Future<Output = ()>>(
+    ffmpeg_path: &str,
+    args: &[String],
+    expected: Option<f64>,
+    mut on_progress: impl FnMut(Progress) -> F,
+) -> Result<(), Error> {
+    let mut all_args: Vec<String> = ["-hide_banner", "-nostats", "-progress", "pipe:1"].iter().map(|arg| arg.to_string()).collect();
+    all_args.extend_from_slice(args);
+    let command = command_line(ffmpeg_path, &all_args);
+    println!("Executing command: {}", command);
+    let mut child = Command::new(ffmpeg_path)
+        .args(&all_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Cancelling the conversion, or shutting down, stops ffmpeg too.
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| Error::new(format!("Failed to execute ffmpeg: {}", e), command.clone(), String::new()))?;
+    // Read alongside, or ffmpeg blocks once the pipe is full.
+    let stderr = child.stderr.take().map(|stderr| tokio::spawn(tail(stderr)));
+
+    let started = Instant::now();
+    let mut reported = started;
+    let mut out_time = None;
+    let mut stalled = false;
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        loop {
+            let line = match tokio::time::timeout(STALL, lines.next_line()).await {
+                Ok(Ok(Some(line))) => line,
+                Ok(_) => break,
+                Err(_) => {
+                    stalled = true;
+                    break;
+                }
+            };
+            let Some((key, value)) = line.split_once('=') else { continue };
+            match key {
+                // Both are microseconds, whatever the name says.
+                "out_time_us" | "out_time_ms" => {
+                    if let Ok(micros) = value.parse::<i64>() {
+                        out_time = Some(micros.max(0) as f64 / 1_000_000.0);
+                    }
+                }
+                // Each report ends with this line.
+                "progress" if value == "continue" && reported.elapsed().as_secs() >= 1 => {
+                    reported = Instant::now();
+                    on_progress(estimate(out_time, expected, started.elapsed().as_secs_f64())).await;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if stalled {
+        let _ = child.kill().await;
+    }
+    let status = child.wait().await;
+    let output = match stderr {
+        Some(task) => task.await.unwrap_or_default(),
+        None => String::new(),
+    };
+    if stalled {
+        let message = format!("ffmpeg reported nothing for {} seconds and was stopped", STALL.as_secs());
+        return Err(Error { kind: ErrorKind::Timeout, message, command, output });
+    }
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(Error::new(format!("ffmpeg failed with exit code: {}", status.code().unwrap_or(-1)), command, output)),
+        Err(e) => Err(Error::new(format!("Failed to wait for ffmpeg: {}", e), command, output)),
+    }
+}
+
+/// The last `TAIL_LINES` lines read from `stream`.
+pub async fn tail(stream: impl AsyncRead + Unpin) -> String {
+    let mut kept = VecDeque::with_capacity(TAIL_LINES);
+    let mut lines = BufReader::new(stream).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if kept.len() == TAIL_LINES {
+            kept.pop_front();
+        }
+        kept.push_back(line);
+    }
+    Vec::from(kept).join("\n")
+}
+
+/// `program` and `args` as one line that can be pasted into a shell.
+pub fn command_line(program: &str, args: &[String]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .map(|arg| {
+            if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c)) {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What kind of failure ffmpeg's error `output` describes, going by the messages it
+/// prints for each.
+fn classify(output: &str) -> ErrorKind {
+    let output = output.to_lowercase();
+    let says = |messages: &[&str]| messages.iter().any(|message| output.contains(message));
+    if says(&["no space left on device", "disk quota exceeded"]) {
+        ErrorKind::DiskFull
+    } else if says(&["no such file or directory", "does not exist"]) {
+        ErrorKind::MissingInput
+    } else if says(&[
+        "invalid data found when processing input",
+        "decoder not found",
+        "unknown decoder",
+        "unsupported codec",
+        "could not find codec parameters",
+        "no decoder for",
+        "encoder not found",
+        "unknown encoder",
+        "not supported by the bitstream filter",
+        "does not contain any stream",
+    ]) {
+        ErrorKind::UnsupportedCodec
+    } else {
+        ErrorKind::Other
+    }
+}
+
+impl Progress {
+    /// `fraction` of the work done in `elapsed` seconds, assuming the rest goes as fast.
+    pub fn at(fraction: f64, elapsed: f64) -> Progress {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let eta = (fraction > 0.0).then(|| now() + (elapsed * (1.0 - fraction) / fraction).round() as i64);
+        Progress { percent: Some(fraction * 100.0), eta }
+    }
+}
+
+fn estimate(out_time: Option<f64>, expected: Option<f64>, elapsed: f64) -> Progress {
+    match (out_time, expected) {
+        (Some(done), Some(total)) if total > 0.0 => Progress::at(done / total, elapsed),
+        _ => Progress { percent: None, eta: None },
+    }
+}
+
+/// An `on_progress` for `run` that does nothing with it.
+pub async fn ignore(_: Progress) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_at_projects_the_rest() {
+        let before = now();
+        let progress = Progress::at(0.25, 30.0);
+        assert_eq!(progress.percent, Some(25.0));
+        // Three times as long again for the remaining three quarters.
+        let eta = progress.eta.unwrap();
+        assert!((before + 90..=now() + 90).contains(&eta));
+    }
+
+    #[test]
+    fn progress_at_clamps_and_has_no_eta_before_starting() {
+        assert_eq!(Progress::at(0.0, 10.0), Progress { percent: Some(0.0), eta: None });
+        assert_eq!(Progress::at(-0.5, 10.0).percent, Some(0.0));
+        let done = Progress::at(1.5, 10.0);
+        assert_eq!(done.percent, Some(100.0));
+        assert!(done.eta.unwrap() <= now());
+    }
+
+    #[test]
+    fn estimate_needs_a_known_duration() {
+        let unknown = Progress { percent: None, eta: None };
+        assert_eq!(estimate(Some(5.0), None, 1.0), unknown);
+        assert_eq!(estimate(None, Some(10.0), 1.0), unknown);
+        assert_eq!(estimate(Some(5.0), Some(0.0), 1.0), unknown);
+        assert_eq!(estimate(Some(5.0), Some(10.0), 1.0).percent, Some(50.0));
+        // ffmpeg can report a little past the probed duration.
+        assert_eq!(estimate(Some(10.2), Some(10.0), 1.0).percent, Some(100.0));
+    }
+
+    #[test]
+    fn classify_reads_ffmpeg_messages() {
+        assert_eq!(classify("av_interleaved_write_frame(): No space left on device"), ErrorKind::DiskFull);
+        assert_eq!(classify("/media/a.mp4: No such file or directory"), ErrorKind::MissingInput);
+        assert_eq!(classify("a.mkv: Invalid data found when processing input"), ErrorKind::UnsupportedCodec);
+        assert_eq!(classify("Unknown encoder 'libx265'"), ErrorKind::UnsupportedCodec);
+        assert_eq!(classify("Conversion failed!"), ErrorKind::Other);
+        assert_eq!(classify(""), ErrorKind::Other);
+    }
+
+    #[test]
+    fn classify_prefers_disk_full() {
+        // Writing can fail on a full disk while the input also looked off.
+        let output = "Invalid data found when processing input\nError writing trailer: No space left on device";
+        assert_eq!(classify(output), ErrorKind::DiskFull);
+    }
+}
@@ -0,0 +1,570 @@
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait, QueryFilter, Set, Statement};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use crate::models::{clip, file_checksum, point, rendition, tag, trash_item};
+use crate::models::rendition::PROXIES_DIR;
+use crate::models::trash_item::TRASH_DIR;
+use crate::models::upload_session::STAGING_DIR;
+use crate::utils::auth::random_token;
+use crate::utils::libraries::Libraries;
+use crate::utils::media_index::link_rows;
+use crate::utils::paths::ServedPath;
+use crate::utils::time::now;
+use crate::utils::uploads::free_path;
+
+// Rename, move, copy and delete. Clips, points, tags, conversions and the rest refer to
+// files by their URL path, and a video's thumbnail, clips and proxies sit in `thumbs/`,
+// `segments/` and `proxies/` next to it, so all of those have to follow the file around.
+
+/// Columns holding the URL path of a file or folder, rewritten when it moves.
+const PATH_COLUMNS: &[(&str, &str)] = &[
+    ("clips", "source_filename"),
+    ("clips", "working_directory"),
+    ("points", "source_filename"),
+    ("tags", "source_filename"),
+    ("conversions", "source_filename"),
+    ("renditions", "source_filename"),
+    ("file_checksums", "path"),
+    ("media", "path"),
+    ("share_links", "path"),
+    ("dropboxes", "path"),
+    ("dropbox_uploads", "filename"),
+    ("upload_sessions", "path"),
+    ("upload_sessions", "staging"),
+];
+
+/// `path` moved from under `from` to under `to`, or None if it isn't `from` or inside it.
+pub fn rebase(path: &str, from: &str, to: &str) -> Option<String> {
+    if path == from {
+        return Some(to.to_string());
+    }
+    let rest = path.strip_prefix(from)?;
+    rest.starts_with('/').then(|| format!("{}{}", to, rest))
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("")
+}
+
+/// The root and the libraries themselves can't be renamed, moved or deleted.
+pub fn is_mount(libraries: &Libraries, relative: &str) -> bool {
+    relative.is_empty() || libraries.named.iter().any(|library| library.name == relative)
+}
+
+fn check_source(libraries: &Libraries, source: &ServedPath) -> Result<(), String> {
+    if is_mount(libraries, &source.relative) {
+        return Err("Libraries and the root folder can't be changed here".to_string());
+    }
+    if fs::symlink_metadata(&source.absolute).is_err() {
+        return Err(format!("{} does not exist", source.url()));
+    }
+    Ok(())
+}
+
+fn check_destination(source: &ServedPath, destination: &ServedPath) -> Result<(), String> {
+    if fs::symlink_metadata(&destination.absolute).is_ok() {
+        return Err(format!("{} already exists", destination.url()));
+    }
+    if destination.absolute.starts_with(&source.absolute) {
+        return Err("A folder can't be moved or copied into itself".to_string());
+    }
+    if destination.absolute.parent().is_none_or(|parent| !parent.is_dir()) {
+        return Err("The destination folder does not exist".to_string());
+    }
+    Ok(())
+}
+
+// ---- Filesystem ----
+
+fn copy_recursive(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            // Half-finished uploads stay where they are.
+            if entry.file_name() == STAGING_DIR {
+                continue;
+            }
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+fn remove_recursive(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() && !path.is_symlink() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+fn rename_or_copy(from: &Path, to: &Path) -> std::io::Result<()> {
+    match fs::rename(from, to) {
+        // Libraries can live on different disks.
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            copy_recursive(from, to)?;
+            remove_recursive(from)
+        }
+        result => result,
+    }
+}
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> std::io::Result<T> + Send + 'static) -> std::io::Result<T> {
+    tokio::task::spawn_blocking(f).await.map_err(std::io::Error::other)?
+}
+
+// ---- Artifacts ----
+
+/// The thumbnail, clip, point and proxy files made from `file`, relative to its folder.
+/// Clip files another video in the folder also uses are left out.
+async fn artifacts(db: &DatabaseConnection, file: &ServedPath, with_points: bool) -> Result<Vec<String>, sea_orm::DbErr> {
+    let path = file.relative.as_str();
+    let mut files = vec![format!("thumbs/{}.webp", file.file_name())];
+
+    let dir = file.parent_relative();
+    let clips = clip::Entity::find().filter(clip::Column::WorkingDirectory.eq(dir)).all(db).await?;
+    for clip in clips.iter().filter(|clip| clip.source_filename == path) {
+        let shared = clips
+            .iter()
+            .any(|other| other.source_filename != path && other.clip_filename == clip.clip_filename);
+        if !shared {
+            files.push(format!("segments/{}", clip.clip_filename));
+            files.push(format!("segments/thumbs/{}.webp", clip.clip_filename));
+        }
+    }
+
+    for made in rendition::Model::for_source(db, path).await? {
+        if let Some(preset) = made.preset() {
+            files.push(format!("{}/{}", PROXIES_DIR, preset.file_name(file.file_name())));
+        }
+    }
+
+    // `segments/point-{id}-{name}.mp4`, made on demand.
+    if with_points {
+        let points = point::Entity::find().filter(point::Column::SourceFilename.eq(path)).all(db).await?;
+        if !points.is_empty() {
+            let segments = file.absolute.with_file_name("segments");
+            let names: Vec<String> = fs::read_dir(&segments)
+                .map(|entries| entries.flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect())
+                .unwrap_or_default();
+            for point in points {
+                let prefix = format!("point-{}-", point.id);
+                files.extend(names.iter().filter(|n| n.starts_with(&prefix)).map(|n| format!("segments/{}", n)));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Move or copy the artifacts of a file along with it. Ones that would clobber something at
+/// the destination are left behind.
+fn carry_artifacts(artifacts: &[String], source: &ServedPath, destination: &ServedPath, copy: bool) {
+    let (Some(from_dir), Some(to_dir)) = (source.absolute.parent(), destination.absolute.parent()) else {
+        return;
+    };
+    // The thumbnail and proxies are named after the file, and get its new name.
+    let old_thumb = format!("thumbs/{}.webp", source.file_name());
+    let new_thumb = format!("thumbs/{}.webp", destination.file_name());
+    let old_proxy = format!("{}/{}.", PROXIES_DIR, source.file_name());
+    let new_proxy = format!("{}/{}.", PROXIES_DIR, destination.file_name());
+    for artifact in artifacts {
+        let from = from_dir.join(artifact);
+        let renamed = match artifact.strip_prefix(&old_proxy) {
+            _ if *artifact == old_thumb => new_thumb.clone(),
+            Some(rest) => format!("{}{}", new_proxy, rest),
+            None => artifact.clone(),
+        };
+        let to = to_dir.join(renamed);
+        if !from.is_file() || to.exists() {
+            continue;
+        }
+        if let Some(parent) = to.parent()
+            && let Err(e) = fs::create_dir_all(parent)
+        {
+            eprintln!("Error creating {}: {}", parent.display(), e);
+            continue;
+        }
+        let result = if copy { fs::copy(&from, &to).map(|_| ()) } else { rename_or_copy(&from, &to) };
+        if let Err(e) = result {
+            eprintln!("Error carrying {} to {}: {}", from.display(), to.display(), e);
+        }
+    }
+}
+
+// ---- Database ----
+
+async fn rewrite_paths(db: &DatabaseConnection, from: &str, to: &str) -> Result<(), sea_orm::DbErr> {
+    // Stale checksums and proxies at the destination would break their unique paths.
+    delete_under(db, "file_checksums", "path", to).await?;
+    delete_under(db, "renditions", "source_filename", to).await?;
+    let from_prefix = format!("{}/", from);
+    let prefix_len = from_prefix.chars().count() as i64;
+    for (table, column) in PATH_COLUMNS {
+        // Compared exactly: LIKE is case-insensitive and treats `_` and `%` as wildcards.
+        let sql = format!(
+            "UPDATE {table} SET {column} = ?1 || substr({column}, ?2) WHERE {column} = ?3 OR substr({column}, 1, ?2) = ?4"
+        );
+        db.execute(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            &sql,
+            [to.into(), prefix_len.into(), from.into(), from_prefix.clone().into()],
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+// SQL for "`column` is `path` or inside it", bound with `under_values(path)` as ?1 to ?3.
+// Compared exactly: LIKE is case-insensitive and treats `_` and `%` as wildcards.
+fn under(column: &str) -> String {
+    format!("({column} = ?1 OR substr({column}, 1, ?2) = ?3)")
+}
+
+fn under_values(path: &str) -> Vec<sea_orm::Value> {
+    let prefix = format!("{}/", path);
+    vec![path.into(), (prefix.chars().count() as i64).into(), prefix.into()]
+}
+
+async fn execute(db: &DatabaseConnection, sql: &str, values: Vec<sea_orm::Value>) -> Result<(), sea_orm::DbErr> {
+    db.execute(Statement::from_sql_and_values(DbBackend::Sqlite, sql, values)).await.map(|_| ())
+}
+
+async fn delete_under(db: &DatabaseConnection, table: &str, column: &str, path: &str) -> Result<(), sea_orm::DbErr> {
+    execute(db, &format!("DELETE FROM {table} WHERE {}", under(column)), under_values(path)).await
+}
+
+/// Cut `path` loose from what points at it from outside: share links and drop-boxes are
+/// revoked, so nothing later put at the same path becomes public by accident, and
+/// conversions still waiting are dropped.
+async fn detach(db: &DatabaseConnection, path: &str) -> Result<(), sea_orm::DbErr> {
+    let sql = format!("DELETE FROM conversions WHERE status IN ('pending', 'running') AND {}", under("source_filename"));
+    execute(db, &sql, under_values(path)).await?;
+    for table in ["share_links", "dropboxes"] {
+        let sql = format!("UPDATE {table} SET revoked_at = ?4 WHERE revoked_at IS NULL AND {}", under("path"));
+        let mut values = under_values(path);
+        values.push(now().into());
+        execute(db, &sql, values).await?;
+    }
+    Ok(())
+}
+
+/// Mark the clips, points and tags under `path` as deleted, or not deleted with `None`.
+async fn set_deleted_at(db: &DatabaseConnection, path: &str, deleted_at: Option<i64>) -> Result<(), sea_orm::DbErr> {
+    for table in ["clips", "points", "tags"] {
+        let sql = format!("UPDATE {table} SET deleted_at = ?4 WHERE {}", under("source_filename"));
+        let mut values = under_values(path);
+        values.push(deleted_at.into());
+        execute(db, &sql, values).await?;
+    }
+    Ok(())
+}
+
+/// Give copies of the clips, points, tags, checksums and proxies under `from` to the copy at `to`.
+async fn copy_rows(db: &DatabaseConnection, from: &str, to: &str) -> Result<(), sea_orm::DbErr> {
+    delete_under(db, "file_checksums", "path", to).await?;
+    delete_under(db, "renditions", "source_filename", to).await?;
+
+    for clip in clip::Entity::find().filter(clip::Column::SourceFilename.starts_with(from)).all(db).await? {
+        let Some(source_filename) = rebase(&clip.source_filename, from, to) else { continue };
+        clip::ActiveModel {
+            working_directory: Set(parent_of(&source_filename).to_string()),
+            source_filename: Set(source_filename),
+            clip_filename: Set(clip.clip_filename),
+            start: Set(clip.start),
+            end: Set(clip.end),
+            name: Set(clip.name),
+            description: Set(clip.description),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+    for point in point::Entity::find().filter(point::Column::SourceFilename.starts_with(from)).all(db).await? {
+        let Some(source_filename) = rebase(&point.source_filename, from, to) else { continue };
+        point::ActiveModel {
+            source_filename: Set(source_filename),
+            time: Set(point.time),
+            name: Set(point.name),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+    for tag in tag::Entity::find().filter(tag::Column::SourceFilename.starts_with(from)).all(db).await? {
+        let Some(source_filename) = rebase(&tag.source_filename, from, to) else { continue };
+        tag::ActiveModel::new(source_filename, tag.tag).insert(db).await?;
+    }
+    for made in rendition::Entity::find().filter(rendition::Column::SourceFilename.starts_with(from)).all(db).await? {
+        let Some(source_filename) = rebase(&made.source_filename, from, to) else { continue };
+        rendition::ActiveModel {
+            source_filename: Set(source_filename),
+            preset: Set(made.preset),
+            size: Set(made.size),
+            width: Set(made.width),
+            height: Set(made.height),
+            created_at: Set(made.created_at),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+    // The copy hashes the same as the original did, so later checks compare against that.
+    for checksum in file_checksum::Entity::find().filter(file_checksum::Column::Path.starts_with(from)).all(db).await? {
+        let Some(path) = rebase(&checksum.path, from, to) else { continue };
+        file_checksum::ActiveModel {
+            path: Set(path),
+            size: Set(checksum.size),
+            xxh64: Set(checksum.xxh64),
+            sha256: Set(checksum.sha256),
+            hashed_at: Set(checksum.hashed_at),
+            verified_at: Set(None),
+            status: Set(file_checksum::Status::Ok.as_str().to_string()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+    Ok(())
+}
+
+// ---- Operations ----
+
+/// Rename or move `source` to `destination`, which must not exist yet.
+pub async fn move_path(db: &DatabaseConnection, libraries: &Libraries, source: &ServedPath, destination: &ServedPath) -> Result<(), String> {
+    check_source(libraries, source)?;
+    check_destination(source, destination)?;
+    let is_file = !source.absolute.is_dir();
+    let artifacts = if is_file {
+        artifacts(db, source, true).await.map_err(|e| format!("Error finding related files: {}", e))?
+    } else {
+        Vec::new()
+    };
+
+    let (from, to) = (source.absolute.clone(), destination.absolute.clone());
+    blocking(move || rename_or_copy(&from, &to))
+        .await
+        .map_err(|e| format!("Error moving {}: {}", source.url(), e))?;
+    carry_artifacts(&artifacts, source, destination, false);
+
+    follow(db, source, destination, is_file)
+        .await
+        .map_err(|e| format!("Moved, but updating clips, points and tags failed: {}", e))
+}
+
+// Point the rows about `source` at `destination`, where it has just moved.
+async fn follow(db: &DatabaseConnection, source: &ServedPath, destination: &ServedPath, is_file: bool) -> Result<(), sea_orm::DbErr> {
+    rewrite_paths(db, &source.relative, &destination.relative).await?;
+    if is_file {
+        let sql = "UPDATE clips SET working_directory = ?1 WHERE source_filename = ?2";
+        execute(db, sql, vec![destination.parent_relative().into(), destination.relative.clone().into()]).await?;
+    }
+    Ok(())
+}
+
+/// Catch up with a file that was moved outside the app: its thumbnail and clips go where
+/// it is now, and its rows follow it.
+pub async fn relink(db: &DatabaseConnection, source: &ServedPath, destination: &ServedPath) -> Result<(), String> {
+    let artifacts = artifacts(db, source, true).await.map_err(|e| format!("Error finding related files: {}", e))?;
+    carry_artifacts(&artifacts, source, destination, false);
+    follow(db, source, destination, true)
+        .await
+        .map_err(|e| format!("Error updating clips, points and tags: {}", e))
+}
+
+/// Copy `source` to `destination`, which must not exist yet, with its clips, points and tags.
+pub async fn copy_path(db: &DatabaseConnection, libraries: &Libraries, source: &ServedPath, destination: &ServedPath) -> Result<(), String> {
+    check_source(libraries, source)?;
+    check_destination(source, destination)?;
+    let is_file = !source.absolute.is_dir();
+    // Point videos are named after the point id, and the copies get new ones.
+    let artifacts = if is_file {
+        artifacts(db, source, false).await.map_err(|e| format!("Error finding related files: {}", e))?
+    } else {
+        Vec::new()
+    };
+
+    let (from, to) = (source.absolute.clone(), destination.absolute.clone());
+    if let Err(e) = blocking(move || copy_recursive(&from, &to)).await {
+        // Don't leave half a copy behind.
+        let _ = remove_recursive(&destination.absolute);
+        return Err(format!("Error copying {}: {}", source.url(), e));
+    }
+    carry_artifacts(&artifacts, source, destination, true);
+
+    copy_rows(db, &source.relative, &destination.relative)
+        .await
+        .map_err(|e| format!("Copied, but copying clips, points and tags failed: {}", e))?;
+    // The copies are files of their own.
+    link_rows(db, libraries, Some(&destination.relative)).await.map(|_| ())
+}
+
+/// Delete `target` for good, along with its artifacts and everything in the database about it.
+pub async fn delete_path(db: &DatabaseConnection, libraries: &Libraries, target: &ServedPath) -> Result<(), String> {
+    check_source(libraries, target)?;
+    let artifacts = if target.absolute.is_dir() {
+        Vec::new()
+    } else {
+        artifacts(db, target, true).await.map_err(|e| format!("Error finding related files: {}", e))?
+    };
+
+    let path = target.absolute.clone();
+    blocking(move || remove_recursive(&path))
+        .await
+        .map_err(|e| format!("Error deleting {}: {}", target.url(), e))?;
+    if let Some(dir) = target.absolute.parent() {
+        for artifact in &artifacts {
+            let _ = fs::remove_file(dir.join(artifact));
+        }
+    }
+
+    let db_error = |e: sea_orm::DbErr| format!("Deleted, but removing clips, points and tags failed: {}", e);
+    detach(db, &target.relative).await.map_err(db_error)?;
+    for (table, column) in [
+        ("clips", "source_filename"),
+        ("points", "source_filename"),
+        ("tags", "source_filename"),
+        ("renditions", "source_filename"),
+        ("file_checksums", "path"),
+        ("media", "path"),
+    ] {
+        delete_under(db, table, column, &target.relative).await.map_err(db_error)?;
+    }
+    Ok(())
+}
+
+// ---- Trash ----
+
+/// The URL prefix and folder of the library `path` is in.
+fn mount_of<'a>(libraries: &'a Libraries, path: &str) -> (&'a str, &'a Path) {
+    let first = path.split('/').next().unwrap_or("");
+    libraries
+        .named
+        .iter()
+        .find(|library| library.name == first)
+        .map(|library| (library.name.as_str(), library.path.as_path()))
+        .unwrap_or(("", libraries.root.as_path()))
+}
+
+// `rest` inside the `.trash` folder of the library with URL prefix `prefix`. Built by hand
+// because resolving refuses anything in the trash.
+fn in_trash(prefix: &str, root: &Path, rest: &str) -> Result<ServedPath, String> {
+    let root = root.canonicalize().map_err(|e| format!("Error finding the trash: {}", e))?;
+    let inside = format!("{}/{}", TRASH_DIR, rest);
+    Ok(ServedPath {
+        absolute: rest.split('/').fold(root.join(TRASH_DIR), |path, part| path.join(part)),
+        relative: if prefix.is_empty() { inside } else { format!("{}/{}", prefix, inside) },
+    })
+}
+
+/// Where a trash item's file is kept.
+pub fn trashed_path(libraries: &Libraries, item: &trash_item::Model) -> Result<ServedPath, String> {
+    let (prefix, root) = mount_of(libraries, &item.trash_path);
+    let rest = if prefix.is_empty() { item.trash_path.as_str() } else { &item.trash_path[prefix.len() + 1..] };
+    match rest.strip_prefix(TRASH_DIR).and_then(|rest| rest.strip_prefix('/')) {
+        Some(rest) if !rest.split('/').any(|part| part.is_empty() || part == "." || part == "..") => in_trash(prefix, root, rest),
+        _ => Err(format!("{} is not in the trash", item.trash_path)),
+    }
+}
+
+/// Move `target` into the trash of its library instead of deleting it. Its clips, points
+/// and tags are kept, marked deleted, until it's restored or purged.
+pub async fn trash_path(db: &DatabaseConnection, libraries: &Libraries, target: &ServedPath, user_id: i32) -> Result<trash_item::Model, String> {
+    check_source(libraries, target)?;
+    let (prefix, root) = mount_of(libraries, &target.relative);
+    // Each item gets its own folder, so things deleted with the same name don't collide.
+    let destination = in_trash(prefix, root, &format!("{}/{}", random_token(), target.file_name()))?;
+    let holder = destination.absolute.parent().map(Path::to_path_buf).unwrap_or_default();
+    fs::create_dir_all(&holder).map_err(|e| format!("Error creating {}: {}", holder.display(), e))?;
+
+    let is_dir = target.absolute.is_dir();
+    detach(db, &target.relative).await.map_err(|e| format!("Error revoking links: {}", e))?;
+    if let Err(e) = move_path(db, libraries, target, &destination).await {
+        let _ = fs::remove_dir(&holder);
+        return Err(e);
+    }
+    let db_error = |e: sea_orm::DbErr| format!("Moved to the trash, but recording it failed: {}", e);
+    set_deleted_at(db, &destination.relative, Some(now())).await.map_err(db_error)?;
+    trash_item::ActiveModel {
+        original_path: Set(target.relative.clone()),
+        trash_path: Set(destination.relative),
+        is_dir: Set(is_dir),
+        deleted_by: Set(user_id),
+        deleted_at: Set(now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .map_err(db_error)
+}
+
+/// Put a trash item back where it was, or next to it as "name (1).ext" if something has
+/// taken its place since. Returns where it ended up.
+pub async fn restore_path(db: &DatabaseConnection, libraries: &Libraries, item: &trash_item::Model) -> Result<ServedPath, String> {
+    let source = trashed_path(libraries, item)?;
+    let mut destination = libraries.resolve(&item.original_path).map_err(|e| e.to_string())?;
+    if fs::symlink_metadata(&destination.absolute).is_ok() {
+        destination = free_path(libraries, &destination).map_err(|e| e.to_string())?;
+    }
+    // The folder it was in may have been deleted too.
+    if let Some(parent) = destination.absolute.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Error creating {}: {}", parent.display(), e))?;
+    }
+    move_path(db, libraries, &source, &destination).await?;
+
+    let db_error = |e: sea_orm::DbErr| format!("Restored, but updating the trash failed: {}", e);
+    set_deleted_at(db, &destination.relative, None).await.map_err(db_error)?;
+    trash_item::Entity::delete_by_id(item.id).exec(db).await.map_err(db_error)?;
+    // Anything that couldn't go back, e.g. a thumbnail that was made again meanwhile.
+    if let Some(holder) = source.absolute.parent() {
+        let _ = fs::remove_dir_all(holder);
+    }
+    Ok(destination)
+}
+
+/// Delete a trash item for good.
+pub async fn purge_path(db: &DatabaseConnection, libraries: &Libraries, item: &trash_item::Model) -> Result<(), String> {
+    let trashed = trashed_path(libraries, item)?;
+    // Already gone from disk: only the record is left to remove.
+    if fs::symlink_metadata(&trashed.absolute).is_ok() {
+        delete_path(db, libraries, &trashed).await?;
+    }
+    if let Some(holder) = trashed.absolute.parent() {
+        let _ = fs::remove_dir_all(holder);
+    }
+    trash_item::Entity::delete_by_id(item.id)
+        .exec(db)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Purged, but removing it from the trash list failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebase_moves_the_path_and_what_is_inside() {
+        assert_eq!(rebase("a/b", "a/b", "c").as_deref(), Some("c"));
+        assert_eq!(rebase("a/b/x.mp4", "a/b", "c/d").as_deref(), Some("c/d/x.mp4"));
+        assert_eq!(rebase("a/b/x/y.mp4", "a/b", "z").as_deref(), Some("z/x/y.mp4"));
+    }
+
+    #[test]
+    fn rebase_leaves_siblings_with_the_same_prefix() {
+        assert_eq!(rebase("a/bc", "a/b", "c"), None);
+        assert_eq!(rebase("a/bc/x.mp4", "a/b", "c"), None);
+        assert_eq!(rebase("a/b.mp4", "a/b", "c"), None);
+        assert_eq!(rebase("a", "a/b", "c"), None);
+        assert_eq!(rebase("x/a/b", "a/b", "c"), None);
+    }
+
+    #[test]
+    fn parent_of_top_level_is_the_root() {
+        assert_eq!(parent_of("a/b/c.mp4"), "a/b");
+        assert_eq!(parent_of("c.mp4"), "");
+    }
+}
@@ -0,0 +1,30 @@
+/// `s` made safe to put in HTML text or a quoted attribute value.
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// A `/`-separated path with each segment percent-encoded, safe to put in an
+/// `href` or `src` as it is.
+pub fn url_path(path: &str) -> String {
+    path.split('/').map(|segment| urlencoding::encode(segment)).collect::<Vec<_>>().join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_covers_text_and_both_quotes() {
+        assert_eq!(escape("x'><img src=\"y\">&"), "x&#39;&gt;&lt;img src=&quot;y&quot;&gt;&amp;");
+    }
+
+    #[test]
+    fn url_path_encodes_each_segment() {
+        assert_eq!(url_path("/a b/x'><i>.mp4"), "/a%20b/x%27%3E%3Ci%3E.mp4");
+        assert_eq!(url_path("a/b"), "a/b");
+    }
+}
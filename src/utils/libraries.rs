@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+use crate::models::settings::Library;
+use crate::utils::paths::{child_path, resolve, PathError, ServedPath};
+
+/// Everything the server serves: the root folder at `/`, plus each named library
+/// under `/{name}/`. A library shadows a root folder of the same name.
+#[derive(Clone, Debug)]
+pub struct Libraries {
+    pub root: PathBuf,
+    pub named: Vec<Library>,
+}
+
+impl Libraries {
+    pub fn new(root: PathBuf, named: Vec<Library>) -> Self {
+        Libraries { root, named }
+    }
+
+    /// Map a URL path (without its leading `/`) to a file in the library it belongs to.
+    /// `relative` in the result keeps the library prefix, so it round-trips as a URL.
+    pub fn resolve(&self, requested: &str) -> Result<ServedPath, PathError> {
+        let (first, rest) = requested.split_once('/').unwrap_or((requested, ""));
+        match self.named.iter().find(|library| library.name == first) {
+            Some(library) => {
+                let mut resolved = resolve(&library.path, rest)?;
+                resolved.relative = if resolved.relative.is_empty() {
+                    library.name.clone()
+                } else {
+                    format!("{}/{}", library.name, resolved.relative)
+                };
+                Ok(resolved)
+            }
+            None => resolve(&self.root, requested),
+        }
+    }
+
+    /// Resolve a single file or folder name (e.g. an upload filename) inside the URL path `dir`.
+    pub fn resolve_child(&self, dir: &str, name: &str) -> Result<ServedPath, PathError> {
+        self.resolve(&child_path(dir, name)?)
+    }
+
+    /// The URL prefix and folder of the root and every library, for walking all of them.
+    pub fn mounts(&self) -> Vec<(&str, &Path)> {
+        std::iter::once(("", self.root.as_path()))
+            .chain(self.named.iter().map(|library| (library.name.as_str(), library.path.as_path())))
+            .collect()
+    }
+
+    /// The URL path of something on disk, e.g. a path reported by the watcher; `None` if
+    /// it isn't served, or is shadowed by a library.
+    pub fn locate(&self, absolute: &Path) -> Option<String> {
+        // Libraries can live inside the root folder, so the deepest match wins.
+        let (prefix, rest) = self
+            .mounts()
+            .into_iter()
+            .filter_map(|(prefix, root)| absolute.strip_prefix(root).ok().map(|rest| (prefix, rest)))
+            .min_by_key(|(_, rest)| rest.components().count())?;
+        let rest = rest.to_str()?.replace(std::path::MAIN_SEPARATOR, "/");
+        if prefix.is_empty() {
+            let first = rest.split('/').next().unwrap_or_default();
+            if self.named.iter().any(|library| library.name == first) {
+                return None;
+            }
+            return Some(rest);
+        }
+        Some(if rest.is_empty() { prefix.to_string() } else { format!("{}/{}", prefix, rest) })
+    }
+}
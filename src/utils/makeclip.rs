@@ -1,5 +1,5 @@
-use std::process::{Command, Stdio};
 use std::path::{Path, PathBuf};
+use crate::utils::ffmpeg;
 
 pub fn create_clip_video(
     source: &str,
@@ -18,11 +18,11 @@ pub fn create_clip_video(
     let output_path = Path::new(output_path);
 
     // Ensure the parent directory exists
-    if let Some(parent) = output_path.parent() {
-        if !parent.exists() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| format!("Failed to create output directory: {}", e))?;
-        }
+    if let Some(parent) = output_path.parent()
+        && !parent.exists()
+    {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
     }
 
     let args = vec![
@@ -37,15 +37,21 @@ pub fn create_clip_video(
         output_path.to_string_lossy().to_string(),
     ];
 
-    println!("Running command: {} {}", ffmpeg_path, args.join(" "));
-
-    Command::new(&ffmpeg_path)
-        .args(&args)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()
-        .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+    // Runs on after the request that asked for it has been answered.
+    let clip = output_path.display().to_string();
+    let expected = duration as f64 / 1000.0;
+    tokio::spawn(async move {
+        let report = |progress: ffmpeg::Progress| {
+            if let Some(percent) = progress.percent {
+                println!("Making clip {}: {:.0}%", clip, percent);
+            }
+            std::future::ready(())
+        };
+        match ffmpeg::run(&ffmpeg_path, &args, Some(expected), report).await {
+            Ok(()) => println!("Clip created: {}", clip),
+            Err(e) => eprintln!("Error creating clip {}: {}", clip, e),
+        }
+    });
 
     Ok(output_path.to_path_buf())
 }
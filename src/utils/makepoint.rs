@@ -1,9 +1,9 @@
-use std::process::{Command, Stdio};
 use std::path::{Path, PathBuf};
+use crate::utils::ffmpeg;
 
 /// Creates a video clip centered around a point (3 seconds before and after)
 /// Returns the output path on success, or an error string on failure
-pub fn create_point_video(
+pub async fn create_point_video(
 	source: &str,
 	point_time: i64, // Milliseconds
 	output_path: &str, // Full output file path
@@ -21,11 +21,11 @@ pub fn create_point_video(
 	let output_path = Path::new(output_path);
 
 	// Ensure the parent directory exists
-	if let Some(parent) = output_path.parent() {
-		if !parent.exists() {
-			std::fs::create_dir_all(parent)
-				.map_err(|e| format!("Failed to create output directory: {}", e))?;
-		}
+	if let Some(parent) = output_path.parent()
+		&& !parent.exists()
+	{
+		std::fs::create_dir_all(parent)
+			.map_err(|e| format!("Failed to create output directory: {}", e))?;
 	}
 
 	let args = vec![
@@ -40,19 +40,7 @@ pub fn create_point_video(
 		output_path.to_string_lossy().to_string(),
 	];
 
-	println!("Running command: {} {}", ffmpeg_path, args.join(" "));
-
-	let status = Command::new(&ffmpeg_path)
-		.args(&args)
-		.stdin(Stdio::null())
-		.stdout(Stdio::null())
-		.stderr(Stdio::null())
-		.status()
-		.map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
-
-	if !status.success() {
-		return Err(format!("ffmpeg failed with exit code: {}", status.code().unwrap_or(-1)));
-	}
+	ffmpeg::run(&ffmpeg_path, &args, None, ffmpeg::ignore).await.map_err(|e| e.to_string())?;
 
 	Ok(output_path.to_path_buf())
 }
@@ -0,0 +1,398 @@
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait, QueryFilter, Set, Statement};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::models::{conversion, media};
+use crate::models::rendition::PROXIES_DIR;
+use crate::models::trash_item::TRASH_DIR;
+use crate::models::upload_session::STAGING_DIR;
+use crate::utils::checksums::fingerprint;
+use crate::utils::file_ops::{rebase, relink};
+use crate::utils::libraries::Libraries;
+use crate::utils::paths::ServedPath;
+use crate::utils::time::now;
+use crate::utils::uploads::VERSIONS_DIR;
+
+/// Folders of things made from media or on their way in, rather than media themselves.
+pub const INTERNAL_DIRS: &[&str] = &["thumbs", "segments", PROXIES_DIR, STAGING_DIR, VERSIONS_DIR, TRASH_DIR];
+
+/// Tables whose rows belong to a file through `media_id` and `source_filename`.
+const MEDIA_TABLES: &[&str] = &["clips", "points", "tags", "conversions"];
+
+/// Every file under `dir`, as its URL path and where it is on disk, leaving out `INTERNAL_DIRS`
+/// and symlinks, which could lead anywhere on the disk.
+pub fn walk(dir: &Path, relative: &str) -> std::io::Result<Vec<(String, PathBuf)>> {
+    let mut found = Vec::new();
+    walk_into(dir, relative, &mut found)?;
+    Ok(found)
+}
+
+fn walk_into(dir: &Path, relative: &str, found: &mut Vec<(String, PathBuf)>) -> std::io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.flatten().collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if INTERNAL_DIRS.contains(&file_name.as_str()) {
+            continue;
+        }
+        let child = if relative.is_empty() { file_name } else { format!("{}/{}", relative, file_name) };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk_into(&entry.path(), &child, found)?;
+        } else if file_type.is_file() {
+            found.push((child, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+// Trashed files keep their media while they can still be restored, but nothing
+// looks for them there.
+fn in_trash(path: &str) -> bool {
+    path.split('/').any(|part| part == TRASH_DIR)
+}
+
+async fn fingerprint_of(path: PathBuf) -> Result<(u64, String), String> {
+    tokio::task::spawn_blocking(move || fingerprint(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Error reading file: {}", e))
+}
+
+/// Extensions shown as videos, which get a thumbnail and a probe when first seen.
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "avi", "mov", "mkv", "webm"];
+
+pub fn is_video(name: &str) -> bool {
+    let ext = name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+    VIDEO_EXTENSIONS.contains(&ext.as_str())
+}
+
+/// The media record for `file`, made the first time it's asked for.
+pub async fn register(db: &DatabaseConnection, file: &ServedPath) -> Result<media::Model, String> {
+    let db_error = |e: sea_orm::DbErr| format!("Error recording media: {}", e);
+    if let Some(existing) = media::Model::find_by_path(db, &file.relative).await.map_err(db_error)? {
+        return Ok(existing);
+    }
+    let (size, fingerprint) = fingerprint_of(file.absolute.clone()).await?;
+    insert(db, file, size, fingerprint).await.map_err(db_error)
+}
+
+// The watcher and a request can come across a new file at the same time; whoever
+// inserts second gets the first one's record.
+async fn insert(db: &DatabaseConnection, file: &ServedPath, size: u64, fingerprint: String) -> Result<media::Model, sea_orm::DbErr> {
+    let inserted = media::Entity::insert(media::ActiveModel {
+        path: Set(file.relative.clone()),
+        size: Set(size as i64),
+        fingerprint: Set(fingerprint),
+        first_seen_at: Set(now()),
+        missing_since: Set(None),
+        ..Default::default()
+    })
+    .on_conflict(OnConflict::column(media::Column::Path).do_nothing().to_owned())
+    .exec(db)
+    .await;
+    let is_new = match inserted {
+        Ok(_) => true,
+        Err(sea_orm::DbErr::RecordNotInserted) => false,
+        Err(e) => return Err(e),
+    };
+    let media = media::Model::find_by_path(db, &file.relative)
+        .await?
+        .ok_or_else(|| sea_orm::DbErr::RecordNotFound(file.relative.clone()))?;
+    if is_new && is_video(file.file_name()) {
+        queue_conversions(db, file).await;
+    }
+    Ok(media)
+}
+
+// A thumbnail for the listings and the duration and size, so neither waits for someone
+// to open the folder.
+async fn queue_conversions(db: &DatabaseConnection, file: &ServedPath) {
+    let thumbnail = file.absolute.with_file_name("thumbs").join(format!("{}.webp", file.file_name()));
+    let mut operations = vec!["probe"];
+    if !thumbnail.exists() {
+        operations.insert(0, "thumbnail");
+    }
+    for operation in operations {
+        if let Err(e) = conversion::Model::request_conversion(db, file.relative.clone(), operation.to_string()).await {
+            eprintln!("Error queueing {} for {}: {}", operation, file.relative, e);
+        }
+    }
+}
+
+/// `register` for rows being created: they're still worth keeping without a media id,
+/// and a rescan links them later.
+pub async fn media_id(db: &DatabaseConnection, file: &ServedPath) -> Option<i32> {
+    match register(db, file).await {
+        Ok(media) => Some(media.id),
+        Err(e) => {
+            eprintln!("Error registering {}: {}", file.relative, e);
+            None
+        }
+    }
+}
+
+/// Give rows without a media id the one of the file they name, for the files that still
+/// exist, inside `under` or everywhere. Returns how many files were linked.
+pub async fn link_rows(db: &DatabaseConnection, libraries: &Libraries, under: Option<&str>) -> Result<usize, String> {
+    let db_error = |e: sea_orm::DbErr| format!("Error linking media: {}", e);
+    let mut paths = HashSet::new();
+    for table in MEDIA_TABLES {
+        let sql = format!("SELECT DISTINCT source_filename FROM {table} WHERE media_id IS NULL");
+        let rows = db.query_all(Statement::from_string(DbBackend::Sqlite, sql)).await.map_err(db_error)?;
+        for row in rows {
+            let path: String = row.try_get("", "source_filename").map_err(db_error)?;
+            if under.is_none_or(|under| rebase(&path, under, under).is_some()) && !in_trash(&path) {
+                paths.insert(path);
+            }
+        }
+    }
+
+    let mut linked = 0;
+    for path in paths {
+        let Ok(file) = libraries.resolve(&path) else { continue };
+        if !file.absolute.is_file() {
+            continue;
+        }
+        let media = register(db, &file).await?;
+        for table in MEDIA_TABLES {
+            let sql = format!("UPDATE {table} SET media_id = ?1 WHERE media_id IS NULL AND source_filename = ?2");
+            db.execute(Statement::from_sql_and_values(DbBackend::Sqlite, &sql, [media.id.into(), path.clone().into()]))
+                .await
+                .map_err(db_error)?;
+        }
+        linked += 1;
+    }
+    Ok(linked)
+}
+
+#[derive(Debug, Default)]
+pub struct RescanReport {
+    /// Files whose clips, points and tags had no media id yet.
+    pub linked: usize,
+    /// Files seen for the first time.
+    pub added: usize,
+    /// Files found again somewhere else, from and to.
+    pub relinked: Vec<(String, String)>,
+    /// Files that are nowhere to be found.
+    pub missing: Vec<String>,
+}
+
+// Identical copies can't be told apart, unless one kept the name.
+fn pick<'a>(matches: &[&'a str], name: &str) -> Option<&'a str> {
+    match matches {
+        [] => None,
+        [only] => Some(*only),
+        _ => matches.iter().copied().find(|path| path.rsplit('/').next() == Some(name)),
+    }
+}
+
+fn is_lost(libraries: &Libraries, media: &media::Model) -> bool {
+    !in_trash(&media.path) && !libraries.resolve(&media.path).is_ok_and(|file| file.absolute.is_file())
+}
+
+/// Bring the media index up to date with a file, folder or path that just changed on
+/// disk: files are recorded, or followed from where they were if they match one that
+/// disappeared, and whatever is gone is marked missing.
+pub async fn index_path(db: &DatabaseConnection, libraries: &Libraries, path: &ServedPath) -> Result<RescanReport, String> {
+    let mut report = RescanReport::default();
+    if in_trash(&path.relative) || path.relative.split('/').any(|part| INTERNAL_DIRS.contains(&part)) {
+        return Ok(report);
+    }
+    if path.absolute.is_dir() {
+        let (dir, relative) = (path.absolute.clone(), path.relative.clone());
+        let files = tokio::task::spawn_blocking(move || walk(&dir, &relative))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("Error reading folders: {}", e))?;
+        for (relative, absolute) in files {
+            index_file(db, libraries, &ServedPath { absolute, relative }, &mut report).await?;
+        }
+    } else if path.absolute.is_file() {
+        index_file(db, libraries, path, &mut report).await?;
+    }
+    mark_gone(db, libraries, &path.relative, &mut report).await
+        .map_err(|e| format!("Error updating media: {}", e))?;
+    Ok(report)
+}
+
+async fn index_file(db: &DatabaseConnection, libraries: &Libraries, file: &ServedPath, report: &mut RescanReport) -> Result<(), String> {
+    let db_error = |e: sea_orm::DbErr| format!("Error recording media: {}", e);
+    let size = fs::metadata(&file.absolute).map_err(|e| format!("Error reading file: {}", e))?.len();
+    if let Some(existing) = media::Model::find_by_path(db, &file.relative).await.map_err(db_error)? {
+        if existing.size != size as i64 {
+            // Written over in place, e.g. by an upload replacing it.
+            let (size, fingerprint) = fingerprint_of(file.absolute.clone()).await?;
+            let mut changed: media::ActiveModel = existing.into();
+            changed.size = Set(size as i64);
+            changed.fingerprint = Set(fingerprint);
+            changed.missing_since = Set(None);
+            changed.update(db).await.map_err(db_error)?;
+        } else if existing.missing_since.is_some() {
+            set_missing_since(db, existing.id, None).await.map_err(db_error)?;
+        }
+        return Ok(());
+    }
+
+    let (size, fingerprint) = fingerprint_of(file.absolute.clone()).await?;
+    let same = media::Entity::find()
+        .filter(media::Column::Size.eq(size as i64))
+        .filter(media::Column::Fingerprint.eq(&fingerprint))
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let lost: Vec<&str> = same
+        .iter()
+        .filter(|media| is_lost(libraries, media))
+        .map(|media| media.path.as_str())
+        .collect();
+    if let Some(from) = pick(&lost, file.file_name()).map(String::from) {
+        let from_file = libraries.resolve(&from).map_err(|e| e.to_string())?;
+        relink(db, &from_file, file).await?;
+        if let Some(media) = same.iter().find(|media| media.path == from) {
+            set_missing_since(db, media.id, None).await.map_err(db_error)?;
+        }
+        report.relinked.push((from, file.relative.clone()));
+        return Ok(());
+    }
+    insert(db, file, size, fingerprint).await.map_err(db_error)?;
+    report.added += 1;
+    Ok(())
+}
+
+// Media at or under `relative` whose file has gone.
+async fn mark_gone(db: &DatabaseConnection, libraries: &Libraries, relative: &str, report: &mut RescanReport) -> Result<(), sea_orm::DbErr> {
+    let candidates = media::Entity::find()
+        .filter(media::Column::Path.starts_with(relative))
+        .filter(media::Column::MissingSince.is_null())
+        .all(db)
+        .await?;
+    // LIKE also matches `a/bc` for `a/b`, and ignores case.
+    for media in candidates.iter().filter(|media| rebase(&media.path, relative, relative).is_some()) {
+        if is_lost(libraries, media) {
+            set_missing_since(db, media.id, Some(now())).await?;
+            report.missing.push(media.path.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Bring the whole media index up to date, for what the watcher missed: while the server
+/// was down, or on filesystems that don't report changes. Files that aren't where they
+/// were are looked for by fingerprint among the ones not recorded yet, and their clips,
+/// points, tags and thumbnails follow them if exactly one matches. The rest of those are
+/// recorded as new, and what's still unaccounted for is marked missing.
+pub async fn rescan(db: &DatabaseConnection, libraries: &Libraries) -> Result<RescanReport, String> {
+    let db_error = |e: sea_orm::DbErr| format!("Error updating media: {}", e);
+    let mut report = RescanReport { linked: link_rows(db, libraries, None).await?, ..Default::default() };
+
+    let all = media::Entity::find().all(db).await.map_err(db_error)?;
+    let mut lost = Vec::new();
+    for media in all.iter().filter(|media| !in_trash(&media.path)) {
+        if is_lost(libraries, media) {
+            lost.push(media);
+        } else if media.missing_since.is_some() {
+            // Put back by hand since the last rescan.
+            set_missing_since(db, media.id, None).await.map_err(db_error)?;
+        }
+    }
+
+    // Files nobody has media for, by size, since a moved file keeps its size.
+    let known: HashSet<&str> = all.iter().map(|media| media.path.as_str()).collect();
+    let mut unknown: HashMap<u64, Vec<(String, PathBuf)>> = HashMap::new();
+    for (prefix, root) in libraries.mounts() {
+        let root = root.to_path_buf();
+        let prefix = prefix.to_string();
+        let files = tokio::task::spawn_blocking(move || walk(&root, &prefix))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("Error reading folders: {}", e))?;
+        for (relative, absolute) in files {
+            if known.contains(relative.as_str()) {
+                continue;
+            }
+            if let Ok(metadata) = fs::metadata(&absolute) {
+                unknown.entry(metadata.len()).or_default().push((relative, absolute));
+            }
+        }
+    }
+
+    let mut fingerprints: HashMap<PathBuf, String> = HashMap::new();
+    let mut claimed = HashSet::new();
+    for media in lost {
+        let mut matches = Vec::new();
+        for (relative, absolute) in unknown.get(&(media.size as u64)).into_iter().flatten() {
+            if claimed.contains(relative) {
+                continue;
+            }
+            if !fingerprints.contains_key(absolute) {
+                let (_, fingerprint) = fingerprint_of(absolute.clone()).await?;
+                fingerprints.insert(absolute.clone(), fingerprint);
+            }
+            if fingerprints[absolute] == media.fingerprint {
+                matches.push(relative.as_str());
+            }
+        }
+        let name = media.path.rsplit('/').next().unwrap_or_default();
+        let Some(found) = pick(&matches, name) else {
+            if media.missing_since.is_none() {
+                set_missing_since(db, media.id, Some(now())).await.map_err(db_error)?;
+            }
+            report.missing.push(media.path.clone());
+            continue;
+        };
+        let (from, to) = match (libraries.resolve(&media.path), libraries.resolve(found)) {
+            (Ok(from), Ok(to)) => (from, to),
+            (Err(e), _) | (_, Err(e)) => return Err(e.to_string()),
+        };
+        relink(db, &from, &to).await?;
+        set_missing_since(db, media.id, None).await.map_err(db_error)?;
+        claimed.insert(found.to_string());
+        report.relinked.push((media.path.clone(), found.to_string()));
+    }
+
+    for (size, files) in unknown {
+        for (relative, absolute) in files {
+            if claimed.contains(&relative) {
+                continue;
+            }
+            let fingerprint = match fingerprints.remove(&absolute) {
+                Some(fingerprint) => fingerprint,
+                None => fingerprint_of(absolute.clone()).await?.1,
+            };
+            insert(db, &ServedPath { absolute, relative }, size, fingerprint).await.map_err(db_error)?;
+            report.added += 1;
+        }
+    }
+    Ok(report)
+}
+
+async fn set_missing_since(db: &DatabaseConnection, id: i32, missing_since: Option<i64>) -> Result<(), sea_orm::DbErr> {
+    media::Entity::update_many()
+        .col_expr(media::Column::MissingSince, sea_orm::sea_query::Expr::value(missing_since))
+        .filter(media::Column::Id.eq(id))
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_skips_internal_dirs_and_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(root.join("videos/thumbs")).unwrap();
+        fs::write(root.join("videos/a.mp4"), b"").unwrap();
+        fs::write(root.join("videos/thumbs/a.mp4.webp"), b"").unwrap();
+        fs::write(dir.path().join("secret.txt"), b"").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), root.join("videos/secret.txt")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), root.join("videos/outside")).unwrap();
+        let found: Vec<String> = walk(&root, "").unwrap().into_iter().map(|(relative, _)| relative).collect();
+        assert_eq!(found, vec!["videos/a.mp4"]);
+    }
+}
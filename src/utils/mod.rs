@@ -1,6 +1,18 @@
 pub mod args;
+pub mod archive;
+pub mod auth;
+pub mod checksums;
 pub mod database;
+pub mod ffmpeg;
+pub mod file_ops;
+pub mod html;
+pub mod libraries;
 pub mod makeclip;
 pub mod makepoint;
+pub mod media_index;
+pub mod paths;
 pub mod redirect;
+pub mod session_key;
 pub mod ssl;
+pub mod time;
+pub mod uploads;
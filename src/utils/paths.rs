@@ -0,0 +1,255 @@
+use actix_web::HttpResponse;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use crate::models::trash_item::TRASH_DIR;
+
+/// A file or folder under the served root, as resolved from a URL path.
+///
+/// `absolute` is what to hand to the filesystem and ffmpeg; `relative` is the
+/// normalized `a/b/c` form stored in the database and used to build links.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServedPath {
+    pub absolute: PathBuf,
+    pub relative: String,
+}
+
+impl ServedPath {
+    /// The URL for this path, always starting with `/`.
+    pub fn url(&self) -> String {
+        format!("/{}", self.relative)
+    }
+
+    /// The folder containing this path, or the root itself.
+    pub fn parent_relative(&self) -> &str {
+        self.relative.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("")
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.relative.rsplit('/').next().unwrap_or("")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PathError {
+    NulByte,
+    Absolute,
+    ParentDir,
+    /// Not a plain file or folder name, e.g. an upload filename containing `/`.
+    InvalidName,
+    /// Resolves (through a symlink) to somewhere outside the served root.
+    EscapesRoot,
+    /// Inside `.trash/`, which is only reached through the trash page.
+    InTrash,
+    Io(String),
+}
+
+impl std::fmt::Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathError::NulByte => write!(f, "Path contains a NUL byte"),
+            PathError::Absolute => write!(f, "Absolute paths are not allowed"),
+            PathError::ParentDir => write!(f, "Paths may not contain '..'"),
+            PathError::InvalidName => write!(f, "Invalid file or folder name"),
+            PathError::EscapesRoot => write!(f, "Path is outside the served folder"),
+            PathError::InTrash => write!(f, "Not found"),
+            PathError::Io(e) => write!(f, "Could not resolve path: {}", e),
+        }
+    }
+}
+
+impl PathError {
+    pub fn response(&self) -> HttpResponse {
+        match self {
+            PathError::EscapesRoot => HttpResponse::Forbidden().body(self.to_string()),
+            PathError::InTrash => HttpResponse::NotFound().body(self.to_string()),
+            PathError::Io(e) => {
+                eprintln!("Error resolving path: {}", e);
+                HttpResponse::InternalServerError().body("Could not resolve path")
+            }
+            _ => HttpResponse::BadRequest().body(self.to_string()),
+        }
+    }
+}
+
+/// Map a URL path, as captured by `{path:.*}` without its leading `/`, to a path under `root`.
+///
+/// Rejects NUL bytes, absolute paths, `..`, the trash and anything that canonicalizes
+/// to a location outside `root`. The target itself does not have to exist, so this
+/// also works for folders and uploads about to be created.
+pub fn resolve(root: &Path, requested: &str) -> Result<ServedPath, PathError> {
+    if requested.contains('\0') {
+        return Err(PathError::NulByte);
+    }
+    // actix strips the leading slash from `{path:.*}`, so one still present means
+    // the URL had `//`, i.e. an absolute path.
+    if requested.starts_with('/') || requested.starts_with('\\') {
+        return Err(PathError::Absolute);
+    }
+
+    let mut parts = Vec::new();
+    for part in requested.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => return Err(PathError::ParentDir),
+            _ => {}
+        }
+        // Catches Windows drive prefixes like `C:` as well.
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => parts.push(part),
+            (Some(Component::Prefix(_) | Component::RootDir), _) => return Err(PathError::Absolute),
+            _ => return Err(PathError::InvalidName),
+        }
+    }
+
+    if parts.first() == Some(&TRASH_DIR) {
+        return Err(PathError::InTrash);
+    }
+
+    let root = root.canonicalize().map_err(|e| PathError::Io(e.to_string()))?;
+    let absolute = parts.iter().fold(root.clone(), |path, part| path.join(part));
+    if !canonicalize_existing(&absolute)?.starts_with(&root) {
+        return Err(PathError::EscapesRoot);
+    }
+    Ok(ServedPath { absolute, relative: parts.join("/") })
+}
+
+/// Check that `name` is a single file or folder name and join it onto the URL path `dir`.
+pub fn child_path(dir: &str, name: &str) -> Result<String, PathError> {
+    if name.contains('\0') {
+        return Err(PathError::NulByte);
+    }
+    let name = name.trim();
+    if name.is_empty() || name == "." || name.contains(['/', '\\']) {
+        return Err(PathError::InvalidName);
+    }
+    if name == ".." {
+        return Err(PathError::ParentDir);
+    }
+    if dir.is_empty() {
+        return Ok(name.to_string());
+    }
+    Ok(format!("{}/{}", dir, name))
+}
+
+// Canonicalize the longest existing prefix of `path` and re-append the rest, so that
+// symlinks anywhere along the way are followed even when the leaf doesn't exist yet.
+fn canonicalize_existing(path: &Path) -> Result<PathBuf, PathError> {
+    let mut missing = Vec::new();
+    let mut current = path;
+    loop {
+        match current.canonicalize() {
+            Ok(found) => {
+                return Ok(missing.iter().rev().fold(found, |path: PathBuf, part| path.join(part)));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                // A dangling symlink can't be followed, so don't let it through either.
+                if current.symlink_metadata().is_ok() {
+                    return Err(PathError::EscapesRoot);
+                }
+                match (current.parent(), current.file_name()) {
+                    (Some(parent), Some(name)) => {
+                        missing.push(name.to_os_string());
+                        current = parent;
+                    }
+                    _ => return Err(PathError::Io(e.to_string())),
+                }
+            }
+            Err(e) => return Err(PathError::Io(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn root() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(root.join("videos")).unwrap();
+        fs::write(root.join("videos/a.mp4"), b"").unwrap();
+        fs::write(dir.path().join("secret.txt"), b"").unwrap();
+        (dir, root)
+    }
+
+    #[test]
+    fn resolves_normal_paths() {
+        let (_dir, root) = root();
+        let resolved = resolve(&root, "videos/a.mp4").unwrap();
+        assert_eq!(resolved.relative, "videos/a.mp4");
+        assert_eq!(resolved.absolute, root.canonicalize().unwrap().join("videos/a.mp4"));
+        assert_eq!(resolved.url(), "/videos/a.mp4");
+        assert_eq!(resolved.parent_relative(), "videos");
+        assert_eq!(resolved.file_name(), "a.mp4");
+        assert_eq!(resolve(&root, "").unwrap().relative, "");
+        assert_eq!(resolve(&root, "videos//./a.mp4").unwrap().relative, "videos/a.mp4");
+        assert_eq!(resolve(&root, "videos/new-folder/x.mp4").unwrap().relative, "videos/new-folder/x.mp4");
+    }
+
+    #[test]
+    fn rejects_parent_dirs() {
+        let (_dir, root) = root();
+        assert_eq!(resolve(&root, ".."), Err(PathError::ParentDir));
+        assert_eq!(resolve(&root, "../secret.txt"), Err(PathError::ParentDir));
+        assert_eq!(resolve(&root, "videos/../../secret.txt"), Err(PathError::ParentDir));
+        assert_eq!(resolve(&root, "videos\\..\\..\\secret.txt"), Err(PathError::ParentDir));
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let (_dir, root) = root();
+        assert_eq!(resolve(&root, "/etc/passwd"), Err(PathError::Absolute));
+        assert_eq!(resolve(&root, "\\windows\\win.ini"), Err(PathError::Absolute));
+    }
+
+    #[test]
+    fn rejects_nul_bytes() {
+        let (_dir, root) = root();
+        assert_eq!(resolve(&root, "videos/a.mp4\0.txt"), Err(PathError::NulByte));
+        assert_eq!(child_path("", "a\0b"), Err(PathError::NulByte));
+    }
+
+    #[test]
+    fn rejects_the_trash() {
+        let (_dir, root) = root();
+        assert_eq!(resolve(&root, ".trash/abc/a.mp4"), Err(PathError::InTrash));
+        assert_eq!(resolve(&root, "./.trash"), Err(PathError::InTrash));
+        assert_eq!(resolve(&root, "videos/.trash").unwrap().relative, "videos/.trash");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_that_escape() {
+        let (dir, root) = root();
+        std::os::unix::fs::symlink(dir.path(), root.join("outside")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), root.join("secret-link")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("missing"), root.join("dangling")).unwrap();
+        assert_eq!(resolve(&root, "outside/secret.txt"), Err(PathError::EscapesRoot));
+        assert_eq!(resolve(&root, "outside/not-yet/file.mp4"), Err(PathError::EscapesRoot));
+        assert_eq!(resolve(&root, "secret-link"), Err(PathError::EscapesRoot));
+        assert_eq!(resolve(&root, "dangling"), Err(PathError::EscapesRoot));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn allows_symlinks_inside_root() {
+        let (_dir, root) = root();
+        std::os::unix::fs::symlink(root.join("videos"), root.join("shortcut")).unwrap();
+        assert_eq!(resolve(&root, "shortcut/a.mp4").unwrap().relative, "shortcut/a.mp4");
+    }
+
+    #[test]
+    fn rejects_bad_upload_names() {
+        let (_dir, root) = root();
+        assert_eq!(child_path("videos", "b.mp4").unwrap(), "videos/b.mp4");
+        assert_eq!(child_path("", "b.mp4").unwrap(), "b.mp4");
+        assert_eq!(child_path("", "../b.mp4"), Err(PathError::InvalidName));
+        assert_eq!(child_path("", "/etc/passwd"), Err(PathError::InvalidName));
+        assert_eq!(child_path("", "..\\b.mp4"), Err(PathError::InvalidName));
+        assert_eq!(child_path("", ".."), Err(PathError::ParentDir));
+        assert_eq!(child_path("", ""), Err(PathError::InvalidName));
+        assert_eq!(resolve(&root, &child_path("../..", "b.mp4").unwrap()), Err(PathError::ParentDir));
+    }
+}
@@ -0,0 +1,60 @@
+use actix_web::cookie::Key;
+use crate::utils::database::data_dir;
+use std::path::PathBuf;
+
+const KEY_FILE: &str = "session.key";
+
+/// Set to 128 hex characters (64 bytes) to supply the cookie signing key yourself.
+const KEY_ENV: &str = "SESSION_KEY";
+
+pub fn key_path() -> PathBuf {
+    data_dir().join(KEY_FILE)
+}
+
+/// Returns the key used to sign and encrypt session cookies.
+/// Prefers SESSION_KEY from the environment, then the key file next to the database,
+/// and generates (and saves) a new random key on first start.
+pub fn load_or_create() -> std::io::Result<Key> {
+    if let Ok(hex_key) = std::env::var(KEY_ENV) {
+        let bytes = hex::decode(hex_key.trim()).map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} is not valid hex: {}", KEY_ENV, e))
+        })?;
+        return Key::try_from(bytes.as_slice()).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} must be at least 64 bytes (128 hex characters)", KEY_ENV))
+        });
+    }
+
+    let path = key_path();
+    if path.exists() {
+        let bytes = std::fs::read(&path)?;
+        match Key::try_from(bytes.as_slice()) {
+            Ok(key) => return Ok(key),
+            Err(_) => eprintln!("Warning: {} is not a valid session key, generating a new one.", path.display()),
+        }
+    }
+
+    println!("Generating new session key at {}", path.display());
+    write_new_key(&path)
+}
+
+/// Replaces the stored key with a fresh one. Every existing session cookie stops validating,
+/// so everyone is logged out the next time the server starts.
+pub fn rotate() -> std::io::Result<PathBuf> {
+    if std::env::var(KEY_ENV).is_ok() {
+        eprintln!("Warning: {} is set in the environment and will keep overriding the key file.", KEY_ENV);
+    }
+    let path = key_path();
+    write_new_key(&path)?;
+    Ok(path)
+}
+
+fn write_new_key(path: &PathBuf) -> std::io::Result<Key> {
+    let key = Key::generate();
+    std::fs::write(path, key.master())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(key)
+}
@@ -10,7 +10,7 @@ fn generate_certificates(
     subject_alt_names: Vec<String>,
 ) -> std::io::Result<()> {
     let cert_key = generate_simple_self_signed(subject_alt_names)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        .map_err(std::io::Error::other)?;
 
     let cert_pem = cert_key.cert.pem();
     let key_pem = cert_key.signing_key.serialize_pem();
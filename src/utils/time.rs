@@ -0,0 +1,30 @@
+/// Seconds since the Unix epoch, the unit every timestamp column uses.
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// A timestamp as UTC (year, month, day, hour, minute, second).
+pub fn civil(timestamp: i64) -> (i64, i64, i64, i64, i64, i64) {
+    // Days to a civil date, from Howard Hinnant's `civil_from_days`.
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day, seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+/// A timestamp as UTC ISO 8601, e.g. `2025-03-01T14:05:00Z`.
+pub fn iso8601(timestamp: i64) -> String {
+    let (year, month, day, hour, minute, second) = civil(timestamp);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second)
+}
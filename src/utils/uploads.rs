@@ -0,0 +1,316 @@
+use std::fs;
+use std::io::ErrorKind;
+use crate::models::upload_session::STAGING_DIR;
+use crate::models::user::Permission;
+use crate::utils::auth::{random_token, CurrentUser};
+use crate::utils::libraries::Libraries;
+use crate::utils::paths::{child_path, PathError, ServedPath};
+use crate::utils::time::now;
+
+/// Where replaced files are kept when an upload overwrites them. Hidden from listings.
+pub const VERSIONS_DIR: &str = ".versions";
+
+/// What to do when an upload has the same name as an existing file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Skip,
+    /// Upload as "name (1).ext", "name (2).ext", ...
+    Rename,
+    /// Replace the file, moving the old one into `.versions/`.
+    Overwrite,
+}
+
+impl ConflictPolicy {
+    pub const ALL: [ConflictPolicy; 3] = [ConflictPolicy::Skip, ConflictPolicy::Rename, ConflictPolicy::Overwrite];
+
+    pub fn from_str_case_insensitive(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "skip" => Some(ConflictPolicy::Skip),
+            "rename" => Some(ConflictPolicy::Rename),
+            "overwrite" => Some(ConflictPolicy::Overwrite),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Rename => "rename",
+            ConflictPolicy::Overwrite => "overwrite",
+        }
+    }
+}
+
+// "clip.mp4" -> ("clip", ".mp4"); dotfiles and names without an extension keep it all in the stem.
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(i) if i > 0 => name.split_at(i),
+        _ => (name, ""),
+    }
+}
+
+/// Map an uploaded file name inside `folder`. Directory uploads send paths like
+/// `card1/DCIM/clip.mp4`; missing folders along the way are created, which needs
+/// the right to create folders there.
+pub fn upload_target(libraries: &Libraries, folder: &ServedPath, name: &str, user: &CurrentUser) -> Result<ServedPath, String> {
+    let mut relative = folder.relative.clone();
+    let parts: Vec<&str> = name.split(['/', '\\']).filter(|part| !part.is_empty()).collect();
+    if parts.is_empty() {
+        return Err(PathError::InvalidName.to_string());
+    }
+    for part in &parts {
+        relative = child_path(&relative, part).map_err(|e| e.to_string())?;
+    }
+    let target = libraries.resolve(&relative).map_err(|e| e.to_string())?;
+    user.require_at(Permission::Upload, &target.relative)
+        .map_err(|_| "You do not have access to this folder".to_string())?;
+
+    if parts.len() > 1
+        && let Some(parent) = target.absolute.parent()
+        && !parent.is_dir()
+    {
+        if !user.can_at(Permission::CreateFolder, target.parent_relative()) {
+            return Err("You can't create folders here".to_string());
+        }
+        fs::create_dir_all(parent).map_err(|e| format!("Error creating folder: {}", e))?;
+    }
+    Ok(target)
+}
+
+/// A new, empty file in `.uploads/` inside the folder `dir`, for an upload to be written
+/// to before it's moved into place.
+pub fn create_staging_file(libraries: &Libraries, dir: &str) -> std::io::Result<ServedPath> {
+    let staging = child_path(dir, STAGING_DIR)
+        .and_then(|staging_dir| libraries.resolve_child(&staging_dir, &format!("{}.part", random_token())))
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if let Some(staging_dir) = staging.absolute.parent() {
+        fs::create_dir_all(staging_dir)?;
+    }
+    fs::OpenOptions::new().write(true).create_new(true).open(&staging.absolute)?;
+    Ok(staging)
+}
+
+/// Remove a staging file if it's still there, and `.uploads/` once it's empty.
+pub fn remove_staging_file(staging: &ServedPath) {
+    let _ = fs::remove_file(&staging.absolute);
+    if let Some(staging_dir) = staging.absolute.parent() {
+        let _ = fs::remove_dir(staging_dir);
+    }
+}
+
+/// The first of "name (1).ext", "name (2).ext", ... that doesn't exist yet.
+pub fn free_path(libraries: &Libraries, target: &ServedPath) -> Result<ServedPath, PathError> {
+    let (stem, extension) = split_extension(target.file_name());
+    let mut n = 1;
+    loop {
+        let candidate = libraries.resolve_child(target.parent_relative(), &format!("{} ({}){}", stem, n, extension))?;
+        if !candidate.absolute.exists() {
+            return Ok(candidate);
+        }
+        n += 1;
+    }
+}
+
+/// Move `target` into `.versions/` in its folder, as "name.<timestamp>.ext".
+pub fn keep_version(libraries: &Libraries, target: &ServedPath) -> std::io::Result<()> {
+    let io_error = |e: PathError| std::io::Error::other(e.to_string());
+    let versions = child_path(target.parent_relative(), VERSIONS_DIR)
+        .and_then(|dir| libraries.resolve(&dir))
+        .map_err(io_error)?;
+    fs::create_dir_all(&versions.absolute)?;
+
+    let (stem, extension) = split_extension(target.file_name());
+    let mut version = libraries
+        .resolve_child(&versions.relative, &format!("{}.{}{}", stem, now(), extension))
+        .map_err(io_error)?;
+    if version.absolute.exists() {
+        version = free_path(libraries, &version).map_err(io_error)?;
+    }
+    fs::rename(&target.absolute, &version.absolute)
+}
+
+/// Put a finished upload at `target` according to `policy`, returning where it ended up.
+/// `staged` is a complete file in the same folder tree, moved into place, never copied.
+pub fn place_file(libraries: &Libraries, staged: &std::path::Path, target: ServedPath, policy: ConflictPolicy) -> std::io::Result<ServedPath> {
+    let mut target = target;
+    loop {
+        // A hard link fails if the target exists, so nothing is replaced by accident.
+        // Filesystems without hard links fall back to a checked rename.
+        let placed = match fs::hard_link(staged, &target.absolute) {
+            Ok(()) => fs::remove_file(staged),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(e),
+            Err(_) if target.absolute.exists() => Err(ErrorKind::AlreadyExists.into()),
+            Err(_) => fs::rename(staged, &target.absolute),
+        };
+        match placed {
+            Ok(()) => return Ok(target),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => match policy {
+                ConflictPolicy::Skip => return Err(e),
+                ConflictPolicy::Rename => {
+                    target = free_path(libraries, &target).map_err(|e| std::io::Error::other(e.to_string()))?;
+                }
+                // Folders are never replaced by a file.
+                ConflictPolicy::Overwrite if target.absolute.is_dir() => return Err(e),
+                ConflictPolicy::Overwrite => {
+                    keep_version(libraries, &target)?;
+                    fs::rename(staged, &target.absolute)?;
+                    return Ok(target);
+                }
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::dropbox;
+    use sea_orm::{ActiveModelTrait, ConnectionTrait, DatabaseConnection, EntityTrait, Set};
+
+    fn libraries() -> (tempfile::TempDir, Libraries) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("videos")).unwrap();
+        let libraries = Libraries::new(dir.path().canonicalize().unwrap(), Vec::new());
+        (dir, libraries)
+    }
+
+    fn staged(libraries: &Libraries, contents: &str) -> std::path::PathBuf {
+        let path = libraries.root.join("videos/upload.part");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn read(libraries: &Libraries, relative: &str) -> String {
+        fs::read_to_string(libraries.root.join(relative)).unwrap()
+    }
+
+    #[test]
+    fn split_extension_keeps_dotfiles_whole() {
+        assert_eq!(split_extension("clip.mp4"), ("clip", ".mp4"));
+        assert_eq!(split_extension("a.tar.gz"), ("a.tar", ".gz"));
+        assert_eq!(split_extension(".env"), (".env", ""));
+        assert_eq!(split_extension("README"), ("README", ""));
+    }
+
+    #[test]
+    fn free_path_counts_past_taken_names() {
+        let (_dir, libraries) = libraries();
+        let target = libraries.resolve("videos/a.mp4").unwrap();
+        assert_eq!(free_path(&libraries, &target).unwrap().relative, "videos/a (1).mp4");
+        fs::write(libraries.root.join("videos/a (1).mp4"), "").unwrap();
+        fs::write(libraries.root.join("videos/a (2).mp4"), "").unwrap();
+        assert_eq!(free_path(&libraries, &target).unwrap().relative, "videos/a (3).mp4");
+    }
+
+    #[test]
+    fn place_file_skip_leaves_the_existing_file() {
+        let (_dir, libraries) = libraries();
+        fs::write(libraries.root.join("videos/a.mp4"), "old").unwrap();
+        let staged = staged(&libraries, "new");
+        let target = libraries.resolve("videos/a.mp4").unwrap();
+        let err = place_file(&libraries, &staged, target, ConflictPolicy::Skip).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(read(&libraries, "videos/a.mp4"), "old");
+        assert!(staged.exists());
+    }
+
+    #[test]
+    fn place_file_rename_picks_a_free_name() {
+        let (_dir, libraries) = libraries();
+        fs::write(libraries.root.join("videos/a.mp4"), "old").unwrap();
+        fs::write(libraries.root.join("videos/a (1).mp4"), "older").unwrap();
+        let staged = staged(&libraries, "new");
+        let target = libraries.resolve("videos/a.mp4").unwrap();
+        let placed = place_file(&libraries, &staged, target, ConflictPolicy::Rename).unwrap();
+        assert_eq!(placed.relative, "videos/a (2).mp4");
+        assert_eq!(read(&libraries, "videos/a.mp4"), "old");
+        assert_eq!(read(&libraries, "videos/a (1).mp4"), "older");
+        assert_eq!(read(&libraries, "videos/a (2).mp4"), "new");
+        assert!(!staged.exists());
+    }
+
+    #[test]
+    fn place_file_overwrite_keeps_a_version() {
+        let (_dir, libraries) = libraries();
+        fs::write(libraries.root.join("videos/a.mp4"), "old").unwrap();
+        let staged = staged(&libraries, "new");
+        let target = libraries.resolve("videos/a.mp4").unwrap();
+        let placed = place_file(&libraries, &staged, target, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(placed.relative, "videos/a.mp4");
+        assert_eq!(read(&libraries, "videos/a.mp4"), "new");
+        let versions: Vec<_> = fs::read_dir(libraries.root.join("videos").join(VERSIONS_DIR)).unwrap().collect();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(fs::read_to_string(versions[0].as_ref().unwrap().path()).unwrap(), "old");
+    }
+
+    #[test]
+    fn place_file_never_replaces_a_folder() {
+        let (_dir, libraries) = libraries();
+        fs::create_dir(libraries.root.join("videos/a.mp4")).unwrap();
+        let staged = staged(&libraries, "new");
+        let target = libraries.resolve("videos/a.mp4").unwrap();
+        assert!(place_file(&libraries, &staged, target, ConflictPolicy::Overwrite).is_err());
+        assert!(libraries.root.join("videos/a.mp4").is_dir());
+        assert!(staged.exists());
+    }
+
+    async fn dropbox(max_bytes: Option<i64>) -> (DatabaseConnection, dropbox::Model) {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        let schema = sea_orm::Schema::new(db.get_database_backend());
+        db.execute(db.get_database_backend().build(&schema.create_table_from_entity(dropbox::Entity))).await.unwrap();
+        let dropbox = dropbox::ActiveModel {
+            token: Set("tok".to_string()),
+            name: Set("Footage".to_string()),
+            path: Set("in".to_string()),
+            max_bytes: Set(max_bytes),
+            allowed_extensions: Set(String::new()),
+            created_by: Set(1),
+            created_at: Set(0),
+            reserved_bytes: Set(0),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        (db, dropbox)
+    }
+
+    async fn reserved(db: &DatabaseConnection, dropbox: &dropbox::Model) -> i64 {
+        dropbox::Entity::find_by_id(dropbox.id).one(db).await.unwrap().unwrap().reserved_bytes
+    }
+
+    #[tokio::test]
+    async fn concurrent_reservations_stay_within_the_quota() {
+        let (db, dropbox) = dropbox(Some(1000)).await;
+        let granted: Vec<u64> = futures::future::join_all((0..10).map(|_| dropbox.reserve(&db, 300)))
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(granted.iter().sum::<u64>(), 1000);
+        assert!(granted.iter().all(|&bytes| bytes <= 300));
+        assert_eq!(reserved(&db, &dropbox).await, 1000);
+        assert_eq!(dropbox.reserve(&db, 1).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn failed_upload_gives_its_reservation_back() {
+        let (db, dropbox) = dropbox(Some(1000)).await;
+        assert_eq!(dropbox.reserve(&db, 800).await.unwrap(), 800);
+        assert_eq!(dropbox.reserve(&db, 800).await.unwrap(), 200);
+        // The first upload failed before writing anything, the second stored 50 bytes.
+        dropbox.release(&db, 800).await.unwrap();
+        dropbox.release(&db, 150).await.unwrap();
+        assert_eq!(reserved(&db, &dropbox).await, 50);
+        assert_eq!(dropbox.reserve(&db, u64::MAX).await.unwrap(), 950);
+    }
+
+    #[tokio::test]
+    async fn without_a_quota_nothing_is_reserved() {
+        let (db, dropbox) = dropbox(None).await;
+        assert_eq!(dropbox.reserve(&db, 5_000_000).await.unwrap(), 5_000_000);
+        dropbox.release(&db, 5_000_000).await.unwrap();
+        assert_eq!(reserved(&db, &dropbox).await, 0);
+    }
+}
@@ -0,0 +1,100 @@
+// Send each file to /uploads in chunks (tus 1.0), so a dropped connection only costs
+// the chunk in flight. Upload URLs are kept in localStorage, so submitting the same
+// files again after a reload picks up where they left off. Without JS the form still
+// posts to /upload in one go.
+(function () {
+  const CHUNK_SIZE = 8 * 1024 * 1024;
+  const MAX_RETRIES = 5;
+  const form = document.getElementById('uploadFormElement');
+  if (!form || !window.fetch) return;
+  const status = document.getElementById('uploadStatus');
+  const tus = { 'Tus-Resumable': '1.0.0' };
+  const b64 = (s) => btoa(unescape(encodeURIComponent(s)));
+  const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+
+  async function offsetOf(url) {
+    const response = await fetch(url, { method: 'HEAD', headers: tus });
+    return response.ok ? parseInt(response.headers.get('Upload-Offset'), 10) : null;
+  }
+
+  // Files from the folder picker keep their path inside the chosen folder.
+  const pathOf = (file) => file.webkitRelativePath || file.name;
+
+  async function create(folder, conflict, file) {
+    const metadata = `filename ${b64(pathOf(file))},folder ${b64(folder)},conflict ${b64(conflict)}`;
+    const response = await fetch('/uploads', {
+      method: 'POST',
+      headers: { ...tus, 'Upload-Length': String(file.size), 'Upload-Metadata': metadata },
+    });
+    if (!response.ok) throw new Error(await response.text());
+    return response.headers.get('Location');
+  }
+
+  async function send(folder, conflict, file, line) {
+    const key = `upload:${folder}/${pathOf(file)}:${file.size}:${file.lastModified}`;
+    let url = localStorage.getItem(key);
+    let offset = url ? await offsetOf(url).catch(() => null) : null;
+    if (offset === null) {
+      url = await create(folder, conflict, file);
+      localStorage.setItem(key, url);
+      offset = 0;
+    }
+    let failures = 0;
+    while (offset < file.size) {
+      line.textContent = `${pathOf(file)}: ${Math.floor((offset * 100) / file.size)}%`;
+      let response;
+      try {
+        response = await fetch(url, {
+          method: 'PATCH',
+          headers: { ...tus, 'Content-Type': 'application/offset+octet-stream', 'Upload-Offset': String(offset) },
+          body: file.slice(offset, offset + CHUNK_SIZE),
+        });
+      } catch (err) {
+        response = null;
+      }
+      if (response && response.ok) {
+        offset = parseInt(response.headers.get('Upload-Offset'), 10);
+        failures = 0;
+        continue;
+      }
+      // 4xx other than an offset mismatch or a busy upload won't get better by retrying.
+      if (response && response.status < 500 && response.status !== 409 && response.status !== 423) {
+        localStorage.removeItem(key);
+        throw new Error(await response.text());
+      }
+      if (++failures > MAX_RETRIES) throw new Error('Upload keeps failing, try again later');
+      await sleep(1000 * failures);
+      const current = await offsetOf(url).catch(() => null);
+      if (current === null) {
+        localStorage.removeItem(key);
+        throw new Error(response ? await response.text() : 'Upload was lost');
+      }
+      offset = current;
+    }
+    localStorage.removeItem(key);
+    line.textContent = `${pathOf(file)}: Uploaded`;
+  }
+
+  form.addEventListener('submit', async (event) => {
+    event.preventDefault();
+    const files = Array.from(form.querySelectorAll('input[type=file]')).flatMap((input) => Array.from(input.files));
+    const folder = form.elements.folder.value;
+    const conflict = form.elements.conflict.value;
+    form.querySelector('button').disabled = true;
+    status.innerHTML = '';
+    let failed = false;
+    for (const file of files) {
+      const line = document.createElement('li');
+      line.className = 'list-group-item';
+      status.appendChild(line);
+      try {
+        await send(folder, conflict, file, line);
+      } catch (err) {
+        failed = true;
+        line.textContent = `${pathOf(file)}: ${err.message}`;
+      }
+    }
+    form.querySelector('button').disabled = false;
+    if (!failed) window.location.reload();
+  });
+})();